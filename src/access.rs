@@ -0,0 +1,256 @@
+//! Checks that cells, primitives and drills are only reached where they can be
+//! accessed, on every path through the program.
+//!
+//! Cells can only be accessed in the Cells Region, and primitives only in the Layers
+//! Region once all 3 gates have been drilled open. The region and the open gates are
+//! followed through jumps as well as through the expressions in order, so a jump
+//! back to a label brings the state the program is in at the jump with it. What goes
+//! wrong on every path is an error, and what only goes wrong on some of them is left
+//! to the lints to warn about.
+//!
+//! The gates closing again, a while after they have been opened, isn't followed here.
+//! The gates only ever get more open, and the lifetimes module warns about them closing
+
+use crate::cfg::ControlFlowGraph;
+use crate::dataflow::{self, Analysis};
+use crate::errors::CompileError;
+use crate::lexer::Span;
+use crate::parser::{OrganismExpression, Expr, LeachExpression, PassiveExpression, Region};
+
+/// The number of gates there are to drill open
+const GATES: u8 = 3;
+
+/// The regions the program may be in, each along with the number of gates that may
+/// be open in it, one bit for each pair. The gates stay as they are when the region
+/// changes, so the pairs keep track of which paths had them open
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AccessState(u8);
+
+impl AccessState {
+    fn bit(region: &Region, open_gates: u8) -> u8 {
+        let region = match region {
+            Region::Cells => 0,
+            Region::Layers => 1
+        };
+        1 << (region * (GATES + 1) + open_gates)
+    }
+    /// The pairs the state may be in
+    fn pairs(self) -> impl Iterator<Item = (Region, u8)> {
+        [Region::Cells, Region::Layers].iter()
+            .flat_map(|region| (0..=GATES).map(move |open_gates| (region.clone(), open_gates)))
+            .filter(move |(region, open_gates)| self.0 & AccessState::bit(region, *open_gates) != 0)
+    }
+    fn from_pairs(pairs: impl Iterator<Item = (Region, u8)>) -> AccessState {
+        AccessState(pairs.fold(0, |bits, (region, open_gates)| bits | AccessState::bit(&region, open_gates)))
+    }
+    pub fn may_be_in(self, region: &Region) -> bool {
+        self.pairs().any(|(r, _)| r == *region)
+    }
+    pub fn must_be_in(self, region: &Region) -> bool {
+        self.pairs().all(|(r, _)| r == *region)
+    }
+    /// Whether some path is in the Layers Region with all the gates open
+    pub fn may_access_primitive(self) -> bool {
+        self.0 & AccessState::bit(&Region::Layers, GATES) != 0
+    }
+    pub fn must_access_primitive(self) -> bool {
+        self.0 == AccessState::bit(&Region::Layers, GATES)
+    }
+    fn moved_to(self, region: &Region) -> AccessState {
+        AccessState::from_pairs(self.pairs().map(|(_, open_gates)| (region.clone(), open_gates)))
+    }
+    /// A drill in the Cells Region is an error, so the paths in it are left as they
+    /// are, as if the drill weren't there
+    fn drilled(self) -> AccessState {
+        AccessState::from_pairs(self.pairs().map(|(region, open_gates)| match region {
+            Region::Layers => (region, (open_gates + 1).min(GATES)),
+            Region::Cells => (region, open_gates)
+        }))
+    }
+}
+
+/// Where the program goes from one region to the other, and drills gates open
+pub struct RegionsAndGates;
+
+impl Analysis for RegionsAndGates {
+    type State = AccessState;
+
+    /// Every program starts in the Cells Region with all the gates closed
+    fn entry_state(&self) -> AccessState {
+        AccessState(AccessState::bit(&Region::Cells, 0))
+    }
+    fn join(&self, state: &mut AccessState, other: &AccessState) {
+        state.0 |= other.0;
+    }
+    fn transfer(&self, state: &mut AccessState, expr: &Expr) {
+        match expr {
+            Expr::Region(region_expr) => *state = state.moved_to(&region_expr.to),
+            Expr::Drill(_) => *state = state.drilled(),
+            Expr::Leach(leach_expr) => *state = after_region_changes(*state, leach_expr),
+            _ => ()
+        };
+    }
+}
+
+fn after_region_changes(state: AccessState, leach_expr: &LeachExpression) -> AccessState {
+    leach_expr.region_change.iter()
+        .flatten()
+        .fold(state, |state, region_expr| state.moved_to(&region_expr.to))
+}
+
+/// Something reached where it can be accessed on some of the paths to it, but not
+/// on all of them
+#[derive(Debug, Clone, PartialEq)]
+pub enum MaybeInvalid {
+    /// A cell on its own or leached, which may be reached outside the Cells Region
+    CellRegion(Span),
+    /// The cell a primitive is stored in, which may be reached outside the Cells
+    /// Region, and where the region changes before it end
+    StoreRegion(Span, Span),
+    /// A primitive, which may be reached outside the Layers Region
+    PrimitiveRegion(Span),
+    /// A primitive, which may be reached before all the gates are open
+    PrimitiveGates(Span),
+    /// A drill, which may be reached outside the Layers Region
+    DrillRegion(Span)
+}
+
+impl MaybeInvalid {
+    pub fn span(&self) -> Span {
+        match self {
+            MaybeInvalid::CellRegion(span) |
+            MaybeInvalid::StoreRegion(span, _) |
+            MaybeInvalid::PrimitiveRegion(span) |
+            MaybeInvalid::PrimitiveGates(span) |
+            MaybeInvalid::DrillRegion(span) => *span
+        }
+    }
+}
+
+/// The accesses that fail on every path to them, as errors, and those that only fail
+/// on some, in the order they are in the program. Expressions that can't be reached
+/// aren't checked
+pub fn check(org_expr: &OrganismExpression) -> (Vec<CompileError>, Vec<MaybeInvalid>) {
+    let cfg = ControlFlowGraph::new(org_expr);
+    let results = dataflow::solve(&cfg, RegionsAndGates);
+    let mut errors = vec![];
+    let mut maybe_invalid = vec![];
+    results.for_each_expr(|_, expr, &state| {
+        match expr {
+            Expr::Cell(cell_expr) => check_cell(cell_expr.span, state, &mut errors, &mut maybe_invalid),
+            Expr::Leach(leach_expr) => match &leach_expr.left {
+                PassiveExpression::Cell(cell_expr) =>
+                    check_cell(cell_expr.span, state, &mut errors, &mut maybe_invalid),
+                PassiveExpression::Primitive(primitive_expr) => {
+                    check_primitive(primitive_expr.span, state, &mut errors, &mut maybe_invalid);
+                    let state = after_region_changes(state, leach_expr);
+                    let target = leach_expr.onto[0].span;
+                    // Going back to the Cells Region has to be done before the tilde
+                    let before_tilde = leach_expr.region_change.iter()
+                        .flatten()
+                        .last()
+                        .map_or(primitive_expr.span, |region_expr| region_expr.span)
+                        .end_point();
+                    if !state.may_be_in(&Region::Cells) {
+                        errors.push(CompileError::InvalidStoreRegion(target, before_tilde));
+                    } else if !state.must_be_in(&Region::Cells) {
+                        maybe_invalid.push(MaybeInvalid::StoreRegion(target, before_tilde));
+                    }
+                }
+            },
+            Expr::Primitive(primitive_expr) =>
+                check_primitive(primitive_expr.span, state, &mut errors, &mut maybe_invalid),
+            Expr::Drill(span) => {
+                if !state.may_be_in(&Region::Layers) {
+                    errors.push(CompileError::DrillInCells(*span));
+                } else if !state.must_be_in(&Region::Layers) {
+                    maybe_invalid.push(MaybeInvalid::DrillRegion(*span));
+                }
+            }
+            Expr::Region(_) | Expr::Label(_) | Expr::Jump { .. } => ()
+        };
+    });
+    (errors, maybe_invalid)
+}
+
+fn check_cell(
+    span: Span,
+    state: AccessState,
+    errors: &mut Vec<CompileError>,
+    maybe_invalid: &mut Vec<MaybeInvalid>
+){
+    if !state.may_be_in(&Region::Cells) {
+        errors.push(CompileError::InvalidCellAccessRegion(span));
+    } else if !state.must_be_in(&Region::Cells) {
+        maybe_invalid.push(MaybeInvalid::CellRegion(span));
+    }
+}
+
+/// A primitive that can't be accessed on any path is reported as being in the wrong
+/// region if no path is in the right one, and as being behind closed gates otherwise
+fn check_primitive(
+    span: Span,
+    state: AccessState,
+    errors: &mut Vec<CompileError>,
+    maybe_invalid: &mut Vec<MaybeInvalid>
+){
+    if !state.may_be_in(&Region::Layers) {
+        errors.push(CompileError::InvalidPrimitiveAccessRegion(span));
+    } else if !state.may_access_primitive() {
+        errors.push(CompileError::InvalidPrimitiveAccessGates(span));
+    } else if !state.must_access_primitive() {
+        if !state.must_be_in(&Region::Layers) {
+            maybe_invalid.push(MaybeInvalid::PrimitiveRegion(span));
+        }
+        if !AccessState::from_pairs(state.pairs().filter(|(region, _)| *region == Region::Layers))
+            .must_access_primitive() {
+            maybe_invalid.push(MaybeInvalid::PrimitiveGates(span));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{parse, try_parse, DRILLS};
+
+    #[test]
+    fn test_straight_line(){
+        assert_eq!(check(&parse(&format!("->L{}$!->C~0^^^^^^666^^^^^^=O", DRILLS))), (vec![], vec![]));
+        // The parser reports what the access check finds
+        assert_eq!(
+            try_parse(&format!(r"\\|//->L{}$!~0^^^^^^666^^^^^^=O", DRILLS)).unwrap_err(),
+            vec![
+                CompileError::DrillInCells(Span::new(0, 5, 1, 1)),
+                CompileError::InvalidStoreRegion(Span::new(26, 27, 1, 27), Span::new(25, 25, 1, 26))
+            ]
+        );
+        assert_eq!(
+            try_parse(r"->L\\|//$!->C~0^^^^^^666^^^^^^=O").unwrap_err(),
+            vec![CompileError::InvalidPrimitiveAccessGates(Span::new(8, 10, 1, 9))]
+        );
+    }
+
+    #[test]
+    fn test_loop(){
+        // The jump back brings the Layers Region to the cell after the label
+        let (errors, maybe_invalid) = check(&parse(r"label:x:0~1->Ljmp:x:^^^^^^666^^^^^^=O"));
+        assert_eq!(errors, vec![]);
+        assert_eq!(maybe_invalid, vec![MaybeInvalid::CellRegion(Span::new(8, 9, 1, 9))]);
+        // Every path to the cell is in the Layers Region, whichever way it came
+        let errors = try_parse(r"->Llabel:x:0~1ijmp:x:^^^^^^666^^^^^^=O").unwrap_err();
+        assert_eq!(errors, vec![CompileError::InvalidCellAccessRegion(Span::new(11, 12, 1, 12))]);
+    }
+
+    #[test]
+    fn test_gates_on_some_paths(){
+        // The drills are skipped when cell 0 is 0
+        let source = r"->Lijmp:x:\\|//\\|//\\|//label:x:$!->C~0^^^^^^666^^^^^^=O";
+        let (errors, maybe_invalid) = check(&parse(source));
+        assert_eq!(errors, vec![]);
+        assert_eq!(maybe_invalid, vec![MaybeInvalid::PrimitiveGates(Span::new(33, 35, 1, 34))]);
+        // Unreachable expressions aren't checked
+        let source = r"jmp:x:$!label:x:^^^^^^666^^^^^^=O";
+        assert_eq!(check(&parse(source)), (vec![], vec![]));
+    }
+}
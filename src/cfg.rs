@@ -0,0 +1,419 @@
+//! The control-flow graph of the organism expression.
+//!
+//! The expressions are split into basic blocks, runs of expressions that are always
+//! carried out one after the other. A block starts at the first expression, at every
+//! label and right after every jump, and flows into the next block unless it ends in
+//! a jump. An unconditional jump only goes to its label, and a conditional one goes
+//! to its label when cell 0 is 0 and on to the next block otherwise. A block with no
+//! successors ends the program, in the death of the organism expression
+
+use std::collections::HashMap;
+use std::ops::Range;
+use crate::parser::{OrganismExpression, Expr};
+
+/// The index of a block in the graph
+pub type BlockId = usize;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BasicBlock {
+    /// The indices of the block's expressions in the organism expression
+    pub exprs: Range<usize>,
+    /// The label the block starts with, if it starts with one
+    pub label: Option<String>,
+    successors: Vec<BlockId>,
+    predecessors: Vec<BlockId>
+}
+
+impl BasicBlock {
+    /// The blocks control can go to from the end of the block. A conditional jump's
+    /// label comes before the block after it
+    pub fn successors(&self) -> &[BlockId] {
+        &self.successors
+    }
+    /// The blocks that go to this one, in order
+    pub fn predecessors(&self) -> &[BlockId] {
+        &self.predecessors
+    }
+}
+
+/// A natural loop, the blocks that can get back to the header without leaving it.
+/// Every back edge to the same header is in the same loop
+#[derive(Debug, Clone, PartialEq)]
+pub struct Loop {
+    /// The one block the loop is entered through
+    pub header: BlockId,
+    /// The blocks that go back to the header, in order
+    pub latches: Vec<BlockId>,
+    /// The blocks of the loop, the header and latches included, in order
+    pub body: Vec<BlockId>
+}
+
+impl Loop {
+    pub fn contains(&self, block: BlockId) -> bool {
+        self.body.binary_search(&block).is_ok()
+    }
+}
+
+#[derive(Debug)]
+pub struct ControlFlowGraph<'a> {
+    org_expr: &'a OrganismExpression,
+    blocks: Vec<BasicBlock>,
+    /// The immediate dominator of each block. The entry block is its own, and blocks
+    /// that can't be reached have none
+    idoms: Vec<Option<BlockId>>,
+    /// When each block is entered and left in a walk of the dominator tree, so that
+    /// dominance is a comparison
+    dom_entered: Vec<usize>,
+    dom_left: Vec<usize>,
+    loops: Vec<Loop>
+}
+
+impl<'a> ControlFlowGraph<'a> {
+    /// Builds the graph of the organism expression. A jump to a label that doesn't
+    /// exist, which the parser never lets through, goes nowhere
+    pub fn new(org_expr: &'a OrganismExpression) -> ControlFlowGraph<'a> {
+        let mut blocks = split_blocks(org_expr);
+        link_blocks(org_expr, &mut blocks);
+        let mut cfg = ControlFlowGraph {
+            org_expr,
+            blocks,
+            idoms: vec![],
+            dom_entered: vec![],
+            dom_left: vec![],
+            loops: vec![]
+        };
+        cfg.find_dominators();
+        cfg.find_loops();
+        cfg
+    }
+    /// The block the program starts in. There is always one, even if it's empty
+    pub fn entry(&self) -> BlockId {
+        0
+    }
+    pub fn blocks(&self) -> &[BasicBlock] {
+        &self.blocks
+    }
+    pub fn block(&self, block: BlockId) -> &BasicBlock {
+        &self.blocks[block]
+    }
+    /// The expressions of the block
+    pub fn exprs(&self, block: BlockId) -> &'a [Expr] {
+        &self.org_expr.exprs[self.blocks[block].exprs.clone()]
+    }
+    pub fn successors(&self, block: BlockId) -> &[BlockId] {
+        self.blocks[block].successors()
+    }
+    pub fn predecessors(&self, block: BlockId) -> &[BlockId] {
+        self.blocks[block].predecessors()
+    }
+    /// The block the expression at the index is in
+    pub fn block_of(&self, expr: usize) -> BlockId {
+        self.blocks.partition_point(|block| block.exprs.end <= expr)
+    }
+    pub fn is_reachable(&self, block: BlockId) -> bool {
+        self.idoms[block].is_some()
+    }
+    /// The block every path from the entry to this one last goes through, if it can
+    /// be reached and isn't the entry
+    pub fn immediate_dominator(&self, block: BlockId) -> Option<BlockId> {
+        self.idoms[block].filter(|&idom| idom != block)
+    }
+    /// Whether every path from the entry to b goes through a. Every block that can be
+    /// reached dominates itself
+    pub fn dominates(&self, a: BlockId, b: BlockId) -> bool {
+        self.is_reachable(a) && self.is_reachable(b) &&
+            self.dom_entered[a] <= self.dom_entered[b] && self.dom_left[b] <= self.dom_left[a]
+    }
+    /// The natural loops, by header
+    pub fn loops(&self) -> &[Loop] {
+        &self.loops
+    }
+    /// The innermost loop the block is in, the one with the fewest blocks
+    pub fn innermost_loop(&self, block: BlockId) -> Option<&Loop> {
+        self.loops.iter()
+            .filter(|lp| lp.contains(block))
+            .min_by_key(|lp| lp.body.len())
+    }
+
+    /// Finds the dominators as in "A Simple, Fast Dominance Algorithm" by Cooper,
+    /// Harvey and Kennedy, going over the blocks in reverse postorder until nothing
+    /// changes
+    fn find_dominators(&mut self) {
+        let count = self.blocks.len();
+        let postorder = self.postorder();
+        let mut order = vec![usize::MAX; count];
+        for (i, &block) in postorder.iter().enumerate() {
+            order[block] = i;
+        }
+        let mut idoms: Vec<Option<BlockId>> = vec![None; count];
+        idoms[self.entry()] = Some(self.entry());
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &block in postorder.iter().rev().skip(1) {
+                let mut new_idom = None;
+                for &pred in self.blocks[block].predecessors.iter() {
+                    if idoms[pred].is_none() {
+                        continue;
+                    }
+                    new_idom = Some(match new_idom {
+                        None => pred,
+                        Some(other) => intersect(&idoms, &order, pred, other)
+                    });
+                }
+                if new_idom.is_some() && idoms[block] != new_idom {
+                    idoms[block] = new_idom;
+                    changed = true;
+                }
+            }
+        }
+        // Numbers the dominator tree so dominance doesn't have to walk up it
+        let mut children: Vec<Vec<BlockId>> = vec![vec![]; count];
+        for (block, idom) in idoms.iter().enumerate() {
+            if let Some(idom) = idom.filter(|&idom| idom != block) {
+                children[idom].push(block);
+            }
+        }
+        let mut entered = vec![0; count];
+        let mut left = vec![0; count];
+        let mut clock = 0;
+        let mut stack = vec![(self.entry(), 0)];
+        entered[self.entry()] = clock;
+        while let Some((block, child)) = stack.pop() {
+            clock += 1;
+            match children[block].get(child) {
+                Some(&next) => {
+                    stack.push((block, child + 1));
+                    entered[next] = clock;
+                    stack.push((next, 0));
+                }
+                None => left[block] = clock
+            }
+        }
+        self.idoms = idoms;
+        self.dom_entered = entered;
+        self.dom_left = left;
+    }
+
+    /// The blocks that can be reached, in postorder from the entry
+    fn postorder(&self) -> Vec<BlockId> {
+        let mut visited = vec![false; self.blocks.len()];
+        let mut postorder = Vec::with_capacity(self.blocks.len());
+        let mut stack = vec![(self.entry(), 0)];
+        visited[self.entry()] = true;
+        while let Some((block, succ)) = stack.pop() {
+            match self.blocks[block].successors.get(succ) {
+                Some(&next) => {
+                    stack.push((block, succ + 1));
+                    if !visited[next] {
+                        visited[next] = true;
+                        stack.push((next, 0));
+                    }
+                }
+                None => postorder.push(block)
+            }
+        }
+        postorder
+    }
+
+    /// An edge to a block that dominates where it comes from is a back edge, and the
+    /// loop of a header is everything that reaches one of its latches without going
+    /// through the header
+    fn find_loops(&mut self) {
+        let mut latches: HashMap<BlockId, Vec<BlockId>> = HashMap::new();
+        for (block, basic_block) in self.blocks.iter().enumerate() {
+            for &succ in basic_block.successors.iter() {
+                if self.dominates(succ, block) {
+                    latches.entry(succ).or_default().push(block);
+                }
+            }
+        }
+        let mut loops: Vec<Loop> = latches.into_iter().map(|(header, latches)| {
+            let mut in_body = vec![false; self.blocks.len()];
+            in_body[header] = true;
+            let mut body = vec![header];
+            let mut stack = latches.clone();
+            while let Some(block) = stack.pop() {
+                if in_body[block] {
+                    continue;
+                }
+                in_body[block] = true;
+                body.push(block);
+                stack.extend(self.blocks[block].predecessors.iter().filter(|&&pred| self.is_reachable(pred)));
+            }
+            body.sort_unstable();
+            Loop { header, latches, body }
+        }).collect();
+        loops.sort_by_key(|lp| lp.header);
+        self.loops = loops;
+    }
+}
+
+/// The closest block that dominates both, walking up from whichever is further
+/// from the entry
+fn intersect(idoms: &[Option<BlockId>], order: &[usize], mut a: BlockId, mut b: BlockId) -> BlockId {
+    while a != b {
+        while order[a] < order[b] {
+            a = idoms[a].unwrap();
+        }
+        while order[b] < order[a] {
+            b = idoms[b].unwrap();
+        }
+    }
+    a
+}
+
+fn split_blocks(org_expr: &OrganismExpression) -> Vec<BasicBlock> {
+    let mut blocks = vec![];
+    let mut start = 0;
+    let mut label = None;
+    for (i, expr) in org_expr.exprs.iter().enumerate() {
+        match expr {
+            Expr::Label(label_expr) => {
+                if i > start || label.is_some() {
+                    blocks.push(new_block(start..i, label.take()));
+                }
+                start = i;
+                label = Some(label_expr.label());
+            }
+            Expr::Jump { .. } => {
+                blocks.push(new_block(start..i + 1, label.take()));
+                start = i + 1;
+            }
+            _ => ()
+        }
+    }
+    if blocks.is_empty() || start < org_expr.exprs.len() || label.is_some() {
+        blocks.push(new_block(start..org_expr.exprs.len(), label));
+    }
+    blocks
+}
+
+fn new_block(exprs: Range<usize>, label: Option<String>) -> BasicBlock {
+    BasicBlock {
+        exprs,
+        label,
+        successors: vec![],
+        predecessors: vec![]
+    }
+}
+
+fn link_blocks(org_expr: &OrganismExpression, blocks: &mut [BasicBlock]) {
+    let labelled: HashMap<String, BlockId> = blocks.iter()
+        .enumerate()
+        .filter_map(|(block, basic_block)| basic_block.label.clone().map(|label| (label, block)))
+        .collect();
+    for block in 0..blocks.len() {
+        let next = Some(block + 1).filter(|&next| next < blocks.len());
+        let last = org_expr.exprs[blocks[block].exprs.clone()].last();
+        let successors: Vec<BlockId> = match last {
+            Some(Expr::Jump { to, conditional, .. }) => {
+                let target = labelled.get(to.as_str()).copied();
+                if *conditional {
+                    target.into_iter().chain(next.filter(|&next| Some(next) != target)).collect()
+                } else {
+                    target.into_iter().collect()
+                }
+            }
+            _ => next.into_iter().collect()
+        };
+        for &succ in successors.iter() {
+            blocks[succ].predecessors.push(block);
+        }
+        blocks[block].successors = successors;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::parse;
+
+    fn edges(cfg: &ControlFlowGraph) -> Vec<Vec<BlockId>> {
+        cfg.blocks().iter().map(|block| block.successors().to_vec()).collect()
+    }
+
+    #[test]
+    fn test_blocks(){
+        // 0: 0~1 jmp:b:  1: label:a: 1~2  2: label:b: ijmp:a:  3: 2~3
+        let org_expr = parse(r"0~1jmp:b:label:a:1~2label:b:ijmp:a:2~3^^^^^^666^^^^^^=O");
+        let cfg = ControlFlowGraph::new(&org_expr);
+        assert_eq!(
+            cfg.blocks().iter().map(|block| (block.exprs.clone(), block.label.clone())).collect::<Vec<_>>(),
+            vec![(0..2, None), (2..4, Some(format!("a"))), (4..6, Some(format!("b"))), (6..7, None)]
+        );
+        assert_eq!(edges(&cfg), vec![vec![2], vec![2], vec![1, 3], vec![]]);
+        assert_eq!(cfg.predecessors(2), &[0, 1]);
+        assert_eq!(cfg.exprs(3), &org_expr.exprs[6..]);
+        assert_eq!((cfg.block_of(0), cfg.block_of(3), cfg.block_of(5), cfg.block_of(6)), (0, 1, 2, 3));
+        assert_eq!(cfg.immediate_dominator(1), Some(2));
+        assert_eq!(cfg.immediate_dominator(3), Some(2));
+        assert!(cfg.dominates(0, 3) && !cfg.dominates(1, 2));
+        assert_eq!(cfg.loops(), &[Loop { header: 2, latches: vec![1], body: vec![1, 2] }]);
+    }
+
+    #[test]
+    fn test_unreachable_and_empty(){
+        // A label right after a jump starts the next block, without an empty one
+        // between them, and two labels in a row are two blocks
+        let org_expr = parse(r"jmp:b:label:a:0~1label:c:label:b:^^^^^^666^^^^^^=O");
+        let cfg = ControlFlowGraph::new(&org_expr);
+        assert_eq!(
+            cfg.blocks().iter().map(|block| block.exprs.clone()).collect::<Vec<_>>(),
+            vec![0..1, 1..3, 3..4, 4..5]
+        );
+        assert_eq!(edges(&cfg), vec![vec![3], vec![2], vec![3], vec![]]);
+        assert!(!cfg.is_reachable(1) && !cfg.is_reachable(2));
+        assert_eq!(cfg.immediate_dominator(3), Some(0));
+        assert!(cfg.loops().is_empty());
+        let org_expr = OrganismExpression::default();
+        let cfg = ControlFlowGraph::new(&org_expr);
+        assert_eq!(cfg.blocks().len(), 1);
+        assert_eq!(cfg.exprs(cfg.entry()), &[]);
+    }
+
+    #[test]
+    fn test_nested_loops(){
+        // An outer loop on a with an inner one on b, which jumps to itself when cell 0
+        // is 0, then a block that jumps back to itself forever
+        let org_expr = parse(
+            r"label:a:0~1label:b:1~2ijmp:b:2~3ijmp:a:label:c:3~4jmp:c:^^^^^^666^^^^^^=O"
+        );
+        let cfg = ControlFlowGraph::new(&org_expr);
+        assert_eq!(edges(&cfg), vec![vec![1], vec![1, 2], vec![0, 3], vec![3]]);
+        assert_eq!(cfg.loops(), &[
+            Loop { header: 0, latches: vec![2], body: vec![0, 1, 2] },
+            Loop { header: 1, latches: vec![1], body: vec![1] },
+            Loop { header: 3, latches: vec![3], body: vec![3] }
+        ]);
+        assert_eq!(cfg.innermost_loop(1).map(|lp| lp.header), Some(1));
+        assert_eq!(cfg.innermost_loop(2).map(|lp| lp.header), Some(0));
+    }
+
+    #[test]
+    fn test_irreducible(){
+        // The cycle between a and b is entered at both, so neither dominates the other
+        // and it isn't a natural loop
+        let org_expr = parse(r"ijmp:b:label:a:0~1label:b:1~2ijmp:a:^^^^^^666^^^^^^=O");
+        let cfg = ControlFlowGraph::new(&org_expr);
+        assert_eq!(edges(&cfg), vec![vec![2, 1], vec![2], vec![1]]);
+        assert!(cfg.loops().is_empty());
+    }
+
+    #[test]
+    fn test_many_blocks(){
+        // Each block loops on itself while cell 0 is 0 and then falls into the next,
+        // so the dominator tree is as deep as there are blocks
+        let blocks = 100_000;
+        let source: String = (0..blocks)
+            .map(|i| format!("label:l{0}:0~1ijmp:l{0}:", i))
+            .chain(std::iter::once(format!("^^^^^^666^^^^^^=O")))
+            .collect();
+        let org_expr = parse(&source);
+        let cfg = ControlFlowGraph::new(&org_expr);
+        assert_eq!(cfg.blocks().len(), blocks);
+        assert_eq!(cfg.loops().len(), blocks);
+        assert!(cfg.dominates(0, blocks - 1));
+        assert_eq!(cfg.immediate_dominator(blocks - 1), Some(blocks - 2));
+    }
+}
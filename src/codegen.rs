@@ -1,18 +1,26 @@
-use std::{mem, process, fs};
-use inkwell::context::Context;
-use inkwell::module::Module;
+use std::process;
+use std::cell::RefCell;
+use std::path::{Path, PathBuf};
+pub use inkwell::context::Context;
+use inkwell::module::{Linkage, Module};
 use inkwell::builder::Builder;
 use inkwell::values::{FunctionValue, IntValue, PointerValue};
 use inkwell::types::{IntType, StringRadix};
 use inkwell::basic_block::BasicBlock;
+use inkwell::passes::{PassManager, PassManagerBuilder};
 use inkwell::{AddressSpace, OptimizationLevel, IntPredicate};
-use inkwell::targets::{CodeModel, RelocMode, InitializationConfig, Target, TargetMachine, FileType};
+use inkwell::targets::{CodeModel, RelocMode, InitializationConfig, Target, TargetMachine, TargetTriple, FileType};
 use crate::parser::{
-    OrganismExpression, ExprType, RegionExpression, Region,
-    LabelExpression, JumpExpression, LeachExpression, PrimitiveExpression, PrimitiveValue,
-    CellExpression, DummyExpression
+    OrganismExpression, Expr, RegionExpression, Region,
+    LabelExpression, LeachExpression, PassiveExpression, PrimitiveValue,
+    CellExpression
 };
 use crate::errors;
+use crate::errors::CompileError;
+use crate::emit::CodeKind;
+use crate::lexer::Span;
+use crate::source_map::FileId;
+use crate::{CompileOptions, OptLevel};
 
 struct Functions<'ctx> {
     putchar: FunctionValue<'ctx>,
@@ -34,11 +42,14 @@ struct DataLandscape<'ctx> {
 }
 
 pub struct CodeGen<'ctx> {
-    org_expr: Box<OrganismExpression>,
+    org_expr: &'ctx OrganismExpression,
     context: &'ctx Context,
     module: Module<'ctx>,
     builder: Builder<'ctx>,
-    labels: Vec<String>
+    labels: Vec<String>,
+    /// What the names of the values and blocks coded for the expression being coded
+    /// start with, so the IR can be traced back to the source
+    name_prefix: RefCell<String>
 }
 
 /// Accepts a PrimitiveValue and returns its Primitive Index as specified
@@ -60,92 +71,54 @@ macro_rules! pindex {
 }
 
 impl<'ctx> CodeGen<'ctx> {
-    pub fn new(org_expr: OrganismExpression, labels: Vec<String>) -> CodeGen<'ctx> {
-        unsafe {
-            let context = Box::new(Context::create());
-            let context = Box::into_raw(context) as *const Context;
-            CodeGen {
-                org_expr: Box::new(org_expr),
-                context: &*context,
-                module: (*context).create_module("main"),
-                builder: (*context).create_builder(),
-                labels
-            }
+    /// The code is generated in the context, which has to outlive it
+    pub fn new(
+        org_expr: &'ctx OrganismExpression,
+        labels: Vec<String>,
+        context: &'ctx Context
+    ) -> CodeGen<'ctx> {
+        CodeGen {
+            org_expr,
+            context,
+            module: context.create_module("main"),
+            builder: context.create_builder(),
+            labels,
+            name_prefix: RefCell::new(String::new())
         }
     }
-    pub fn code(&mut self) -> Result<(), String> {
+    pub fn code(&mut self) -> Result<(), CompileError> {
         let main_fn = self.init_main_fn();
         let main_block = self.context.append_basic_block(main_fn, "main");
         self.init_user_defined_blocks(&main_fn);
         let data_landscape = self.init_data_landscape(&main_fn);
-        let functions = self.init_functions();
+        let functions = self.init_functions()?;
         self.builder.position_at_end(main_block);
-        // Makes the right org expr of the head org expr to become
-        // the new head org expr and returns the old head
-        // If the right expr is none, then it replaces self's org_expr with a garbage org_expr
-        // This works because this fake org_expr will never be processed
-        // The if condition at the bottom of the loop will break out when it
-        // encounters the none on the right expr which the fake org_expr will have
-        let consume_org_expr = || unsafe {
-            let s = self as *const Self as *mut Self;
-            if self.org_expr.right.is_some(){
-                let right = mem::replace(&mut (*s).org_expr.right, None);
-                let org_expr: Box<OrganismExpression> = mem::replace(&mut (*s).org_expr, right.unwrap());
-                org_expr
-            } else {
-                let fake_org_expr_child = Box::new(DummyExpression::new());
-                let fake_org_expr = Box::new(OrganismExpression {
-                    child: fake_org_expr_child,
-                    right: None
-                });
-                mem::replace(&mut (*s).org_expr, fake_org_expr)
-            }
-        };
-        loop {
-            match self.org_expr.child.get_type(){
-                ExprType::Leach => {
-                    let org_expr = consume_org_expr();
-                    self.code_leach_expr(org_expr, &main_fn, &data_landscape, &functions);
-                }
+        for expr in self.org_expr.exprs.iter() {
+            self.name_prefix.replace(name_prefix(expr.span()));
+            match expr {
+                Expr::Leach(leach_expr) => self.code_leach_expr(leach_expr, &main_fn, &data_landscape, &functions)?,
                 // A lone cell expression with no effect
                 // If it was part of any leach expressions,
                 // it would have been in a leach expression
-                ExprType::Cell => {
-                    consume_org_expr();
-                    self.code_lone_cell_expression(&main_fn, &functions, &data_landscape);
-                }
+                Expr::Cell(_) => self.code_lone_cell_expression(&main_fn, &functions, &data_landscape)?,
                 // A lone primitive, just like the cell arm above
                 // But the PAR still needs to be carried out
-                ExprType::Primitive => {
-                    consume_org_expr();
-                    self.code_lone_primitive_expression(&main_fn, &functions, &data_landscape);
-                }
-                ExprType::Jump => {
-                    let org_expr = consume_org_expr();
-                    self.code_jmp(org_expr, &main_fn, &functions, &data_landscape);
-                }
-                ExprType::Drill => {
-                    consume_org_expr();
-                    self.code_drill_expr(&main_fn, &data_landscape, &functions)
-                }
-                ExprType::Region => {
-                    let org_expr: Box<OrganismExpression> = consume_org_expr();
-                    self.code_region_expr(org_expr, &data_landscape, &functions);
-                }
-                ExprType::Label => {
-                    let org_expr = consume_org_expr();
-                    self.code_label(org_expr, &main_fn);
-                }
-                ExprType::Dummy => break,
-                x => {
-                    println!("Supposed to be unreachable: {:?}", x);
-                    unreachable!();
-                }
-            }
+                Expr::Primitive(_) => self.code_lone_primitive_expression(&main_fn, &functions, &data_landscape)?,
+                Expr::Jump { to, conditional, .. } =>
+                    self.code_jmp(to, *conditional, &main_fn, &functions, &data_landscape),
+                Expr::Drill(_) => self.code_drill_expr(&main_fn, &data_landscape, &functions)?,
+                Expr::Region(region_expr) => self.code_region_expr(region_expr, &data_landscape, &functions),
+                Expr::Label(label_expr) => self.code_label(label_expr, &main_fn)
+            };
         }
+        self.name_prefix.replace(String::new());
         self.code_end_main(&main_fn);
         Ok(())
     }
+    /// The name with the prefix of the expression being coded
+    fn name(&self, name: &str) -> String {
+        format!("{}{}", self.name_prefix.borrow(), name)
+    }
     fn init_data_landscape(&self, main_fn: &FunctionValue) -> DataLandscape {
         let main_entry_block = self.get_basic_block(main_fn, "entry");
         let main_block = self.get_basic_block(main_fn, "main");
@@ -200,20 +173,20 @@ impl<'ctx> CodeGen<'ctx> {
         self.context.append_basic_block(main_fn, main_fn_block_names::END_IN_FAIL);
         main_fn
     }
-    fn init_functions(&self) -> Functions {
+    fn init_functions(&self) -> Result<Functions, CompileError> {
         let four_bytes = self.context.i32_type();
         let void = self.context.void_type();
         let putchar_type = void.fn_type(&[four_bytes.into()], false);
         let putchar = self.module.add_function("putchar", putchar_type, None);
         let getchar_type = four_bytes.fn_type(&[], false);
         let getchar = self.module.add_function("getchar", getchar_type, None);
-        let primitive_access_routine = self.code_primitive_access_routine(&putchar);
-        let state_update_routine = self.code_state_update_routine();
-        let drill_gate_routine = self.code_drill_gate_routine(&putchar);
-        let func_validation_routine = self.code_function_validation_routine(&putchar);
-        let expr_life_validation_routine = self.code_expression_life_validation_routine();
-        let cell_access_routine = self.code_cell_access_routine();
-        Functions {
+        let primitive_access_routine = self.code_primitive_access_routine(&putchar)?;
+        let state_update_routine = self.code_state_update_routine()?;
+        let drill_gate_routine = self.code_drill_gate_routine(&putchar)?;
+        let func_validation_routine = self.code_function_validation_routine(&putchar)?;
+        let expr_life_validation_routine = self.code_expression_life_validation_routine()?;
+        let cell_access_routine = self.code_cell_access_routine()?;
+        Ok(Functions {
             putchar,
             getchar,
             primitive_access_routine,
@@ -222,7 +195,7 @@ impl<'ctx> CodeGen<'ctx> {
             func_validation_routine,
             expr_life_validation_routine,
             cell_access_routine
-        }
+        })
     }
     fn init_user_defined_blocks(&self, main_fn: &FunctionValue){
         for label in self.labels.iter(){
@@ -240,12 +213,7 @@ impl<'ctx> CodeGen<'ctx> {
         self.builder.position_at_end(end_in_fail);
         self.builder.build_return(Some(&four_bytes.int(1)));
     }
-    fn code_primitive_access_routine(&self, putchar: &FunctionValue) -> FunctionValue {
-        let print_err_and_exit = ||{
-            println!("Something went wrong while coding the primitive access routine");
-            println!("I'm not sorry at all. Be a real man and don't whine");
-            process::exit(1);
-        };
+    fn code_primitive_access_routine(&self, putchar: &FunctionValue) -> Result<FunctionValue, CompileError> {
         let byte = self.context.i8_type();
         let byte_ptr = byte.ptr_type(AddressSpace::Generic);
         let four_bytes = self.context.i32_type();
@@ -255,7 +223,7 @@ impl<'ctx> CodeGen<'ctx> {
         let primitive_access_routine = self.module.add_function(
             "primitive_access_routine",
             primitive_access_routine_type,
-            None
+            Some(Linkage::Internal)
         );
         let entry_block = self.context.append_basic_block(primitive_access_routine, "entry_block");
         let curr_region_is_layers_block = self.context.append_basic_block(
@@ -274,7 +242,7 @@ impl<'ctx> CodeGen<'ctx> {
         let curr_region_ptr = primitive_access_routine.get_first_param();
         let curr_gate_state_ptr = primitive_access_routine.get_last_param();
         if curr_region_ptr.is_none() || curr_gate_state_ptr.is_none(){
-            print_err_and_exit();
+            return Err(CompileError::Codegen(format!("the primitive access routine")));
         }
         let curr_region_ptr = curr_region_ptr.unwrap().into_pointer_value();
         let curr_gate_state_ptr = curr_gate_state_ptr.unwrap().into_pointer_value();
@@ -313,14 +281,9 @@ impl<'ctx> CodeGen<'ctx> {
         let gates_not_open_err_msg = errors::err_invalid_primitive_access_gates_not_open_runtime();
         self.code_print(&*putchar, gates_not_open_err_msg);
         self.builder.build_return(Some(&four_bytes.const_int(1, false)));
-        primitive_access_routine
+        Ok(primitive_access_routine)
     }
-    fn code_state_update_routine(&self) -> FunctionValue {
-        let print_err_and_exit = ||{
-            println!("Something went wrong while coding the state update routine");
-            println!("Im' not sorry. Be a real man and don't whine about it");
-            process::exit(1);
-        };
+    fn code_state_update_routine(&self) -> Result<FunctionValue, CompileError> {
         let byte = self.context.i8_type();
         let byte_ptr = byte.ptr_type(AddressSpace::Generic);
         let four_bytes = self.context.i32_type();
@@ -341,7 +304,7 @@ impl<'ctx> CodeGen<'ctx> {
         let state_update_routine = self.module.add_function(
             "state_update_routine",
             state_update_routine_type,
-            None
+            Some(Linkage::Internal)
         );
         let gate_time_to_stay_open_ptr = state_update_routine.get_first_param();
         let curr_gate_state_ptr = state_update_routine.get_nth_param(1);
@@ -350,7 +313,7 @@ impl<'ctx> CodeGen<'ctx> {
         let cells_ptr = state_update_routine.get_last_param();
         if gate_time_to_stay_open_ptr.is_none() || curr_gate_state_ptr.is_none()
             || ttl_table_ptr.is_none() || reduce_ttso.is_none() || cells_ptr.is_none() {
-            print_err_and_exit();
+            return Err(CompileError::Codegen(format!("the state update routine")));
         }
         let curr_gate_state_ptr = curr_gate_state_ptr.unwrap();
         let ttl_table_ptr = ttl_table_ptr.unwrap();
@@ -466,14 +429,9 @@ impl<'ctx> CodeGen<'ctx> {
             self.builder.position_at_end(next_update_ttl_cell_block);
         }
         self.builder.build_return(None);
-        state_update_routine
+        Ok(state_update_routine)
     }
-    fn code_drill_gate_routine(&self, putchar: &FunctionValue) -> FunctionValue {
-        let print_err_and_exit = ||{
-            println!("Something went wrong while coding the drill gate routine");
-            println!("I'm not sorry. Be a real man and don't whine about it");
-            process::exit(1);
-        };
+    fn code_drill_gate_routine(&self, putchar: &FunctionValue) -> Result<FunctionValue, CompileError> {
         let byte = self.context.i8_type();
         let byte_ptr = byte.ptr_type(AddressSpace::Generic);
         let four_bytes = self.context.i32_type();
@@ -488,7 +446,7 @@ impl<'ctx> CodeGen<'ctx> {
         let drill_gate_routine = self.module.add_function(
             "drill_gate_routine",
             drill_gate_routine_type,
-            None
+            Some(Linkage::Internal)
         );
         let entry_block = self.context.append_basic_block(drill_gate_routine, "entry");
         let update_cgs_block = self.context.append_basic_block(drill_gate_routine, "update_cgs_block");
@@ -503,7 +461,7 @@ impl<'ctx> CodeGen<'ctx> {
         let curr_gate_state_ptr = drill_gate_routine.get_nth_param(1);
         let ttso_ptr = drill_gate_routine.get_last_param();
         if curr_gate_state_ptr.is_none() || curr_region_ptr.is_none() || ttso_ptr.is_none(){
-            print_err_and_exit();
+            return Err(CompileError::Codegen(format!("the drill gate routine")));
         }
         let curr_region_ptr = curr_region_ptr.unwrap().into_pointer_value();
         let curr_gate_state_ptr = curr_gate_state_ptr.unwrap().into_pointer_value();
@@ -560,14 +518,9 @@ impl<'ctx> CodeGen<'ctx> {
         self.builder.build_return(Some(&four_bytes.int(1)));
         self.builder.position_at_end(end_block);
         self.builder.build_return(Some(&four_bytes.int(0)));
-        drill_gate_routine
+        Ok(drill_gate_routine)
     }
-    fn code_expression_life_validation_routine(&self) -> FunctionValue {
-        let print_err_and_exit = ||{
-            println!("Something went wrong while coding the Expression Life Validatin routine");
-            println!("I'm not sorry. Be a real man and don't whine about it");
-            process::exit(1);
-        };
+    fn code_expression_life_validation_routine(&self) -> Result<FunctionValue, CompileError> {
         let byte = self.context.i8_type();
         let byte_ptr = byte.ptr_type(AddressSpace::Generic);
         let four_bytes = self.context.i32_type();
@@ -580,12 +533,12 @@ impl<'ctx> CodeGen<'ctx> {
         let expr_life_validation_routine = self.module.add_function(
             "expression_life_validation_routine",
             expr_life_validation_routine_type,
-            None
+            Some(Linkage::Internal)
         );
         let ttl_ptr = expr_life_validation_routine.get_first_param();
         let cell_num = expr_life_validation_routine.get_last_param();
         if ttl_ptr.is_none() || cell_num.is_none(){
-            print_err_and_exit();
+            return Err(CompileError::Codegen(format!("the expression life validation routine")));
         }
         let ttl_ptr = ttl_ptr.unwrap().into_pointer_value();
         let cell_num = cell_num.unwrap().into_int_value();
@@ -622,14 +575,9 @@ impl<'ctx> CodeGen<'ctx> {
         self.builder.build_return(Some(&four_bytes.int(1)));
         self.builder.position_at_end(expression_life_validation_success);
         self.builder.build_return(Some(&four_bytes.int(0)));
-        expr_life_validation_routine
+        Ok(expr_life_validation_routine)
     }
-    fn code_function_validation_routine(&self, putchar: &FunctionValue) -> FunctionValue {
-        let print_err_and_exit = ||{
-            println!("Something went wrong while coding the Function Validation Routine");
-            println!("I'm not sorry. Be a real man and don't whine about it");
-            process::exit(1);
-        };
+    fn code_function_validation_routine(&self, putchar: &FunctionValue) -> Result<FunctionValue, CompileError> {
         let byte = self.context.i8_type();
         let byte_ptr = byte.ptr_type(AddressSpace::Generic);
         let four_bytes = self.context.i32_type();
@@ -645,13 +593,13 @@ impl<'ctx> CodeGen<'ctx> {
         let func_validation_routine = self.module.add_function(
             "func_validation_routine",
             func_validation_routine_type,
-            None
+            Some(Linkage::Internal)
         );
         let cell_number = func_validation_routine.get_first_param();
         let cells_ptr = func_validation_routine.get_nth_param(1);
         let ttl_table_ptr = func_validation_routine.get_last_param();
         if cell_number.is_none() || cells_ptr.is_none() || ttl_table_ptr.is_none() {
-            print_err_and_exit();
+            return Err(CompileError::Codegen(format!("the function validation routine")));
         }
         let cell_number = cell_number.unwrap().into_int_value();
         let cells_ptr = cells_ptr.unwrap().into_pointer_value();
@@ -720,14 +668,9 @@ impl<'ctx> CodeGen<'ctx> {
         self.builder.build_return(Some(&four_bytes.int(1)));
         self.builder.position_at_end(func_validation_success_block);
         self.builder.build_return(Some(&four_bytes.int(0)));
-        func_validation_routine
+        Ok(func_validation_routine)
     }
-    fn code_cell_access_routine(&self) -> FunctionValue {
-        let print_err_and_exit = ||{
-            println!("Something went wrong while coding the Cell Access Routine");
-            println!("I'm not sorry. Be a real man and don't whine about it");
-            process::exit(1);
-        };
+    fn code_cell_access_routine(&self) -> Result<FunctionValue, CompileError> {
         let byte = self.context.i8_type();
         let byte_ptr = byte.ptr_type(AddressSpace::Generic);
         let four_bytes = self.context.i32_type();
@@ -736,11 +679,11 @@ impl<'ctx> CodeGen<'ctx> {
         let cell_access_routine = self.module.add_function(
             "cell_access_routine",
             cell_access_routine_type,
-            None
+            Some(Linkage::Internal)
         );
         let curr_region_ptr = cell_access_routine.get_first_param();
         if curr_region_ptr.is_none(){
-            print_err_and_exit();
+            return Err(CompileError::Codegen(format!("the cell access routine")));
         }
         let curr_region_ptr = curr_region_ptr.unwrap().into_pointer_value();
         let entry_block = self.context.append_basic_block(cell_access_routine, "entry_block");
@@ -763,88 +706,57 @@ impl<'ctx> CodeGen<'ctx> {
         self.builder.build_return(Some(&four_bytes.int(0)));
         self.builder.position_at_end(fail_block);
         self.builder.build_return(Some(&four_bytes.int(1)));
-        cell_access_routine
+        Ok(cell_access_routine)
     }
     fn code_leach_expr(
         &self,
-        org_expr: Box<OrganismExpression>,
+        leach_expr: &LeachExpression,
         main_fn: &FunctionValue,
         data_landscape: &DataLandscape,
         functions: &Functions
-    ){
-        let leach_expr = org_expr.child.as_any().downcast_ref::<LeachExpression>().unwrap();
-        if leach_expr.left().get_type() == ExprType::Primitive {
-            let left_expr = leach_expr.left().as_any().downcast_ref::<PrimitiveExpression>().unwrap();
-            let right_expr = leach_expr.right().as_ref().unwrap().left();
-            let right_expr = right_expr.as_any().downcast_ref::<CellExpression>().unwrap();
-            let region_changes = leach_expr.region_change.clone();
-            self.code_store_primitive(
-                left_expr.pval(),
-                right_expr.ident(),
-                region_changes,
-                &*main_fn,
-                &*data_landscape,
-                &*functions
-            );
-        } else {
-            // Then it is a cell
-            if leach_expr.is_chain {
-                // Then it's a function call
-                // Get the args for the function
-                // However the args will be interpreted depends on the kind of function it is
-                let mut args: Vec<&CellExpression> = vec![];
-                let fake_expr = CellExpression {ident: 255};
-                let leach_expr_ptr = leach_expr as *const LeachExpression as *mut LeachExpression;
-                loop {
-                    unsafe {
-                        let right = {
-                            let right = mem::replace(&mut (*leach_expr_ptr).right, None);
-                            if right.is_none(){
-                                let fake_leach_expr = LeachExpression::new(
-                                    Box::new(fake_expr.clone()), None, false, None
-                                );
-                                Box::new(fake_leach_expr)
-                            } else {
-                                right.unwrap()
-                            }
-                        };
-                        let mut old_leach_expr = mem::replace(&mut *leach_expr_ptr, *right);
-                        let left = mem::replace(&mut old_leach_expr.left, Box::new(fake_expr.clone()));
-                        let left_ptr = Box::into_raw(left);
-                        let left = (*left_ptr).as_any().downcast_ref::<CellExpression>().unwrap();
-                        if *left == fake_expr {
-                            break;
-                        }
-                        args.push(left);
-                    }
-                }
-                // The first cell isn't supposed to be an arg
-                let mut args = args.into_iter();
-                let cell_holding_primitive_func = args.next().unwrap().ident();
-                let args: Vec<&CellExpression> = args.collect();
+    ) -> Result<(), CompileError> {
+        // Only cells are leached onto
+        let right_expr = &leach_expr.onto()[0];
+        match leach_expr.left() {
+            PassiveExpression::Primitive(left_expr) => {
+                let region_changes = leach_expr.region_change.clone();
+                self.code_store_primitive(
+                    left_expr.pval(),
+                    right_expr.ident(),
+                    region_changes,
+                    &*main_fn,
+                    &*data_landscape,
+                    &*functions
+                )?;
+            }
+            // Then it's a function call
+            // The cell leached onto the others holds the primitive function, and the
+            // cells it's leached onto are the args
+            // However the args will be interpreted depends on the kind of function it is
+            PassiveExpression::Cell(left_expr) if leach_expr.is_chain => {
+                let args: Vec<&CellExpression> = leach_expr.onto().iter().collect();
                 self.code_function_call(
-                    cell_holding_primitive_func,
+                    left_expr.ident(),
                     args,
                     &*main_fn,
                     &*data_landscape,
                     &*functions
-                );
-            } else {
-                // Then it's a copy operation
-                // The cell must either be in the TTL table
-                // So check if it's there first
-                let left_expr = leach_expr.left().as_any().downcast_ref::<CellExpression>().unwrap();
-                let right_expr = leach_expr.right().as_ref().unwrap().left();
-                let right_expr = right_expr.as_any().downcast_ref::<CellExpression>().unwrap();
+                )?;
+            }
+            // Then it's a copy operation
+            // The cell must either be in the TTL table
+            // So check if it's there first
+            PassiveExpression::Cell(left_expr) => {
                 self.code_cell_copy(
                     left_expr.ident(),
                     right_expr.ident(),
                     &*main_fn,
                     &*data_landscape,
                     &*functions
-                );
+                )?;
             }
-        }
+        };
+        Ok(())
     }
     fn code_function_call(
         &self,
@@ -853,12 +765,7 @@ impl<'ctx> CodeGen<'ctx> {
         main_fn: &FunctionValue,
         data_landscape: &DataLandscape,
         functions: &Functions
-    ){
-        let print_err_and_exit = ||{
-            println!("Something went wrong while coding a function call");
-            println!("I'm not sorry. Be a real man and don't whine about it");
-            process::exit(1);
-        };
+    ) -> Result<(), CompileError> {
         let byte = self.context.i8_type();
         let four_bytes = self.context.i32_type();
         // Verify that the function is actually a function
@@ -869,25 +776,25 @@ impl<'ctx> CodeGen<'ctx> {
                 data_landscape.cells_ptr.into(),
                 data_landscape.ttl_table_ptr.into()
             ],
-            "carry_out_function_validation_routine"
+            &self.name("carry_out_function_validation_routine")
         ).try_as_basic_value().left();
         if func_validation_routine_result.is_none(){
-            print_err_and_exit();
+            return Err(CompileError::Codegen(format!("a function call")));
         }
         let func_validation_routine_result = func_validation_routine_result.unwrap().into_int_value();
         let func_is_valid = self.builder.build_int_compare(
             IntPredicate::EQ,
             func_validation_routine_result,
             four_bytes.int(0),
-            "func_is_valid"
+            &self.name("func_is_valid")
         );
         let func_validation_successful_block = self.context.append_basic_block(
             *main_fn,
-            "func_validation_routine_block"
+            &self.name("func_validation_routine_block")
         );
         let func_validation_failed_block = self.context.append_basic_block(
             *main_fn,
-            "func_validation_failed_block"
+            &self.name("func_validation_failed_block")
         );
         let end_in_fail_block = self.get_basic_block(main_fn, main_fn_block_names::END_IN_FAIL);
         self.builder.build_conditional_branch(
@@ -904,17 +811,17 @@ impl<'ctx> CodeGen<'ctx> {
             self.builder.build_in_bounds_gep(
                 data_landscape.cells_ptr,
                 &[four_bytes.int(pf_cell_ident as i64)],
-                "cell_ptr"
+                &self.name("cell_ptr")
             )
         };
-        let primitive_index = self.builder.build_load(cell_ptr, "func_primitive_index");
+        let primitive_index = self.builder.build_load(cell_ptr, &self.name("func_primitive_index"));
         // Block to branch to after the execution of a function
         // The main line of code building continues from here
-        let continue_main_block = self.context.append_basic_block(*main_fn, "continue_main");
-        let addition_block = self.context.append_basic_block(*main_fn, "addition_block");
-        let subtraction_block = self.context.append_basic_block(*main_fn, "subtraction_block");
-        let input_block = self.context.append_basic_block(*main_fn, "input_block");
-        let output_block = self.context.append_basic_block(*main_fn, "output_block");
+        let continue_main_block = self.context.append_basic_block(*main_fn, &self.name("continue_main"));
+        let addition_block = self.context.append_basic_block(*main_fn, &self.name("addition_block"));
+        let subtraction_block = self.context.append_basic_block(*main_fn, &self.name("subtraction_block"));
+        let input_block = self.context.append_basic_block(*main_fn, &self.name("input_block"));
+        let output_block = self.context.append_basic_block(*main_fn, &self.name("output_block"));
         self.builder.build_switch(
             primitive_index.into_int_value(),
             output_block,
@@ -933,25 +840,25 @@ impl<'ctx> CodeGen<'ctx> {
             self.builder.build_in_bounds_gep(
                 data_landscape.cells_ptr,
                 &[four_bytes.int(first_arg.ident() as i64)],
-                "first_arg_cell_ptr"
+                &self.name("first_arg_cell_ptr")
             )
         };
-        let mut addition_result = self.builder.build_load(first_arg_cell_ptr, "first_arg_value")
+        let mut addition_result = self.builder.build_load(first_arg_cell_ptr, &self.name("first_arg_value"))
             .into_int_value();
         for arg in args.iter() {
             let arg_cell_ptr = unsafe {
                 self.builder.build_in_bounds_gep(
                     data_landscape.cells_ptr,
                     &[four_bytes.int(arg.ident() as i64)],
-                    "arg_cell_ptr"
+                    &self.name("arg_cell_ptr")
                 )
             };
-            let arg_value = self.builder.build_load(arg_cell_ptr, "arg_value")
+            let arg_value = self.builder.build_load(arg_cell_ptr, &self.name("arg_value"))
                 .into_int_value();
             addition_result = self.builder.build_int_add(
                 addition_result,
                 arg_value,
-                "add_another_arg"
+                &self.name("add_another_arg")
             );
         }
         // Store the result in the last cell
@@ -959,7 +866,7 @@ impl<'ctx> CodeGen<'ctx> {
             self.builder.build_in_bounds_gep(
                 data_landscape.cells_ptr,
                 &[four_bytes.int(target_cell_ident as i64)],
-                "last_arg_cell_ptr"
+                &self.name("last_arg_cell_ptr")
             )
         };
         self.builder.build_store(last_arg_cell_ptr, addition_result);
@@ -970,25 +877,25 @@ impl<'ctx> CodeGen<'ctx> {
             self.builder.build_in_bounds_gep(
                 data_landscape.cells_ptr,
                 &[four_bytes.int(first_arg.ident() as i64)],
-                "first_arg_cell_ptr"
+                &self.name("first_arg_cell_ptr")
             )
         };
-        let mut subtraction_result = self.builder.build_load(first_arg_cell_ptr, "first_arg_value")
+        let mut subtraction_result = self.builder.build_load(first_arg_cell_ptr, &self.name("first_arg_value"))
             .into_int_value();
         for arg in args.iter() {
             let arg_cell_ptr = unsafe {
                 self.builder.build_in_bounds_gep(
                     data_landscape.cells_ptr,
                     &[four_bytes.int(arg.ident() as i64)],
-                    "arg_cell_ptr"
+                    &self.name("arg_cell_ptr")
                 )
             };
-            let arg_value = self.builder.build_load(arg_cell_ptr, "arg_value")
+            let arg_value = self.builder.build_load(arg_cell_ptr, &self.name("arg_value"))
                 .into_int_value();
             subtraction_result = self.builder.build_int_sub(
                 subtraction_result,
                 arg_value,
-                "add_another_arg"
+                &self.name("add_another_arg")
             );
         }
         self.builder.build_store(last_arg_cell_ptr, subtraction_result);
@@ -1003,10 +910,10 @@ impl<'ctx> CodeGen<'ctx> {
                     self.builder.build_in_bounds_gep(
                         data_landscape.cells_ptr,
                         &[four_bytes.int(num_cell_offset as i64)],
-                        "num_cell_ptr"
+                        &self.name("num_cell_ptr")
                     )
                 };
-                num_value = self.builder.build_load(num_cell_ptr, "num_value")
+                num_value = self.builder.build_load(num_cell_ptr, &self.name("num_value"))
                     .into_int_value();
             } else {
                 let first_digit_cell_offset = args[i].ident();
@@ -1015,36 +922,36 @@ impl<'ctx> CodeGen<'ctx> {
                     self.builder.build_in_bounds_gep(
                         data_landscape.cells_ptr,
                         &[four_bytes.int(first_digit_cell_offset as i64)],
-                        "first_digit_cell_ptr"
+                        &self.name("first_digit_cell_ptr")
                     )
                 };
                 let second_digit_cell_ptr = unsafe {
                     self.builder.build_in_bounds_gep(
                         data_landscape.cells_ptr,
                         &[four_bytes.int(second_digit_cell_offset as i64)],
-                        "second_digit_cell_ptr"
+                        &self.name("second_digit_cell_ptr")
                     )
                 };
-                let first_digit_value = self.builder.build_load(first_digit_cell_ptr, "first_digit")
+                let first_digit_value = self.builder.build_load(first_digit_cell_ptr, &self.name("first_digit"))
                     .into_int_value();
-                let second_digit_value = self.builder.build_load(second_digit_cell_ptr, "second_digit")
+                let second_digit_value = self.builder.build_load(second_digit_cell_ptr, &self.name("second_digit"))
                     .into_int_value();
                 let first_digit_tens_value = self.builder.build_int_mul(
                     first_digit_value,
                     four_bytes.int(10),
-                    "first_digit_tens_value"
+                    &self.name("first_digit_tens_value")
                 );
                 let final_ascii_value = self.builder.build_int_add(
                     first_digit_tens_value,
                     second_digit_value,
-                    "final_ascii_value"
+                    &self.name("final_ascii_value")
                 );
                 num_value = final_ascii_value;
             }
             self.builder.build_call(
                 functions.putchar,
                 &[num_value.into()],
-                "print_value"
+                &self.name("print_value")
             );
         }
         self.code_post_func_exec_routine(pf_cell_ident, &args, &data_landscape, &functions);
@@ -1056,10 +963,10 @@ impl<'ctx> CodeGen<'ctx> {
             self.builder.build_in_bounds_gep(
                 data_landscape.cells_ptr,
                 &[four_bytes.int(first_arg.ident() as i64)],
-                "first_arg_cell_ptr"
+                &self.name("first_arg_cell_ptr")
             )
         };
-        let input = self.builder.build_call(functions.getchar, &[], "input")
+        let input = self.builder.build_call(functions.getchar, &[], &self.name("input"))
             .try_as_basic_value()
             .left()
             .unwrap()
@@ -1069,6 +976,7 @@ impl<'ctx> CodeGen<'ctx> {
         self.builder.build_unconditional_branch(continue_main_block);
         // Other code that comes after this should be in the continue_main_block
         self.builder.position_at_end(continue_main_block);
+        Ok(())
     }
     fn code_cell_copy(
         &self,
@@ -1077,12 +985,7 @@ impl<'ctx> CodeGen<'ctx> {
         main_fn: &FunctionValue,
         data_landscape: &DataLandscape,
         functions: &Functions
-    ){
-        let print_err_and_exit = ||{
-            println!("Something went wrong while coding a leach expression");
-            println!("I'm not sorry. Be a real man and don't whine about it");
-            process::exit(1);
-        };
+    ) -> Result<(), CompileError> {
         let byte = self.context.i8_type();
         let four_bytes = self.context.i32_type();
         let expr_life_validation_result = self.builder.build_call(
@@ -1091,25 +994,25 @@ impl<'ctx> CodeGen<'ctx> {
                 data_landscape.ttl_table_ptr.into(),
                 byte.int(left_cell_ident as i64).into()
             ],
-            "expr_life_validation_result"
+            &self.name("expr_life_validation_result")
         ).try_as_basic_value().left();
         if expr_life_validation_result.is_none(){
-            print_err_and_exit();
+            return Err(CompileError::Codegen(format!("a leach expression")));
         }
         let expr_life_validation_result = expr_life_validation_result.unwrap().into_int_value();
         let expr_is_alive = self.builder.build_int_compare(
             IntPredicate::EQ,
             expr_life_validation_result,
             four_bytes.int(0),
-            "expr_is_alive"
+            &self.name("expr_is_alive")
         );
         let expr_life_validation_successful = self.context.append_basic_block(
             *main_fn,
-            "expr_life_validation_successful"
+            &self.name("expr_life_validation_successful")
         );
         let expr_life_validation_failed = self.context.append_basic_block(
             *main_fn,
-            "expr_life_validation_failed"
+            &self.name("expr_life_validation_failed")
         );
         let end_in_fail = self.get_basic_block(&main_fn, main_fn_block_names::END_IN_FAIL);
         self.builder.build_conditional_branch(
@@ -1117,7 +1020,7 @@ impl<'ctx> CodeGen<'ctx> {
             expr_life_validation_successful,
             expr_life_validation_failed
         );
-        let continue_main_block = self.context.append_basic_block(*main_fn, "continue_main_block");
+        let continue_main_block = self.context.append_basic_block(*main_fn, &self.name("continue_main_block"));
         self.builder.position_at_end(expr_life_validation_failed);
         let err_msg = errors::err_attempt_to_leach_death_expression_onto_another_cell();
         self.code_print(&functions.putchar, err_msg);
@@ -1129,17 +1032,17 @@ impl<'ctx> CodeGen<'ctx> {
             self.builder.build_in_bounds_gep(
                 data_landscape.cells_ptr,
                 &[four_bytes.int(src_cell_addr_offset as i64)],
-                "src_cell_ptr"
+                &self.name("src_cell_ptr")
             )
         };
-        let src_cell_value = self.builder.build_load(src_cell_ptr, "src_cell_value")
+        let src_cell_value = self.builder.build_load(src_cell_ptr, &self.name("src_cell_value"))
             .into_int_value();
         let dest_cell_addr_offset = right_cell_ident;
         let dest_cell_ptr = unsafe {
             self.builder.build_in_bounds_gep(
                 data_landscape.cells_ptr,
                 &[four_bytes.int(dest_cell_addr_offset as i64)],
-                "dest_cell_ptr"
+                &self.name("dest_cell_ptr")
             )
         };
         self.builder.build_store(dest_cell_ptr, src_cell_value);
@@ -1151,20 +1054,20 @@ impl<'ctx> CodeGen<'ctx> {
                 data_landscape.ttl_table_ptr.into(),
                 byte.int(1).into(),
                 data_landscape.cells_ptr.into()
-            ], "carry_out_state_update_routine");
+            ], &self.name("carry_out_state_update_routine"));
         let dest_ttl_cell_offset = right_cell_ident;
         let dest_ttl_cell_ptr = unsafe {
             self.builder.build_in_bounds_gep(
                 data_landscape.ttl_table_ptr,
                 &[byte.int(dest_ttl_cell_offset as i64)],
-                "dest_ttl_cell_ptr"
+                &self.name("dest_ttl_cell_ptr")
             )
         };
         let src_ttl_cell_ptr = unsafe {
             self.builder.build_in_bounds_gep(
                 data_landscape.ttl_table_ptr,
                 &[byte.int(src_cell_addr_offset as i64)],
-                "src_ttl_cell_ptr"
+                &self.name("src_ttl_cell_ptr")
             )
         };
         self.builder.build_store(dest_ttl_cell_ptr, byte.int(5));
@@ -1172,6 +1075,7 @@ impl<'ctx> CodeGen<'ctx> {
         self.builder.build_store(src_ttl_cell_ptr, byte.int(0));
         self.builder.build_unconditional_branch(continue_main_block);
         self.builder.position_at_end(continue_main_block);
+        Ok(())
     }
     fn code_store_primitive(
         &self,
@@ -1181,12 +1085,7 @@ impl<'ctx> CodeGen<'ctx> {
         main_fn: &FunctionValue,
         data_landscape: &DataLandscape,
         functions: &Functions
-    ){
-        let print_err_and_exit = ||{
-            println!("Something went wrong while coding a store primitive operation");
-            println!("I'm not sorry. Be a real man and don't whine about it");
-            process::exit(1);
-        };
+    ) -> Result<(), CompileError> {
         let byte = self.context.i8_type();
         let four_bytes = self.context.i32_type();
         let primitive_access_routine_result = self.builder.build_call(
@@ -1195,27 +1094,27 @@ impl<'ctx> CodeGen<'ctx> {
                 data_landscape.curr_region_ptr.into(),
                 data_landscape.curr_gates_state_ptr.into()
             ],
-            "primitive_access_routine_result"
+            &self.name("primitive_access_routine_result")
         ).try_as_basic_value().left();
         if primitive_access_routine_result.is_none(){
-            print_err_and_exit();
+            return Err(CompileError::Codegen(format!("a store primitive operation")));
         }
         let primitive_access_routine_result = primitive_access_routine_result.unwrap();
         let primitive_access_routine_successful_block = self.context.append_basic_block(
             *main_fn,
-            "primitive_access_routine_successful_block"
+            &self.name("primitive_access_routine_successful_block")
         );
         let primitive_access_routine_failed_block = self.context.append_basic_block(
             *main_fn,
-            "primitive_access_routine_failed_block"
+            &self.name("primitive_access_routine_failed_block")
         );
         let end_in_fail_block = self.get_basic_block(main_fn, main_fn_block_names::END_IN_FAIL);
-        let continue_main_block = self.context.append_basic_block(*main_fn, "continue_main_block");
+        let continue_main_block = self.context.append_basic_block(*main_fn, &self.name("continue_main_block"));
         let primitive_access_is_successful = self.builder.build_int_compare(
             IntPredicate::EQ,
             primitive_access_routine_result.into_int_value(),
             four_bytes.int(0),
-            "primitive_access_is_successful"
+            &self.name("primitive_access_is_successful")
         );
         self.builder.build_conditional_branch(
             primitive_access_is_successful,
@@ -1254,32 +1153,31 @@ impl<'ctx> CodeGen<'ctx> {
         }
         if region_changes.is_some(){
             for region_expr in region_changes.unwrap(){
-                let organismed_region_expr = Box::new(OrganismExpression::new(Box::new(region_expr), None));
-                self.code_region_expr(organismed_region_expr, &*data_landscape, &*functions);
+                self.code_region_expr(&region_expr, &*data_landscape, &*functions);
             }
         }
         let cell_access_routine_result = self.builder.build_call(
             functions.cell_access_routine,
             &[data_landscape.curr_region_ptr.into()],
-            "carry_out_cell_access_routine"
+            &self.name("carry_out_cell_access_routine")
         ).try_as_basic_value().left();
         if cell_access_routine_result.is_none(){
-            print_err_and_exit();
+            return Err(CompileError::Codegen(format!("a store primitive operation")));
         }
         let cell_access_routine_result = cell_access_routine_result.unwrap();
         let cell_access_routine_successful = self.builder.build_int_compare(
             IntPredicate::EQ,
             cell_access_routine_result.into_int_value(),
             four_bytes.int(0),
-            "cell_access_routine_successful"
+            &self.name("cell_access_routine_successful")
         );
         let cell_access_routine_successful_block = self.context.append_basic_block(
             *main_fn,
-            "cell_access_routine_successful_block"
+            &self.name("cell_access_routine_successful_block")
         );
         let cell_access_routine_failed_block = self.context.append_basic_block(
             *main_fn,
-            "cell_access_routine_failed_block"
+            &self.name("cell_access_routine_failed_block")
         );
         self.builder.build_conditional_branch(
             cell_access_routine_successful,
@@ -1295,7 +1193,7 @@ impl<'ctx> CodeGen<'ctx> {
             self.builder.build_in_bounds_gep(
                 data_landscape.cells_ptr,
                 &[four_bytes.int(target_cell_ident as i64)],
-                "target_cell_ptr"
+                &self.name("target_cell_ptr")
             )
         };
         self.builder.build_store(target_cell_ptr, four_bytes.int(value_to_store_in_target_cell as i64));
@@ -1308,27 +1206,27 @@ impl<'ctx> CodeGen<'ctx> {
                 byte.int(1).into(),
                 data_landscape.cells_ptr.into()
             ],
-            "carry_out_state_update_routine"
+            &self.name("carry_out_state_update_routine")
         );
         let ttl_cell_offset = target_cell_ident;
         let ttl_cell_ptr = unsafe {
             self.builder.build_in_bounds_gep(
                 data_landscape.ttl_table_ptr,
                 &[byte.int(ttl_cell_offset as i64)],
-                "ttl_cell_ptr"
+                &self.name("ttl_cell_ptr")
             )
         };
         self.builder.build_store(ttl_cell_ptr, byte.int(5));
         self.builder.build_unconditional_branch(continue_main_block);
         self.builder.position_at_end(continue_main_block);
+        Ok(())
     }
     fn code_region_expr(
         &self,
-        org_expr: Box<OrganismExpression>,
+        region_expr: &RegionExpression,
         data_landscape: &DataLandscape,
         functions: &Functions
     ) -> () {
-        let region_expr = (*org_expr).child.as_any().downcast_ref::<RegionExpression>().unwrap();
         let byte = self.context.i8_type();
         let target_region_num: IntValue;
         match region_expr.to() {
@@ -1345,15 +1243,10 @@ impl<'ctx> CodeGen<'ctx> {
                 byte.int(1).into(),
                 data_landscape.cells_ptr.into()
             ],
-            "carry_out_state_update_routine"
+            &self.name("carry_out_state_update_routine")
         );
     }
-    fn code_drill_expr(&self, main_fn: &FunctionValue, data_landscape: &DataLandscape, functions: &Functions){
-        let print_err_and_exit = ||{
-            println!("Something went wrong while coding the drill gate routine");
-            println!("I'm not sorry. Be a real man and don't whine about it");
-            process::exit(1);
-        };
+    fn code_drill_expr(&self, main_fn: &FunctionValue, data_landscape: &DataLandscape, functions: &Functions) -> Result<(), CompileError> {
         let byte = self.context.i8_type();
         let four_bytes = self.context.i32_type();
         let drill_result = self.builder.build_call(
@@ -1363,21 +1256,21 @@ impl<'ctx> CodeGen<'ctx> {
                 data_landscape.curr_gates_state_ptr.into(),
                 data_landscape.gates_ttso_ptr.into()
             ],
-            "carry_out_drill_routine"
+            &self.name("carry_out_drill_routine")
         ).try_as_basic_value().left();
         if drill_result.is_none(){
-            print_err_and_exit();
+            return Err(CompileError::Codegen(format!("a drill expression")));
         }
         let drill_result = drill_result.unwrap().into_int_value();
-        let drill_success_block = self.context.append_basic_block(*main_fn, "drill_success_block");
-        let drill_fail_block = self.context.append_basic_block(*main_fn, "drill_fail_block");
-        let continue_main_block = self.context.append_basic_block(*main_fn, "continue_main_block");
+        let drill_success_block = self.context.append_basic_block(*main_fn, &self.name("drill_success_block"));
+        let drill_fail_block = self.context.append_basic_block(*main_fn, &self.name("drill_fail_block"));
+        let continue_main_block = self.context.append_basic_block(*main_fn, &self.name("continue_main_block"));
         let end_main_fail = self.get_basic_block(main_fn, main_fn_block_names::END_IN_FAIL);
         let drill_fail =  self.builder.build_int_compare(
             IntPredicate::NE,
             drill_result,
             four_bytes.int(0),
-            "drill_fail"
+            &self.name("drill_fail")
         );
         self.builder.build_conditional_branch(
             drill_fail,
@@ -1396,21 +1289,19 @@ impl<'ctx> CodeGen<'ctx> {
                 byte.int(0).into(),
                 data_landscape.cells_ptr.into()
             ],
-            "carry_out_state_update_routine"
+            &self.name("carry_out_state_update_routine")
         );
         self.builder.build_unconditional_branch(continue_main_block);
         self.builder.position_at_end(continue_main_block);
+        Ok(())
     }
-    fn code_label(&self, org_expr: Box<OrganismExpression>, main_fn: &FunctionValue){
-        let label_expr = org_expr.child.as_any().downcast_ref::<LabelExpression>().unwrap();
+    fn code_label(&self, label_expr: &LabelExpression, main_fn: &FunctionValue){
         let label = format!("{}{}", USER_DEFINED_LABEL_PREFIX, label_expr.label());
         let labelled_block = self.get_basic_block(main_fn, label.as_str());
         self.builder.build_unconditional_branch(labelled_block);
         self.builder.position_at_end(labelled_block);
     }
-    fn code_jmp(&self, org_expr: Box<OrganismExpression>, main_fn: &FunctionValue, functions: &Functions, data_landscape: &DataLandscape){
-        let jmp_expr = org_expr.child.as_any().downcast_ref::<JumpExpression>().unwrap();
-        let to = jmp_expr.to();
+    fn code_jmp(&self, to: &str, conditional: bool, main_fn: &FunctionValue, functions: &Functions, data_landscape: &DataLandscape){
         let target_block = main_fn.get_basic_blocks()
             .into_iter()
             .filter(|block|{
@@ -1418,7 +1309,7 @@ impl<'ctx> CodeGen<'ctx> {
                 let block_name = block.get_name().to_str().unwrap();
                 block_name == dest_block_name
             }).next().unwrap();
-        if jmp_expr.conditional(){
+        if conditional {
             self.code_conditional_jmp(&target_block, &*main_fn, &*functions, &*data_landscape);
         } else {
             self.code_unconditional_jmp(&target_block, &*main_fn, &*functions, &*data_landscape);
@@ -1435,10 +1326,10 @@ impl<'ctx> CodeGen<'ctx> {
                 byte.int(1).into(),
                 data_landscape.cells_ptr.into()
             ],
-            "carry_out_state_update_routine"
+            &self.name("carry_out_state_update_routine")
         );
         self.builder.build_unconditional_branch(*target_block);
-        let continue_main_block = self.context.append_basic_block(*main_fn, "continue_main_block");
+        let continue_main_block = self.context.append_basic_block(*main_fn, &self.name("continue_main_block"));
         self.builder.position_at_end(continue_main_block);
     }
     fn code_conditional_jmp(&self, target_block: &BasicBlock<'_>, main_fn: &FunctionValue, functions: &Functions, data_landscape: &DataLandscape){
@@ -1448,15 +1339,15 @@ impl<'ctx> CodeGen<'ctx> {
             self.builder.build_in_bounds_gep(
                 data_landscape.cells_ptr,
                 &[four_bytes.int(0)],
-                "cell_0_ptr"
+                &self.name("cell_0_ptr")
             )
         };
-        let cell_0_val = self.builder.build_load(cell_0_ptr, "cell_0_val");
+        let cell_0_val = self.builder.build_load(cell_0_ptr, &self.name("cell_0_val"));
         let cell_0_val_is_0 = self.builder.build_int_compare(
             IntPredicate::EQ,
             cell_0_val.into_int_value(),
             four_bytes.int(0),
-            "cell_0_val_is_0"
+            &self.name("cell_0_val_is_0")
         );
         self.builder.build_call(
             functions.state_update_routine,
@@ -1467,9 +1358,9 @@ impl<'ctx> CodeGen<'ctx> {
                 byte.int(1).into(),
                 data_landscape.cells_ptr.into()
             ],
-            "carry_out_state_update_routine"
+            &self.name("carry_out_state_update_routine")
         );
-        let continue_main_block = self.context.append_basic_block(*main_fn, "continue_main_block");
+        let continue_main_block = self.context.append_basic_block(*main_fn, &self.name("continue_main_block"));
         self.builder.build_conditional_branch(
             cell_0_val_is_0,
             *target_block,
@@ -1482,31 +1373,26 @@ impl<'ctx> CodeGen<'ctx> {
         main_fn: &FunctionValue,
         functions: &Functions,
         data_landscape: &DataLandscape
-    ){
-        let print_err_and_exit = ||{
-            println!("Something went wrong while coding a leach expression");
-            println!("I'm not sorry at all. Be a real man and don't whine");
-            process::exit(1);
-        };
+    ) -> Result<(), CompileError> {
         let four_bytes = self.context.i32_type();
         let cell_access_routine_result = self.builder.build_call(
             functions.cell_access_routine,
             &[data_landscape.curr_region_ptr.into()],
-            "cell_access_routine_result"
+            &self.name("cell_access_routine_result")
         ).try_as_basic_value().left();
         if cell_access_routine_result.is_none(){
-            print_err_and_exit();
+            return Err(CompileError::Codegen(format!("a cell expression")));
         }
         let cell_access_routine_result = cell_access_routine_result.unwrap().into_int_value();
-        let cell_access_failed_block = self.context.append_basic_block(*main_fn, "cell_access_failed_block");
-        let cell_access_successful_block = self.context.append_basic_block(*main_fn, "cell_access_successful_block");
-        let continue_main_block = self.context.append_basic_block(*main_fn, "continue_main_block");
+        let cell_access_failed_block = self.context.append_basic_block(*main_fn, &self.name("cell_access_failed_block"));
+        let cell_access_successful_block = self.context.append_basic_block(*main_fn, &self.name("cell_access_successful_block"));
+        let continue_main_block = self.context.append_basic_block(*main_fn, &self.name("continue_main_block"));
         let end_in_fail_block = self.get_basic_block(main_fn, main_fn_block_names::END_IN_FAIL);
         let cell_access_routine_successful = self.builder.build_int_compare(
             IntPredicate::EQ,
             cell_access_routine_result,
             four_bytes.int(0),
-            "cell_access_routine_successful"
+            &self.name("cell_access_routine_successful")
         );
         self.builder.build_conditional_branch(
             cell_access_routine_successful,
@@ -1520,18 +1406,14 @@ impl<'ctx> CodeGen<'ctx> {
         self.builder.position_at_end(cell_access_successful_block);
         self.builder.build_unconditional_branch(continue_main_block);
         self.builder.position_at_end(continue_main_block);
+        Ok(())
     }
     fn code_lone_primitive_expression(
         &self,
         main_fn: &FunctionValue,
         functions: &Functions,
         data_landscape: &DataLandscape
-    ){
-        let print_err_and_exit = ||{
-            println!("Something went wrong while coding a primitive expression");
-            println!("I'm not sorry at all. Be a real man and don't whine");
-            process::exit(1);
-        };
+    ) -> Result<(), CompileError> {
         let four_bytes = self.context.i32_type();
         let primitive_access_routine_result = self.builder.build_call(
             functions.primitive_access_routine,
@@ -1539,27 +1421,27 @@ impl<'ctx> CodeGen<'ctx> {
                 data_landscape.curr_region_ptr.into(),
                 data_landscape.curr_gates_state_ptr.into()
             ],
-            "primitive_access_routine_result"
+            &self.name("primitive_access_routine_result")
         ).try_as_basic_value().left();
         if primitive_access_routine_result.is_none(){
-            print_err_and_exit();
+            return Err(CompileError::Codegen(format!("a primitive expression")));
         }
         let primitive_access_routine_result = primitive_access_routine_result.unwrap().into_int_value();
         let primitive_access_failed_block = self.context.append_basic_block(
             *main_fn,
-            "primitive_access_failed_block"
+            &self.name("primitive_access_failed_block")
         );
         let primitive_access_successful_block = self.context.append_basic_block(
             *main_fn,
-            "primitive_access_successful_block"
+            &self.name("primitive_access_successful_block")
         );
-        let continue_main_block = self.context.append_basic_block(*main_fn, "continue_main_block");
+        let continue_main_block = self.context.append_basic_block(*main_fn, &self.name("continue_main_block"));
         let end_in_fail_block = self.get_basic_block(main_fn, main_fn_block_names::END_IN_FAIL);
         let primitive_access_routine_successful = self.builder.build_int_compare(
             IntPredicate::EQ,
             primitive_access_routine_result,
             four_bytes.int(0),
-            "primitive_access_routine_successful"
+            &self.name("primitive_access_routine_successful")
         );
         self.builder.build_conditional_branch(
             primitive_access_routine_successful,
@@ -1571,6 +1453,7 @@ impl<'ctx> CodeGen<'ctx> {
         self.builder.position_at_end(primitive_access_successful_block);
         self.builder.build_unconditional_branch(continue_main_block);
         self.builder.position_at_end(continue_main_block);
+        Ok(())
     }
     fn code_post_func_exec_routine(
         &self,
@@ -1585,7 +1468,7 @@ impl<'ctx> CodeGen<'ctx> {
                 self.builder.build_in_bounds_gep(
                     data_landscape.ttl_table_ptr,
                     &[byte.int(cell_ttl_addr_offset as i64)],
-                    "cell_ttl_ptr"
+                    &self.name("cell_ttl_ptr")
                 )
             };
             self.builder.build_store(cell_ttl_ptr, byte.int(0));
@@ -1604,7 +1487,7 @@ impl<'ctx> CodeGen<'ctx> {
                     byte.int(1).into(),
                     data_landscape.cells_ptr.into()
                 ],
-                "carry_out_state_update_routine"
+                &self.name("carry_out_state_update_routine")
             );
         }
         // Kill all args except the last
@@ -1621,7 +1504,7 @@ impl<'ctx> CodeGen<'ctx> {
             self.builder.build_gep(
                 data_landscape.ttl_table_ptr,
                 &[byte.int(target_cell_ident as i64)],
-                "target_cell_ptr"
+                &self.name("target_cell_ptr")
             )
         };
         self.builder.build_store(target_cell_ptr, byte.int(5));
@@ -1642,52 +1525,205 @@ impl<'ctx> CodeGen<'ctx> {
                 basic_block.get_name().to_str().unwrap() == name
             }).next().unwrap()
     }
-    pub fn write_code_to_file(&self, out_filename: &str){
-        let print_err_and_exit = ||{
-            println!("Something went wrong while writing code to a file");
-            println!("Be a real man and don't whine");
-            process::exit(1);
+    /// Runs the passes of the level over the module. The runtime routines are internal,
+    /// so once they're inlined into main they're dropped, and the data landscape's
+    /// allocas are promoted to registers where they can be
+    pub fn optimize(&self, opt_level: OptLevel) {
+        let (level, size_level, inline_threshold) = match opt_level {
+            OptLevel::O0 => return,
+            OptLevel::O1 => (OptimizationLevel::Less, 0, 225),
+            OptLevel::O2 => (OptimizationLevel::Default, 0, 225),
+            OptLevel::O3 => (OptimizationLevel::Aggressive, 0, 275),
+            OptLevel::Os => (OptimizationLevel::Default, 1, 75)
         };
+        let builder = PassManagerBuilder::create();
+        builder.set_optimization_level(level);
+        builder.set_size_level(size_level);
+        builder.set_inliner_with_threshold(inline_threshold);
+        let passes = PassManager::create(());
+        passes.add_function_inlining_pass();
+        passes.add_promote_memory_to_register_pass();
+        passes.add_cfg_simplification_pass();
+        builder.populate_module_pass_manager(&passes);
+        passes.run_on(&self.module);
+    }
+    /// The code in the form, as it's written to a file
+    pub fn emit(&self, kind: CodeKind, options: &CompileOptions) -> Result<Vec<u8>, CompileError> {
+        let file_type = match kind {
+            CodeKind::LlvmIr => return Ok(self.module.print_to_string().to_bytes().to_vec()),
+            CodeKind::LlvmBc => return Ok(self.module.write_bitcode_to_memory().as_slice().to_vec()),
+            CodeKind::Asm => FileType::Assembly,
+            CodeKind::Obj => FileType::Object
+        };
+        self.target_machine(options)?
+            .write_to_memory_buffer(&self.module, file_type)
+            .map(|buffer| buffer.as_slice().to_vec())
+            .map_err(|err| CompileError::ObjectWrite(err.to_string()))
+    }
+    /// Sets the module's triple and data layout to those of the target, so the passes
+    /// and the code generated after are for it
+    pub fn set_target(&self, options: &CompileOptions) -> Result<(), CompileError> {
+        let target_machine = self.target_machine(options)?;
+        self.module.set_triple(&target_machine.get_triple());
+        self.module.set_data_layout(&target_machine.get_target_data().get_data_layout());
+        Ok(())
+    }
+    /// The machine the code is generated for, the --target or the host
+    fn target_machine(&self, options: &CompileOptions) -> Result<TargetMachine, CompileError> {
         Target::initialize_all(&InitializationConfig::default());
-        let target_triple = TargetMachine::get_default_triple();
-        let cpu = TargetMachine::get_host_cpu_name().to_string();
-        let features = TargetMachine::get_host_cpu_features().to_string();
-        let target = Target::from_triple(&target_triple);
-        if target.is_err(){
-            print_err_and_exit();
+        let target_triple = target_triple(options);
+        // Without a CPU, the code is for any CPU of the target so it runs wherever the
+        // target does and is the same whichever host generated it
+        let (cpu, mut features) = match options.target_cpu.as_deref() {
+            Some("native") if is_host(&target_triple) => (
+                TargetMachine::get_host_cpu_name().to_string(),
+                TargetMachine::get_host_cpu_features().to_string()
+            ),
+            Some("native") => return Err(CompileError::TargetCreation(format!(
+                "the native CPU is the host's, so it can't be used for {}",
+                target_triple.as_str().to_string_lossy()
+            ))),
+            Some(cpu) => (cpu.to_string(), String::new()),
+            None => (String::from("generic"), String::new())
+        };
+        for feature in options.target_features.iter() {
+            if !features.is_empty() {
+                features.push(',');
+            }
+            features.push_str(feature);
         }
-        let target = target.unwrap();
-        let target_machine = target.create_target_machine(
+        let target = Target::from_triple(&target_triple)
+            .map_err(|err| CompileError::TargetCreation(err.to_string()))?;
+        target.create_target_machine(
             &target_triple,
             &cpu,
             &features,
-            OptimizationLevel::Default,
-            RelocMode::Default,
+            // O0 only leaves out the passes over the IR. The machine code is generated
+            // at the default level, as it always has been without -O
+            match options.opt_level {
+                OptLevel::O1 => OptimizationLevel::Less,
+                OptLevel::O0 | OptLevel::O2 | OptLevel::Os => OptimizationLevel::Default,
+                OptLevel::O3 => OptimizationLevel::Aggressive
+            },
+            // A position independent executable needs position independent code
+            if options.pie { RelocMode::PIC } else { RelocMode::Static },
             CodeModel::Default
-        );
-        if target_machine.is_none(){
-            print_err_and_exit();
-        }
-        let target_machine = target_machine.unwrap();
-        let temp_out_filename = format!("{}.tmp", out_filename);
-        let write_to_file_result = target_machine.write_to_file(
-            &self.module,
-            FileType::Object,
-            temp_out_filename.as_ref()
-        );
-        if write_to_file_result.is_err(){
-            print_err_and_exit();
-        }
-        let save_code_result = process::Command::new("gcc")
-            .arg(&temp_out_filename)
-            .arg(format!("-o{}", out_filename))
-            .arg("-no-pie")
-            .output();
-        if save_code_result.is_err(){
-            print_err_and_exit();
+        ).ok_or(CompileError::TargetCreation(format!(
+            "no target machine for {}", target_triple.as_str().to_string_lossy()
+        )))
+    }
+}
+
+/// The triple of the --target, or the host's without one, normalized so the host's
+/// compares equal to itself however it's written
+fn target_triple(options: &CompileOptions) -> TargetTriple {
+    let target_triple = match &options.target {
+        Some(target) => TargetTriple::create(target),
+        None => TargetMachine::get_default_triple()
+    };
+    TargetMachine::normalize_triple(&target_triple)
+}
+
+fn is_host(target_triple: &TargetTriple) -> bool {
+    let host = TargetMachine::normalize_triple(&TargetMachine::get_default_triple());
+    target_triple.as_str() == host.as_str()
+}
+
+/// The triple of the host, the default target
+pub fn host_target() -> String {
+    TargetMachine::get_default_triple().as_str().to_string_lossy().into_owned()
+}
+
+/// The names and descriptions of the targets LLVM was built with
+pub fn targets() -> Vec<(String, String)> {
+    Target::initialize_all(&InitializationConfig::default());
+    let mut targets = vec![];
+    let mut target = Target::get_first();
+    while let Some(curr_target) = target {
+        targets.push((
+            curr_target.get_name().to_string_lossy().into_owned(),
+            curr_target.get_description().to_string_lossy().into_owned()
+        ));
+        target = curr_target.get_next();
+    }
+    targets
+}
+
+/// Links the object file into an executable at the out path, with the --linker. Without
+/// one, objects for the host are linked with gcc and objects for another target with
+/// the cross gcc named after its triple, like aarch64-linux-gnu-gcc
+pub fn link(object: &Path, out: &Path, options: &CompileOptions) -> Result<(), CompileError> {
+    let linker = match (&options.linker, &options.target) {
+        (Some(linker), _) => linker.clone(),
+        (None, Some(target)) if !is_host(&target_triple(options)) => PathBuf::from(format!("{}-gcc", target)),
+        (None, _) => PathBuf::from("gcc")
+    };
+    let flavor = LinkerFlavor::of(&linker);
+    let mut command = process::Command::new(&linker);
+    command.arg(object)
+        .arg("-o")
+        .arg(out);
+    command.args(flavor.executable_args(options.pie, options.static_link));
+    if let (LinkerFlavor::Clang, Some(target)) = (flavor, &options.target) {
+        command.arg(format!("--target={}", target));
+    }
+    if let Some(sysroot) = &options.sysroot {
+        command.arg(format!("--sysroot={}", sysroot.display()));
+    }
+    command.args(options.link_args.iter());
+    match command.output() {
+        Ok(output) if output.status.success() => Ok(()),
+        Ok(output) => {
+            let mut reason = format!(
+                "{} failed with {}\n{}",
+                linker.display(), output.status, String::from_utf8_lossy(&output.stderr).trim()
+            );
+            // The linker itself is most likely missing the runtime it wasn't given
+            if flavor == LinkerFlavor::Ld {
+                reason.push_str(&format!(
+                    "\n{} is given no C runtime, so its start files, -lc and the dynamic linker have to be passed with --link-arg",
+                    linker.display()
+                ));
+            }
+            Err(CompileError::Link(reason))
+        },
+        Err(err) => Err(CompileError::Link(format!("couldn't run {}: {}", linker.display(), err)))
+    }
+}
+
+/// How a linker is told what kind of executable to link
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LinkerFlavor {
+    /// A compiler driver, like gcc or cc, that runs the linker with the C runtime
+    Driver,
+    /// clang, a driver that's told the target with --target
+    Clang,
+    /// The linker itself, like ld or ld.lld
+    Ld
+}
+
+impl LinkerFlavor {
+    fn of(linker: &Path) -> LinkerFlavor {
+        let name = linker.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+        if name == "ld" || name.starts_with("ld.") || name.ends_with("-ld") {
+            LinkerFlavor::Ld
+        } else if name.starts_with("clang") {
+            LinkerFlavor::Clang
+        } else {
+            LinkerFlavor::Driver
         }
-        if fs::remove_file(&temp_out_filename).is_err(){
-            print_err_and_exit();
+    }
+
+    fn executable_args(&self, pie: bool, static_link: bool) -> &'static [&'static str] {
+        match (self, pie, static_link) {
+            (LinkerFlavor::Ld, true, true) => &["-static", "-pie", "--no-dynamic-linker"],
+            (LinkerFlavor::Ld, true, false) => &["-pie"],
+            (LinkerFlavor::Ld, false, true) => &["-static"],
+            (LinkerFlavor::Ld, false, false) => &[],
+            (_, true, true) => &["-static-pie"],
+            (_, true, false) => &["-pie"],
+            (_, false, true) => &["-static", "-no-pie"],
+            (_, false, false) => &["-no-pie"]
         }
     }
 }
@@ -1710,4 +1746,14 @@ mod main_fn_block_names {
     pub const END_IN_FAIL: &'static str = "end_main_fail";
 }
 
-const USER_DEFINED_LABEL_PREFIX: &'static str = "user_defined_label";
\ No newline at end of file
+const USER_DEFINED_LABEL_PREFIX: &'static str = "label.";
+
+/// The prefix of the names coded for the expression at the span, its line and column,
+/// as in l1c5. Expressions from included files have the index of the file first
+fn name_prefix(span: Span) -> String {
+    if span.file == FileId::ROOT {
+        format!("l{}c{}.", span.line, span.column)
+    } else {
+        format!("f{}l{}c{}.", span.file.0, span.line, span.column)
+    }
+}
\ No newline at end of file
@@ -0,0 +1,390 @@
+//! Infers what the expression in each cell holds at every point of the program.
+//!
+//! A cell holds the Death Expression until something is leached onto it, and again
+//! once that expression dies. While an expression lives in it, it holds a number or
+//! one of the rampage primitives: input, output, addition or subtraction. A rampage
+//! primitive is nothing but its index, so the numbers 0 to 3 go on massacres just as
+//! well as the primitives they are the indices of. What's leached is followed
+//! through copies and massacres, and through jumps, so a leach
+//! of the Death Expression onto another cell, or a massacre by a cell without a
+//! rampage primitive, can be found before any code is generated. What goes wrong on
+//! every path is an error, and what only goes wrong on some of them is left to the
+//! lints to warn about. A massacre whose primitive is known has the number of cells
+//! it's given checked against what the primitive takes
+
+use crate::cfg::ControlFlowGraph;
+use crate::dataflow::{self, Analysis};
+use crate::errors::CompileError;
+use crate::lexer::Span;
+use crate::lifetimes::{self, CellLife, Expired, ExpressionLives, Lives, CELLS};
+use crate::parser::{OrganismExpression, Expr, LeachExpression, PassiveExpression, PrimitiveValue};
+
+/// What the living expression in a cell may hold, one bit for each rampage primitive
+/// and one for any other number
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Holds(u8);
+
+impl Holds {
+    pub const NOTHING: Holds = Holds(0);
+    /// A number that isn't the index of a rampage primitive
+    pub const NUMBER: Holds = Holds(1);
+    /// Anything a living expression can hold
+    pub const UNKNOWN: Holds = Holds(0b11111);
+    const FUNCTIONS: [PrimitiveValue; 4] = [
+        PrimitiveValue::Input,
+        PrimitiveValue::Output,
+        PrimitiveValue::Addition,
+        PrimitiveValue::Subtraction
+    ];
+
+    /// What a cell holds once the primitive is leached onto it
+    pub fn primitive(pval: &PrimitiveValue) -> Holds {
+        match pval {
+            PrimitiveValue::Input | PrimitiveValue::Three => Holds(1 << 1),
+            PrimitiveValue::Output | PrimitiveValue::Two => Holds(1 << 2),
+            PrimitiveValue::Addition | PrimitiveValue::One => Holds(1 << 3),
+            PrimitiveValue::Subtraction | PrimitiveValue::Zero => Holds(1 << 4),
+            _ => Holds::NUMBER
+        }
+    }
+    pub fn union(self, other: Holds) -> Holds {
+        Holds(self.0 | other.0)
+    }
+    pub fn may_hold(self, holds: Holds) -> bool {
+        self.0 & holds.0 != 0
+    }
+    pub fn may_be_number(self) -> bool {
+        self.may_hold(Holds::NUMBER)
+    }
+    pub fn may_be_function(self) -> bool {
+        self.0 & !Holds::NUMBER.0 != 0
+    }
+    /// The rampage primitives that may be held
+    pub fn functions(self) -> impl Iterator<Item = PrimitiveValue> {
+        Holds::FUNCTIONS.iter()
+            .filter(move |function| self.may_hold(Holds::primitive(function)))
+            .cloned()
+    }
+    /// The rampage primitive held, if nothing else may be
+    pub fn only_function(self) -> Option<PrimitiveValue> {
+        let mut functions = self.functions();
+        match (self.may_be_number(), functions.next(), functions.next()) {
+            (false, Some(function), None) => Some(function),
+            _ => None
+        }
+    }
+}
+
+/// What each cell may hold, along with how long its expression may have left to live
+#[derive(Debug, Clone, PartialEq)]
+pub struct Contents {
+    pub lives: Lives,
+    holds: [Holds; CELLS]
+}
+
+impl Contents {
+    /// What the living expression in the cell may hold. The Death Expression isn't
+    /// among them, the cell's life tells whether it may hold that
+    pub fn holds(&self, ident: u8) -> Holds {
+        self.holds[ident as usize]
+    }
+    pub fn life(&self, ident: u8) -> &CellLife {
+        &self.lives.cells[ident as usize]
+    }
+}
+
+/// What's leached onto the cells, and what the massacres leave behind
+pub struct CellContents;
+
+impl Analysis for CellContents {
+    type State = Contents;
+
+    /// Every cell starts with the Death Expression and nothing living in it
+    fn entry_state(&self) -> Contents {
+        Contents {
+            lives: ExpressionLives.entry_state(),
+            holds: [Holds::NOTHING; CELLS]
+        }
+    }
+    fn join(&self, state: &mut Contents, other: &Contents) {
+        ExpressionLives.join(&mut state.lives, &other.lives);
+        for (holds, other_holds) in state.holds.iter_mut().zip(other.holds.iter()) {
+            *holds = holds.union(*other_holds);
+        }
+    }
+    fn transfer(&self, state: &mut Contents, expr: &Expr) {
+        if let Expr::Leach(leach_expr) = expr {
+            leach(state, leach_expr);
+        }
+        ExpressionLives.transfer(&mut state.lives, expr);
+        // What a dead expression held doesn't matter any more
+        for (holds, life) in state.holds.iter_mut().zip(state.lives.cells.iter()) {
+            if !life.may_be_alive() {
+                *holds = Holds::NOTHING;
+            }
+        }
+    }
+}
+
+fn leach(state: &mut Contents, leach_expr: &LeachExpression) {
+    let last = leach_expr.onto.last().unwrap().ident as usize;
+    state.holds[last] = match &leach_expr.left {
+        PassiveExpression::Primitive(primitive_expr) => Holds::primitive(&primitive_expr.pval),
+        PassiveExpression::Cell(cell_expr) if leach_expr.is_chain =>
+            massacre_result(state.holds(cell_expr.ident), state.holds[last]),
+        PassiveExpression::Cell(cell_expr) => state.holds(cell_expr.ident)
+    };
+}
+
+/// What the last cell of a massacre holds after it. Output doesn't produce an
+/// expression, so the cell keeps what it held. The numbers the others produce can
+/// be anything, the indices of the rampage primitives included
+fn massacre_result(head: Holds, last: Holds) -> Holds {
+    if head.may_be_number() {
+        return Holds::UNKNOWN;
+    }
+    head.functions().fold(Holds::NOTHING, |result, function| match function {
+        PrimitiveValue::Output => result.union(last),
+        _ => Holds::UNKNOWN
+    })
+}
+
+/// What's wrong with the number of cells a massacre by the rampage primitive is
+/// given, if anything. Input stores into exactly 1 cell and addition adds exactly 2.
+/// Output prints the cells in pairs, and a cell left over on its own, so it takes
+/// as many as it's given, as does subtraction. A cell left over after a pair is
+/// left to the odd-output-operands lint
+fn arity_error(function: &PrimitiveValue, given: usize) -> Option<String> {
+    let cells = if given == 1 { "cell" } else { "cells" };
+    match function {
+        PrimitiveValue::Input if given != 1 =>
+            Some(format!("input given {} operand {}; it takes exactly 1", given, cells)),
+        PrimitiveValue::Addition if given != 2 =>
+            Some(format!("addition given {} operand {}; it takes exactly 2", given, cells)),
+        _ => None
+    }
+}
+
+/// A leach that fails on some of the paths to it, but not on all of them, or that
+/// most likely doesn't do what was meant
+#[derive(Debug, Clone, PartialEq)]
+pub enum MaybeInvalidLeach {
+    /// A cell leached onto another, which may still hold the Death Expression it
+    /// started with
+    DeathExpression(Span),
+    /// The cell a massacre starts with, which may hold a number or the Death
+    /// Expression it started with instead of a rampage primitive
+    NonFunction(Span),
+    /// The last cell of an output massacre given an odd number of cells, more than
+    /// 1, along with the number. It's printed on its own instead of as a digit of
+    /// a pair
+    OddOutput(Span, usize)
+}
+
+impl MaybeInvalidLeach {
+    pub fn span(&self) -> Span {
+        match self {
+            MaybeInvalidLeach::DeathExpression(span) |
+            MaybeInvalidLeach::NonFunction(span) |
+            MaybeInvalidLeach::OddOutput(span, _) => *span
+        }
+    }
+}
+
+/// The leaches that fail on every path to them, as errors, and those that only fail
+/// on some, in the order they are in the program. A cell whose expression may have
+/// died of old age is left to the dead-cell-read lint. The lives of the expressions
+/// are followed along with what they hold, so what's used after it may have died is
+/// returned too, as lifetimes::check would find it. Expressions that can't be
+/// reached aren't checked
+pub fn check(
+    org_expr: &OrganismExpression
+) -> (Vec<CompileError>, Vec<MaybeInvalidLeach>, Vec<Expired>) {
+    let cfg = ControlFlowGraph::new(org_expr);
+    let results = dataflow::solve(&cfg, CellContents);
+    let mut errors = vec![];
+    let mut maybe_invalid = vec![];
+    let mut expired = vec![];
+    results.for_each_expr(|_, expr, contents| {
+        let head = match expr {
+            Expr::Leach(LeachExpression { left: PassiveExpression::Cell(cell_expr), .. }) =>
+                contents.holds(cell_expr.ident).only_function(),
+            _ => None
+        };
+        lifetimes::check_expr(expr, head, &contents.lives, &mut expired);
+        let (leach_expr, cell_expr) = match expr {
+            Expr::Leach(leach_expr) => match &leach_expr.left {
+                PassiveExpression::Cell(cell_expr) => (leach_expr, cell_expr),
+                PassiveExpression::Primitive(_) => return
+            },
+            _ => return
+        };
+        let span = cell_expr.span;
+        let life = contents.life(cell_expr.ident);
+        let holds = contents.holds(cell_expr.ident);
+        // The Death Expression a cell started with, or was left with by a leach, is
+        // an error. Where the expression may have died of old age instead, the
+        // dead-cell-read lint warns about it
+        let death_expression = !life.may_be_alive() && !life.may_have_expired();
+        if leach_expr.is_chain {
+            if death_expression {
+                errors.push(CompileError::NonFunctionMassacre(span, format!("the Death Expression")));
+            } else if !life.may_be_alive() {
+                if life.may_be_unleached() {
+                    maybe_invalid.push(MaybeInvalidLeach::NonFunction(span));
+                }
+            } else if !holds.may_be_function() {
+                errors.push(CompileError::NonFunctionMassacre(span, format!("a number")));
+            } else {
+                if holds.may_be_number() || life.may_be_unleached() {
+                    maybe_invalid.push(MaybeInvalidLeach::NonFunction(span));
+                }
+                // The cells are only checked when it's known what they're given to
+                let given = leach_expr.onto.len();
+                if let Some(description) = holds.only_function().and_then(|function| arity_error(&function, given)) {
+                    let cells_span = leach_expr.onto[0].span.to(leach_expr.onto[given - 1].span);
+                    errors.push(CompileError::MassacreArity(span, cells_span, description));
+                }
+                if holds.only_function() == Some(PrimitiveValue::Output) && given > 1 && given % 2 == 1 {
+                    maybe_invalid.push(MaybeInvalidLeach::OddOutput(leach_expr.onto[given - 1].span, given));
+                }
+            }
+        } else if death_expression {
+            errors.push(CompileError::LeachDeathExpression(span));
+        } else if life.may_be_unleached() {
+            maybe_invalid.push(MaybeInvalidLeach::DeathExpression(span));
+        }
+    });
+    (errors, maybe_invalid, expired)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{parse, DRILLS};
+
+    /// What the cell holds right before the last expression
+    fn holds_at_end(source: &str, ident: u8) -> Holds {
+        let org_expr = parse(source);
+        let cfg = ControlFlowGraph::new(&org_expr);
+        let results = dataflow::solve(&cfg, CellContents);
+        let mut holds = Holds::NOTHING;
+        results.for_each_expr(|_, _, contents| holds = contents.holds(ident));
+        holds
+    }
+
+    #[test]
+    fn test_holds(){
+        let source = format!("->L{}$}}->C~0->L$(->C~10~2->C^^^^^^666^^^^^^=O", DRILLS);
+        assert_eq!(holds_at_end(&source, 2).only_function(), Some(PrimitiveValue::Addition));
+        assert_eq!(holds_at_end(&source, 1), Holds::NUMBER);
+        // Cell 0 is copied away
+        assert_eq!(holds_at_end(&source, 0), Holds::NOTHING);
+        // 2 is the index of output
+        let source = format!("->L{}$@->C~0->C^^^^^^666^^^^^^=O", DRILLS);
+        assert_eq!(holds_at_end(&source, 0).only_function(), Some(PrimitiveValue::Output));
+        // The sum could be any number
+        let source = format!("->L{}$}}->C~0->L$(->C~10~1^^^^^^666^^^^^^=M->C^^^^^^666^^^^^^=O", DRILLS);
+        assert_eq!(holds_at_end(&source, 1), Holds::UNKNOWN);
+    }
+
+    #[test]
+    fn test_errors(){
+        assert_eq!(
+            check(&parse(r"0~1~2^^^^^^666^^^^^^=M^^^^^^666^^^^^^=O")).0,
+            vec![CompileError::NonFunctionMassacre(Span::new(0, 1, 1, 1), format!("the Death Expression"))]
+        );
+        assert_eq!(
+            check(&parse(r"->L\\|//\\|//\\|//$(->C~00~1^^^^^^666^^^^^^=M^^^^^^666^^^^^^=O")).0,
+            vec![CompileError::NonFunctionMassacre(Span::new(25, 26, 1, 26), format!("a number"))]
+        );
+        assert_eq!(
+            check(&parse(r"->L\\|//\\|//\\|//$@->C~00~10~2^^^^^^666^^^^^^=O")).0,
+            vec![CompileError::LeachDeathExpression(Span::new(28, 29, 1, 29))]
+        );
+    }
+
+    #[test]
+    fn test_arity(){
+        let arity_errors = |head: &str, cells: &str| -> Vec<String> {
+            let source = format!("->L{}${}->C~00{}^^^^^^666^^^^^^=M^^^^^^666^^^^^^=O", DRILLS, head, cells);
+            check(&parse(&source)).0.into_iter()
+                .map(|err| match err {
+                    CompileError::MassacreArity(_, _, description) => description,
+                    err => panic!("expected an arity error, got {:?}", err)
+                })
+                .collect()
+        };
+        assert_eq!(arity_errors("<>", "~1"), Vec::<String>::new());
+        assert_eq!(arity_errors("<>", "~1~2"), vec![format!("input given 2 operand cells; it takes exactly 1")]);
+        assert_eq!(arity_errors("}", "~1~2"), Vec::<String>::new());
+        assert_eq!(arity_errors("}", "~1"), vec![format!("addition given 1 operand cell; it takes exactly 2")]);
+        // The cell left over is printed on its own, which the lint warns about
+        assert_eq!(arity_errors("><", "~1~2~3"), Vec::<String>::new());
+        let odd_output = |cells: &str| check(&parse(&format!(
+            "->L{}$><->C~00{}^^^^^^666^^^^^^=M^^^^^^666^^^^^^=O", DRILLS, cells
+        ))).1;
+        assert_eq!(odd_output("~1~2~3"), vec![MaybeInvalidLeach::OddOutput(Span::new(32, 33, 1, 33), 3)]);
+        assert_eq!(odd_output("~1~2"), vec![]);
+        assert_eq!(odd_output("~1"), vec![]);
+        assert_eq!(arity_errors("{", "~1~2~3"), Vec::<String>::new());
+        // 3 is the index of input
+        assert_eq!(arity_errors("#", "~1~2").len(), 1);
+        let (errors, ..) = check(&parse(&format!("->L{}$}}->C~00~1~2~3^^^^^^666^^^^^^=M^^^^^^666^^^^^^=O", DRILLS)));
+        assert_eq!(errors[0].span(), Some(Span::new(27, 32, 1, 28)));
+    }
+
+    #[test]
+    fn test_some_paths(){
+        // Cell 0 has nothing leached onto it the first time around the loop
+        let source = r"label:x:0~1->L\\|//\\|//\\|//$!->C~0ijmp:x:^^^^^^666^^^^^^=O";
+        assert_eq!(check(&parse(source)), (vec![], vec![MaybeInvalidLeach::DeathExpression(Span::new(8, 9, 1, 9))], vec![]));
+        // Cell 0 holds a number when the jump is taken
+        let source = r"->L\\|//\\|//\\|//$><->C~0ijmp:x:->L$(->C~0label:x:0~1^^^^^^666^^^^^^=M^^^^^^666^^^^^^=O";
+        assert_eq!(check(&parse(source)), (vec![], vec![MaybeInvalidLeach::NonFunction(Span::new(51, 52, 1, 52))], vec![]));
+    }
+
+    #[test]
+    fn test_old_age(){
+        // Cell 0 dies of old age on every path before it's read, which is left to the
+        // dead-cell-read lint, as it would be on some of them
+        let source = format!("->L{}$!->C~0->L->C->L->C->L->C0~1^^^^^^666^^^^^^=O", DRILLS);
+        let (errors, maybe_invalid, expired) = check(&parse(&source));
+        assert_eq!((errors, maybe_invalid), (vec![], vec![]));
+        assert!(matches!(expired.as_slice(), [lifetimes::Expired::Cell { always: true, .. }]));
+        let source = format!("->L{}$}}->C~0->L->C->L->C->L->C0~1~2^^^^^^666^^^^^^=M^^^^^^666^^^^^^=O", DRILLS);
+        let (errors, maybe_invalid, _) = check(&parse(&source));
+        assert_eq!((errors, maybe_invalid), (vec![], vec![]));
+        // Leached away, it holds the Death Expression
+        let source = format!("->L{}$!->C~00~10~2^^^^^^666^^^^^^=O", DRILLS);
+        assert_eq!(check(&parse(&source)).0, vec![CompileError::LeachDeathExpression(Span::new(28, 29, 1, 29))]);
+    }
+
+    #[test]
+    fn test_expired(){
+        // The lives followed along with the contents are the ones lifetimes::check
+        // solves for on its own
+        let sources = [
+            format!("->L{}$!->C~0->L->C->L->C->L->C0~1^^^^^^666^^^^^^=O", DRILLS),
+            format!("->L{}$!->C~0->L->C->L$@->C~1^^^^^^666^^^^^^=O", DRILLS),
+            format!("->L{}label:x:$!->C~0->Lijmp:x:^^^^^^666^^^^^^=O", DRILLS)
+        ];
+        for source in sources.iter() {
+            let org_expr = parse(source);
+            let (_, _, expired) = check(&org_expr);
+            assert!(!expired.is_empty(), "{}", source);
+            assert_eq!(expired, lifetimes::check(&org_expr), "{}", source);
+        }
+        // Cell 1 is dead when it's given to output, and a massacre that isn't known
+        // to be input reads its one cell too
+        let one_cell = |head: &str| format!(
+            "->L{}${}->C~0->L$!->C~11~20~1^^^^^^666^^^^^^=M^^^^^^666^^^^^^=O", DRILLS, head
+        );
+        let (_, _, expired) = check(&parse(&one_cell("><")));
+        assert!(matches!(expired.as_slice(), [Expired::Cell { always: true, .. }]), "{:?}", expired);
+        assert_eq!(expired, lifetimes::check(&parse(&one_cell("><"))));
+        // Input stores into it instead
+        let (_, _, expired) = check(&parse(&one_cell("<>")));
+        assert_eq!(expired, vec![]);
+    }
+}
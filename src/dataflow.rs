@@ -0,0 +1,144 @@
+//! Forward dataflow over the control-flow graph.
+//!
+//! An analysis says what state the program starts in, how an expression changes the
+//! state and how the states of two paths meeting at a block are merged. Solving it
+//! gives the state at the start of every block, merged over all the paths that reach
+//! it, loops included, so checks can be made against every way an expression can be
+//! reached instead of just the expressions written before it
+
+use std::collections::VecDeque;
+use crate::cfg::{BlockId, ControlFlowGraph};
+use crate::parser::Expr;
+
+pub trait Analysis {
+    /// What is known at a point in the program. Merging states must only ever lose
+    /// what is known, so that solving ends
+    type State: Clone + PartialEq;
+
+    /// The state at the start of the program
+    fn entry_state(&self) -> Self::State;
+    /// Merges the state of another path into the state
+    fn join(&self, state: &mut Self::State, other: &Self::State);
+    /// Carries the state past the expression
+    fn transfer(&self, state: &mut Self::State, expr: &Expr);
+}
+
+/// The states an analysis has at the start of each block
+pub struct Results<'c, A: Analysis> {
+    pub analysis: A,
+    cfg: &'c ControlFlowGraph<'c>,
+    /// None for blocks that can't be reached
+    entry_states: Vec<Option<A::State>>
+}
+
+impl<'c, A: Analysis> Results<'c, A> {
+    /// The state at the start of the block, if it can be reached
+    pub fn entry_state(&self, block: BlockId) -> Option<&A::State> {
+        self.entry_states[block].as_ref()
+    }
+    /// The state at the end of the block, if it can be reached
+    pub fn exit_state(&self, block: BlockId) -> Option<A::State> {
+        let mut state = self.entry_state(block)?.clone();
+        for expr in self.cfg.exprs(block) {
+            self.analysis.transfer(&mut state, expr);
+        }
+        Some(state)
+    }
+    /// Calls f with every expression that can be reached, in the order they are in
+    /// the program, along with its index and the state right before it
+    pub fn for_each_expr<F>(&self, mut f: F)
+    where
+        F: FnMut(usize, &'c Expr, &A::State)
+    {
+        for (block, basic_block) in self.cfg.blocks().iter().enumerate() {
+            let mut state = match self.entry_state(block) {
+                Some(state) => state.clone(),
+                None => continue
+            };
+            for (expr, i) in self.cfg.exprs(block).iter().zip(basic_block.exprs.clone()) {
+                f(i, expr, &state);
+                self.analysis.transfer(&mut state, expr);
+            }
+        }
+    }
+}
+
+/// Solves the analysis over the graph, going over the blocks until no block's state
+/// changes
+pub fn solve<'c, A: Analysis>(cfg: &'c ControlFlowGraph<'c>, analysis: A) -> Results<'c, A> {
+    let count = cfg.blocks().len();
+    let mut entry_states: Vec<Option<A::State>> = vec![None; count];
+    entry_states[cfg.entry()] = Some(analysis.entry_state());
+    let mut queued = vec![false; count];
+    let mut queue = VecDeque::from(vec![cfg.entry()]);
+    queued[cfg.entry()] = true;
+    while let Some(block) = queue.pop_front() {
+        queued[block] = false;
+        let mut state = entry_states[block].clone().unwrap();
+        for expr in cfg.exprs(block) {
+            analysis.transfer(&mut state, expr);
+        }
+        for &succ in cfg.successors(block) {
+            let changed = match &mut entry_states[succ] {
+                Some(succ_state) => {
+                    let before = succ_state.clone();
+                    analysis.join(succ_state, &state);
+                    *succ_state != before
+                }
+                empty => {
+                    *empty = Some(state.clone());
+                    true
+                }
+            };
+            if changed && !queued[succ] {
+                queued[succ] = true;
+                queue.push_back(succ);
+            }
+        }
+    }
+    Results {
+        analysis,
+        cfg,
+        entry_states
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::parse;
+
+    /// The most drills any path has gone through, up to 10
+    struct MostDrills;
+
+    impl Analysis for MostDrills {
+        type State = usize;
+
+        fn entry_state(&self) -> usize {
+            0
+        }
+        fn join(&self, state: &mut usize, other: &usize) {
+            *state = (*state).max(*other);
+        }
+        fn transfer(&self, state: &mut usize, expr: &Expr) {
+            if let Expr::Drill(_) = expr {
+                *state = (*state + 1).min(10);
+            }
+        }
+    }
+
+    #[test]
+    fn test_solve(){
+        // The loop keeps adding drills until the most there can be, and the block
+        // after the unconditional jump is never reached
+        let org_expr = parse(r"->L\\|//label:x:\\|//ijmp:x:\\|//jmp:y:\\|//label:y:^^^^^^666^^^^^^=O");
+        let cfg = ControlFlowGraph::new(&org_expr);
+        let results = solve(&cfg, MostDrills);
+        assert_eq!(results.entry_state(1), Some(&10));
+        assert_eq!(results.exit_state(2), Some(10));
+        assert_eq!(results.entry_state(3), None);
+        let mut seen = vec![];
+        results.for_each_expr(|i, _, &state| seen.push((i, state)));
+        assert_eq!(seen, vec![(0, 0), (1, 0), (2, 10), (3, 10), (4, 10), (5, 10), (6, 10), (8, 10)]);
+    }
+}
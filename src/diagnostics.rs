@@ -0,0 +1,433 @@
+use std::fmt;
+use crate::lexer::Span;
+use crate::source_map::SourceMap;
+
+/// How many chars of a line are shown around the labels in a snippet.
+/// Mindbend programs are a single line, so the whole line can't just be printed
+const WINDOW_WIDTH: usize = 80;
+/// How many chars to show before the first label in a window
+const WINDOW_LEAD: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LabelStyle {
+    Primary,
+    Secondary
+}
+
+/// A message pointing at a part of the source
+#[derive(Debug, Clone, PartialEq)]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+    pub style: LabelStyle
+}
+
+impl Label {
+    pub fn primary(span: Span, message: String) -> Label {
+        Label {
+            span,
+            message,
+            style: LabelStyle::Primary
+        }
+    }
+    pub fn secondary(span: Span, message: String) -> Label {
+        Label {
+            span,
+            message,
+            style: LabelStyle::Secondary
+        }
+    }
+}
+
+/// A change to the source that would fix the problem
+#[derive(Debug, Clone, PartialEq)]
+pub struct Suggestion {
+    pub message: String,
+    /// The part of the source to replace. Empty for an insertion
+    pub span: Span,
+    pub replacement: String
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning")
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    /// An error code, like MB0013, or the name of the lint that found the problem
+    pub code: Option<&'static str>,
+    pub labels: Vec<Label>,
+    pub notes: Vec<String>,
+    pub suggestion: Option<Suggestion>
+}
+
+impl Diagnostic {
+    pub fn new(message: String) -> Diagnostic {
+        Diagnostic {
+            severity: Severity::Error,
+            message,
+            code: None,
+            labels: vec![],
+            notes: vec![],
+            suggestion: None
+        }
+    }
+    pub fn with_severity(mut self, severity: Severity) -> Diagnostic {
+        self.severity = severity;
+        self
+    }
+    pub fn with_code(mut self, code: &'static str) -> Diagnostic {
+        self.code = Some(code);
+        self
+    }
+    pub fn with_label(mut self, label: Label) -> Diagnostic {
+        self.labels.push(label);
+        self
+    }
+    pub fn with_note(mut self, note: String) -> Diagnostic {
+        self.notes.push(note);
+        self
+    }
+    pub fn with_suggestion(mut self, message: String, span: Span, replacement: String) -> Diagnostic {
+        self.suggestion = Some(Suggestion {
+            message,
+            span,
+            replacement
+        });
+        self
+    }
+    pub fn primary_span(&self) -> Option<Span> {
+        self.labels.iter()
+            .find(|label| label.style == LabelStyle::Primary)
+            .map(|label| label.span)
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+/// Renders the diagnostic with the parts of the source its labels point at, like so:
+///
+/// ```text
+/// error[MB0016]: Label name at line 1, column 1 duplicated in the label name at line 1, column 17
+///  --> prog.mb:1:17
+///   |
+/// 1 | label:x:label:y:label:x:^^^^^^666^^^^^^=O
+///   | --------
+///   | first defined here
+///   |                 ^^^^^^^^ label `x` defined again here
+/// ```
+///
+/// followed by the notes, if there are any
+pub fn render(diagnostic: &Diagnostic, filename: &str, source: &str) -> String {
+    render_in(diagnostic, &SourceMap::from_source(filename, source))
+}
+
+/// Renders the diagnostic like render, taking the source of each label from the file
+/// of the source map it's in. Labels in files other than the primary label's are shown
+/// after it, each file under a `:::` line
+pub fn render_in(diagnostic: &Diagnostic, source_map: &SourceMap) -> String {
+    let mut out = match diagnostic.code {
+        Some(code) => format!("{}[{}]: {}\n", diagnostic.severity, code, diagnostic.message),
+        None => format!("{}: {}\n", diagnostic.severity, diagnostic.message)
+    };
+    let gutter = gutter_width(diagnostic);
+    if let Some(primary_span) = diagnostic.primary_span() {
+        let mut files = vec![primary_span.file];
+        for label in diagnostic.labels.iter() {
+            if !files.contains(&label.span.file) {
+                files.push(label.span.file);
+            }
+        }
+        for file in files {
+            let mut labels: Vec<&Label> = diagnostic.labels.iter()
+                .filter(|label| label.span.file == file)
+                .collect();
+            labels.sort_by_key(|label| (label.span.line, label.span.column));
+            let (arrow, location) = if file == primary_span.file {
+                ("-->", primary_span)
+            } else {
+                (":::", labels[0].span)
+            };
+            out.push_str(&format!(
+                "{}{} {}:{}:{}\n",
+                " ".repeat(gutter), arrow, source_map.name(file), location.line, location.column
+            ));
+            render_snippet(&mut out, &labels, gutter, &source_map.source(file));
+        }
+    }
+    for note in diagnostic.notes.iter() {
+        out.push_str(&format!("{} = note: {}\n", " ".repeat(gutter), note));
+    }
+    out
+}
+
+/// Renders the lines the labels, which must be sorted by position, point at
+fn render_snippet(out: &mut String, labels: &[&Label], gutter: usize, source: &str){
+    let lines: Vec<&str> = source.lines().collect();
+    out.push_str(&format!("{} |\n", " ".repeat(gutter)));
+    let mut prev_line: Option<usize> = None;
+    for window in windows(labels, &lines) {
+        if let Some(prev_line) = prev_line {
+            if window.line != prev_line {
+                out.push_str(&format!("{} |\n", " ".repeat(gutter)));
+            } else {
+                out.push_str(&format!("{} ...\n", " ".repeat(gutter)));
+            }
+        }
+        render_window(out, &window, gutter);
+        prev_line = Some(window.line);
+    }
+}
+
+/// A part of a line of the source and the labels that fall in it
+struct Window<'a> {
+    line: usize,
+    text: String,
+    // The column of the line the window starts from
+    start_column: usize,
+    truncated_start: bool,
+    truncated_end: bool,
+    labels: Vec<(&'a Label, usize, usize)>
+}
+
+fn gutter_width(diagnostic: &Diagnostic) -> usize {
+    let max_line = diagnostic.labels.iter()
+        .map(|label| label.span.line)
+        .max()
+        .unwrap_or(1);
+    format!("{}", max_line).len()
+}
+
+/// Groups the labels, which must be sorted by position, into windows of at most
+/// WINDOW_WIDTH chars
+fn windows<'a>(labels: &[&'a Label], lines: &[&str]) -> Vec<Window<'a>> {
+    let mut windows: Vec<Window<'a>> = vec![];
+    for label in labels {
+        let line_text: Vec<char> = lines.get(label.span.line - 1)
+            .map(|line| line.chars().collect())
+            .unwrap_or(vec![]);
+        // Columns are counted in chars, but spans are in bytes
+        let start_column = label.span.column;
+        let len = match lines.get(label.span.line - 1) {
+            Some(_) => label_len(label, &line_text),
+            None => 1
+        };
+        let end_column = start_column + len;
+        if let Some(window) = windows.last_mut() {
+            let window_end = window.start_column + WINDOW_WIDTH;
+            if window.line == label.span.line && end_column <= window_end {
+                window.labels.push((label, start_column, len));
+                continue;
+            }
+        }
+        let window_start = if start_column > WINDOW_LEAD { start_column - WINDOW_LEAD } else { 1 };
+        let window_end = (window_start + WINDOW_WIDTH).min(line_text.len() + 1);
+        let text: String = line_text.iter()
+            .skip(window_start - 1)
+            .take(window_end - window_start)
+            .collect();
+        windows.push(Window {
+            line: label.span.line,
+            text,
+            start_column: window_start,
+            truncated_start: window_start > 1,
+            truncated_end: window_end <= line_text.len(),
+            labels: vec![(label, start_column, len)]
+        });
+    }
+    windows
+}
+
+/// The number of chars the label underlines. Empty spans still get a single caret
+fn label_len(label: &Label, line_text: &[char]) -> usize {
+    let available = (line_text.len() + 1).saturating_sub(label.span.column - 1).max(1);
+    let len = if label.span.len() == 0 {
+        1
+    } else {
+        // Mindbend tokens are ascii, except the names in labels and jumps
+        let chars_in_span = line_text.iter()
+            .skip(label.span.column - 1)
+            .scan(0, |bytes, c| {
+                if *bytes >= label.span.len() {
+                    None
+                } else {
+                    *bytes += c.len_utf8();
+                    Some(c)
+                }
+            })
+            .count();
+        chars_in_span.max(1)
+    };
+    len.min(available)
+}
+
+fn render_window(out: &mut String, window: &Window, gutter: usize) {
+    let prefix = if window.truncated_start { "..." } else { "" };
+    let suffix = if window.truncated_end { "..." } else { "" };
+    out.push_str(&format!(
+        "{:>width$} | {}{}{}\n",
+        window.line, prefix, window.text, suffix, width = gutter
+    ));
+    let last = window.labels.len() - 1;
+    for (i, (label, start_column, len)) in window.labels.iter().enumerate() {
+        let marker = match label.style {
+            LabelStyle::Primary => "^",
+            LabelStyle::Secondary => "-"
+        };
+        let indent = " ".repeat(prefix.len() + start_column - window.start_column);
+        let underline = marker.repeat(*len);
+        // Only the last label's message can go on the same line as its underline
+        // without hiding the underlines that come after it
+        if i == last || label.message.is_empty() {
+            out.push_str(&format!(
+                "{} | {}{} {}\n", " ".repeat(gutter), indent, underline, label.message
+            ).trim_end());
+            out.push('\n');
+        } else {
+            out.push_str(&format!("{} | {}{}\n", " ".repeat(gutter), indent, underline));
+            out.push_str(&format!("{} | {}{}\n", " ".repeat(gutter), indent, label.message));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source_map::FileId;
+    #[test]
+    fn test_render_primary(){
+        let source = "jmp:x:^^^^^^666^^^^^^=O";
+        let diagnostic = Diagnostic::new(format!("Attempt to jump to non existent label"))
+            .with_label(Label::primary(Span::new(0, 6, 1, 1), format!("no label named `x`")));
+        let expected = "\
+error: Attempt to jump to non existent label
+ --> prog.mb:1:1
+  |
+1 | jmp:x:^^^^^^666^^^^^^=O
+  | ^^^^^^ no label named `x`
+";
+        assert_eq!(render(&diagnostic, "prog.mb", source), expected);
+    }
+    #[test]
+    fn test_render_secondary(){
+        let source = "label:x:label:y:label:x:^^^^^^666^^^^^^=O";
+        let diagnostic = Diagnostic::new(format!("Duplicate label"))
+            .with_label(Label::primary(Span::new(16, 24, 1, 17), format!("label `x` defined again here")))
+            .with_label(Label::secondary(Span::new(0, 8, 1, 1), format!("first defined here")));
+        let expected = "\
+error: Duplicate label
+ --> prog.mb:1:17
+  |
+1 | label:x:label:y:label:x:^^^^^^666^^^^^^=O
+  | --------
+  | first defined here
+  |                 ^^^^^^^^ label `x` defined again here
+";
+        assert_eq!(render(&diagnostic, "prog.mb", source), expected);
+    }
+    #[test]
+    fn test_render_windowed(){
+        let source = format!("{}$E{}", "0".repeat(100), "1".repeat(100));
+        let diagnostic = Diagnostic::new(format!("Invalid primitive"))
+            .with_label(Label::primary(Span::new(101, 102, 1, 102), format!("not a primitive")));
+        let expected = format!("\
+error: Invalid primitive
+ --> prog.mb:1:102
+  |
+1 | ...{}$E{}...
+  |                    ^ not a primitive
+", "0".repeat(15), "1".repeat(63));
+        assert_eq!(render(&diagnostic, "prog.mb", &source), expected);
+    }
+    #[test]
+    fn test_render_far_apart_labels(){
+        let source = format!("label:x:{}label:x:", "0".repeat(200));
+        let diagnostic = Diagnostic::new(format!("Duplicate label"))
+            .with_label(Label::primary(Span::new(208, 216, 1, 209), format!("defined again here")))
+            .with_label(Label::secondary(Span::new(0, 8, 1, 1), format!("first defined here")));
+        let expected = format!("\
+error: Duplicate label
+ --> prog.mb:1:209
+  |
+1 | label:x:{}...
+  | -------- first defined here
+  ...
+1 | ...{}label:x:
+  |                    ^^^^^^^^ defined again here
+", "0".repeat(72), "0".repeat(16));
+        assert_eq!(render(&diagnostic, "prog.mb", &source), expected);
+    }
+    #[test]
+    fn test_render_with_code(){
+        let diagnostic = Diagnostic::new(format!("Attempt to drill in the Cells Region"))
+            .with_code("MB0013")
+            .with_label(Label::primary(Span::new(0, 5, 1, 1), format!("not here")));
+        let expected = "\
+error[MB0013]: Attempt to drill in the Cells Region
+ --> prog.mb:1:1
+  |
+1 | \\\\|//^^^^^^666^^^^^^=O
+  | ^^^^^ not here
+";
+        assert_eq!(render(&diagnostic, "prog.mb", "\\\\|//^^^^^^666^^^^^^=O"), expected);
+    }
+    #[test]
+    fn test_render_warning_with_note(){
+        let diagnostic = Diagnostic::new(format!("Cell expression has no effect"))
+            .with_severity(Severity::Warning)
+            .with_code("lone-cell-expression")
+            .with_label(Label::primary(Span::new(0, 1, 1, 1), format!("does nothing")))
+            .with_note(format!("`-W lone-cell-expression` is on by default"));
+        let expected = "\
+warning[lone-cell-expression]: Cell expression has no effect
+ --> prog.mb:1:1
+  |
+1 | 0^^^^^^666^^^^^^=O
+  | ^ does nothing
+  = note: `-W lone-cell-expression` is on by default
+";
+        assert_eq!(render(&diagnostic, "prog.mb", "0^^^^^^666^^^^^^=O"), expected);
+    }
+    #[test]
+    fn test_render_without_labels(){
+        let diagnostic = Diagnostic::new(format!("Something went wrong"));
+        assert_eq!(render(&diagnostic, "prog.mb", ""), "error: Something went wrong\n");
+    }
+    #[test]
+    fn test_render_in_other_file(){
+        let mut source_map = SourceMap::from_source("prog.mb", "label:x:include:lib.mb:^^^^^^666^^^^^^=O");
+        let lib = source_map.add_source("lib.mb", format!("0~1label:x:"));
+        let diagnostic = Diagnostic::new(format!("Duplicate label"))
+            .with_label(Label::primary(Span::new(3, 11, 1, 4).in_file(lib), format!("defined again here")))
+            .with_label(Label::secondary(Span::new(0, 8, 1, 1).in_file(FileId::ROOT), format!("first defined here")));
+        let expected = "\
+error: Duplicate label
+ --> lib.mb:1:4
+  |
+1 | 0~1label:x:
+  |    ^^^^^^^^ defined again here
+ ::: prog.mb:1:1
+  |
+1 | label:x:include:lib.mb:^^^^^^666^^^^^^=O
+  | -------- first defined here
+";
+        assert_eq!(render_in(&diagnostic, &source_map), expected);
+    }
+}
@@ -0,0 +1,199 @@
+//! JSON dumps of the tokens and the organism expression, for tools outside Rust
+//! that want the compiler's view of a program.
+//!
+//! Every dump is an object with the version of its format, which goes up whenever
+//! a field is changed or taken out, so tools can tell what they are reading
+
+use serde_json::{json, Value};
+use crate::lexer::{Span, Token, TokenKind};
+use crate::parser::{
+    Expr, PassiveExpression, CellExpression, PrimitiveExpression, PrimitiveValue,
+    RegionExpression, Region
+};
+use crate::source_map::SourceMap;
+use crate::Program;
+
+pub const FORMAT_VERSION: u32 = 1;
+
+/// The tokens as `{"version": 1, "tokens": [...]}`
+pub fn tokens_to_json(tokens: &[Token], source_map: &SourceMap) -> Value {
+    let tokens: Vec<Value> = tokens.iter().map(|token| token_to_json(token, source_map)).collect();
+    json!({
+        "version": FORMAT_VERSION,
+        "tokens": tokens
+    })
+}
+
+/// A token as `{"kind": ..., "span": ...}`, with the fields of the kind, if it has any
+pub fn token_to_json(token: &Token, source_map: &SourceMap) -> Value {
+    let mut value = match &token.kind {
+        TokenKind::TripleSix => json!({"kind": "triple_six"}),
+        TokenKind::TripleSixEq => json!({"kind": "triple_six_eq"}),
+        TokenKind::TripleSixEqM => json!({"kind": "massacre"}),
+        TokenKind::TripleSixEqO => json!({"kind": "death"}),
+        TokenKind::Tilde => json!({"kind": "tilde"}),
+        TokenKind::Drill => json!({"kind": "drill"}),
+        TokenKind::Label(label) => json!({"kind": "label", "label": label}),
+        TokenKind::Jump(label) => json!({"kind": "jump", "label": label}),
+        TokenKind::ConditionalJump(label) => json!({"kind": "conditional_jump", "label": label}),
+        TokenKind::Include(path) => json!({"kind": "include", "path": path}),
+        TokenKind::Macro(name, params) => json!({"kind": "macro", "name": name, "params": params}),
+        TokenKind::EndMacro => json!({"kind": "end_macro"}),
+        TokenKind::Call(name) => json!({"kind": "call", "name": name}),
+        TokenKind::Placeholder(param) => json!({"kind": "placeholder", "param": param}),
+        TokenKind::CellIdent(ident) => json!({"kind": "cell", "ident": ident}),
+        TokenKind::RegionIdent(ident) => json!({"kind": "region", "ident": ident}),
+        TokenKind::PrimitiveIdent(ident) => json!({"kind": "primitive", "ident": ident})
+    };
+    value["span"] = span_to_json(token.span, source_map);
+    value
+}
+
+/// The program as `{"version": 1, "labels": [...], "exprs": [...]}`
+pub fn program_to_json(program: &Program, source_map: &SourceMap) -> Value {
+    let exprs: Vec<Value> = program.ast.exprs.iter().map(|expr| expr_to_json(expr, source_map)).collect();
+    json!({
+        "version": FORMAT_VERSION,
+        "labels": program.labels,
+        "exprs": exprs
+    })
+}
+
+/// An expression as `{"kind": ..., "span": ...}`, with the fields of the kind
+pub fn expr_to_json(expr: &Expr, source_map: &SourceMap) -> Value {
+    let mut value = match expr {
+        Expr::Leach(leach_expr) => {
+            let left = match leach_expr.left() {
+                PassiveExpression::Cell(cell_expr) => cell_to_json(cell_expr, source_map),
+                PassiveExpression::Primitive(primitive_expr) => primitive_to_json(primitive_expr, source_map)
+            };
+            let onto: Vec<Value> = leach_expr.onto().iter()
+                .map(|cell_expr| cell_to_json(cell_expr, source_map))
+                .collect();
+            let region_changes: Vec<Value> = leach_expr.region_change.iter()
+                .flatten()
+                .map(|region_expr| region_to_json(region_expr, source_map))
+                .collect();
+            json!({
+                "kind": "leach",
+                "left": left,
+                "onto": onto,
+                "is_chain": leach_expr.is_chain,
+                "region_changes": region_changes
+            })
+        }
+        Expr::Region(region_expr) => return region_to_json(region_expr, source_map),
+        Expr::Drill(_) => json!({"kind": "drill"}),
+        Expr::Label(label_expr) => json!({"kind": "label", "label": label_expr.label}),
+        Expr::Jump { to, conditional, .. } => json!({"kind": "jump", "to": to, "conditional": conditional}),
+        Expr::Cell(cell_expr) => return cell_to_json(cell_expr, source_map),
+        Expr::Primitive(primitive_expr) => return primitive_to_json(primitive_expr, source_map)
+    };
+    value["span"] = span_to_json(expr.span(), source_map);
+    value
+}
+
+fn cell_to_json(cell_expr: &CellExpression, source_map: &SourceMap) -> Value {
+    json!({
+        "kind": "cell",
+        "ident": cell_expr.ident,
+        "span": span_to_json(cell_expr.span, source_map)
+    })
+}
+
+fn primitive_to_json(primitive_expr: &PrimitiveExpression, source_map: &SourceMap) -> Value {
+    json!({
+        "kind": "primitive",
+        "value": primitive_value_name(&primitive_expr.pval),
+        "span": span_to_json(primitive_expr.span, source_map)
+    })
+}
+
+fn region_to_json(region_expr: &RegionExpression, source_map: &SourceMap) -> Value {
+    let to = match region_expr.to {
+        Region::Cells => "cells",
+        Region::Layers => "layers"
+    };
+    json!({
+        "kind": "region",
+        "to": to,
+        "span": span_to_json(region_expr.span, source_map)
+    })
+}
+
+fn primitive_value_name(pval: &PrimitiveValue) -> &'static str {
+    match pval {
+        PrimitiveValue::One => "one",
+        PrimitiveValue::Two => "two",
+        PrimitiveValue::Three => "three",
+        PrimitiveValue::Four => "four",
+        PrimitiveValue::Five => "five",
+        PrimitiveValue::Six => "six",
+        PrimitiveValue::Seven => "seven",
+        PrimitiveValue::Eight => "eight",
+        PrimitiveValue::Nine => "nine",
+        PrimitiveValue::Zero => "zero",
+        PrimitiveValue::Input => "input",
+        PrimitiveValue::Output => "output",
+        PrimitiveValue::Addition => "addition",
+        PrimitiveValue::Subtraction => "subtraction"
+    }
+}
+
+/// The span as it is, without reading the file it's in. Tokens never go past the
+/// end of a line, so the line and column are of the start
+fn span_to_json(span: Span, source_map: &SourceMap) -> Value {
+    json!({
+        "file": source_map.name(span.file),
+        "start": span.start,
+        "end": span.end,
+        "line": span.line,
+        "column": span.column
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::tokenize;
+    use crate::parser::Parser;
+
+    #[test]
+    fn test_tokens_to_json(){
+        let source_map = SourceMap::from_source("prog.mb", "");
+        let tokens = tokenize(r"->L$><label:x:^^^^^^666^^^^^^=O").unwrap();
+        assert_eq!(tokens_to_json(&tokens, &source_map), json!({
+            "version": 1,
+            "tokens": [
+                {"kind": "region", "ident": "L", "span": {"file": "prog.mb", "start": 0, "end": 3, "line": 1, "column": 1}},
+                {"kind": "primitive", "ident": "><", "span": {"file": "prog.mb", "start": 3, "end": 6, "line": 1, "column": 4}},
+                {"kind": "label", "label": "x", "span": {"file": "prog.mb", "start": 6, "end": 14, "line": 1, "column": 7}}
+            ]
+        }));
+    }
+
+    #[test]
+    fn test_program_to_json(){
+        let source_map = SourceMap::from_source("prog.mb", "");
+        let source = r"->L\\|//\\|//\\|//$5->C~Alabel:x:0~1~2^^^^^^666^^^^^^=Mijmp:x:^^^^^^666^^^^^^=O";
+        let (ast, labels) = Parser::new(tokenize(source).unwrap()).parse().unwrap();
+        let value = program_to_json(&Program { ast, labels, findings: Default::default() }, &source_map);
+        assert_eq!(value["version"], json!(1));
+        assert_eq!(value["labels"], json!(["x"]));
+        let exprs = value["exprs"].as_array().unwrap();
+        assert_eq!(exprs.len(), 8);
+        assert_eq!(exprs[0], json!({
+            "kind": "region", "to": "layers",
+            "span": {"file": "prog.mb", "start": 0, "end": 3, "line": 1, "column": 1}
+        }));
+        assert_eq!(exprs[4]["left"]["value"], json!("nine"));
+        assert_eq!(exprs[4]["onto"][0]["ident"], json!(10));
+        assert_eq!(exprs[4]["region_changes"][0]["to"], json!("cells"));
+        assert_eq!(exprs[6]["is_chain"], json!(true));
+        assert_eq!(exprs[6]["onto"].as_array().unwrap().len(), 2);
+        assert_eq!(exprs[7], json!({
+            "kind": "jump", "to": "x", "conditional": true,
+            "span": {"file": "prog.mb", "start": 55, "end": 62, "line": 1, "column": 56}
+        }));
+    }
+}
@@ -0,0 +1,135 @@
+//! The outputs the compiler can be asked for with --emit
+
+use std::path::PathBuf;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EmitKind {
+    /// The program as plain Mindbend, with the files it includes read in
+    /// and its macros expanded
+    Expanded,
+    /// The tokens of the expanded program, as JSON
+    Tokens,
+    /// The parsed program, as JSON
+    Ast,
+    /// The generated code, in one of its forms
+    Code(CodeKind),
+    /// The executable
+    Link
+}
+
+impl EmitKind {
+    pub const NAMES: [&'static str; 8] = [
+        "expanded", "tokens", "ast", "llvm-ir", "llvm-bc", "asm", "obj", "link"
+    ];
+
+    pub fn from_name(name: &str) -> Option<EmitKind> {
+        match name {
+            "expanded" => Some(EmitKind::Expanded),
+            "tokens" => Some(EmitKind::Tokens),
+            "ast" => Some(EmitKind::Ast),
+            "llvm-ir" => Some(EmitKind::Code(CodeKind::LlvmIr)),
+            "llvm-bc" => Some(EmitKind::Code(CodeKind::LlvmBc)),
+            "asm" => Some(EmitKind::Code(CodeKind::Asm)),
+            "obj" => Some(EmitKind::Code(CodeKind::Obj)),
+            "link" => Some(EmitKind::Link),
+            _ => None
+        }
+    }
+}
+
+/// The forms the generated code can be written out in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CodeKind {
+    /// LLVM IR, as text
+    LlvmIr,
+    /// LLVM bitcode
+    LlvmBc,
+    /// Assembly for the target
+    Asm,
+    /// An object file for the target, as it's linked into the executable
+    Obj
+}
+
+impl CodeKind {
+    /// The extension of the files the form is usually written to
+    pub fn extension(&self) -> &'static str {
+        match self {
+            CodeKind::LlvmIr => "ll",
+            CodeKind::LlvmBc => "bc",
+            CodeKind::Asm => "s",
+            CodeKind::Obj => "o"
+        }
+    }
+    /// Whether the form is text that can be printed
+    pub fn is_text(&self) -> bool {
+        matches!(self, CodeKind::LlvmIr | CodeKind::Asm)
+    }
+}
+
+/// An output and where it's written. Without a path, the executable is written to
+/// the -o file, llvm-bc and obj are written next to it with their extension and the
+/// rest are printed on stdout
+#[derive(Debug, Clone, PartialEq)]
+pub struct Emit {
+    pub kind: EmitKind,
+    pub path: Option<PathBuf>
+}
+
+impl Emit {
+    /// Parses an --emit value, KIND or KIND=PATH
+    pub fn parse(value: &str) -> Result<Emit, String> {
+        let (name, path) = match value.find('=') {
+            Some(i) => (&value[..i], Some(PathBuf::from(&value[i + 1..]))),
+            None => (value, None)
+        };
+        let kind = EmitKind::from_name(name).ok_or_else(|| format!(
+            "unknown emit kind `{}`, expected one of {}", name, EmitKind::NAMES.join(", ")
+        ))?;
+        if path.as_ref().map(|path| path.as_os_str().is_empty()).unwrap_or(false) {
+            return Err(format!("expected a path after `{}=`", name));
+        }
+        Ok(Emit { kind, path })
+    }
+    /// Whether the output is printed on stdout instead of written to a file
+    pub fn is_printed(&self) -> bool {
+        self.path.is_none() && match self.kind {
+            EmitKind::Expanded | EmitKind::Tokens | EmitKind::Ast => true,
+            EmitKind::Code(kind) => kind.is_text(),
+            EmitKind::Link => false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse(){
+        assert_eq!(Emit::parse("expanded"), Ok(Emit { kind: EmitKind::Expanded, path: None }));
+        assert_eq!(
+            Emit::parse("link=bin/prog"),
+            Ok(Emit { kind: EmitKind::Link, path: Some(PathBuf::from("bin/prog")) })
+        );
+        assert_eq!(Emit::parse("ast"), Ok(Emit { kind: EmitKind::Ast, path: None }));
+        assert_eq!(
+            Emit::parse("obj=prog.o"),
+            Ok(Emit { kind: EmitKind::Code(CodeKind::Obj), path: Some(PathBuf::from("prog.o")) })
+        );
+        assert_eq!(Emit::parse("llvm-ir"), Ok(Emit { kind: EmitKind::Code(CodeKind::LlvmIr), path: None }));
+        assert_eq!(
+            Emit::parse("llvm"),
+            Err(format!("unknown emit kind `llvm`, expected one of expanded, tokens, ast, llvm-ir, llvm-bc, asm, obj, link"))
+        );
+        assert_eq!(Emit::parse("expanded="), Err(format!("expected a path after `expanded=`")));
+    }
+
+    #[test]
+    fn test_is_printed(){
+        assert!(Emit::parse("asm").unwrap().is_printed());
+        assert!(Emit::parse("tokens").unwrap().is_printed());
+        assert!(!Emit::parse("asm=prog.s").unwrap().is_printed());
+        assert!(!Emit::parse("llvm-bc").unwrap().is_printed());
+        assert!(!Emit::parse("link").unwrap().is_printed());
+    }
+}
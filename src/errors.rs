@@ -1,126 +1,398 @@
-use std::fmt::Display;
-use std::ops::Add;
+use std::fmt;
+use crate::lexer::Span;
+use crate::diagnostics::{Diagnostic, Label};
 
-pub fn err_whitespace(pos: i32) -> String {
-    format!("Invalid whitespace at position n where n is around {}", pos)
-}
-
-pub fn err_unrecognized_token(pos: i32) -> String {
-    format!("Unrecognized token at position n where n is around {}", pos)
-}
+/// How many errors are reported before the compiler gives up, unless told otherwise
+pub const DEFAULT_ERROR_LIMIT: usize = 20;
 
-pub fn err_expected<T>(pos: i32, expected: T) -> String where T: Display {
-    format!("Expected {} at position n where n is around {}", expected, pos)
-}
+/// An error found while compiling a Mindbend program
+///
+/// Every variant has a stable code, which `mindbend explain` takes to print
+/// a long form explanation of the rule that was broken
+#[derive(Debug, Clone, PartialEq)]
+pub enum CompileError {
+    // Lexer errors
+    Whitespace(Span),
+    UnrecognizedToken(Span),
+    /// The span and a description of what was expected there
+    Expected(Span, String),
+    InvalidPrimitive(Span),
+    /// The span of the last token in the program
+    OrgExprMustEndInDeath(Span),
 
-pub fn err_invalid_primitive(pos: i32) -> String {
-    format!("Invalid primitive at position n where n is around {}", pos)
-}
+    // Parser errors
+    InvalidPrimitiveAccessRegion(Span),
+    InvalidPrimitiveAccessGates(Span),
+    InvalidCellAccessRegion(Span),
+    /// The span of the cell a primitive is leached onto and the point where the
+    /// region changes after the primitive end
+    InvalidStoreRegion(Span, Span),
+    /// The span of the cell leached onto another while it holds the Death Expression
+    LeachDeathExpression(Span),
+    /// The span of the cell heading the massacre and a description of what it holds
+    NonFunctionMassacre(Span, String),
+    /// The span of the cell heading the massacre, of the cells it's given and what's
+    /// wrong with their number
+    MassacreArity(Span, Span, String),
+    UnrecognizedRegion(Span, String),
+    ExpectedCellExpressionAfter(Span),
+    ExpectedCellExpression(Span),
+    UnrecognizedCell(Span, String),
+    DrillInCells(Span),
+    /// The span of the chained leach expression and of its last cell
+    ChainedLeachMustEndInMassacre(Span, Span),
+    JumpToNonExistentLabel(Span, String),
+    /// The span of the first label, the duplicate and the label name
+    DuplicateLabel(Span, Span, String),
+    LeachMustStartWithPrimitiveOrCell(Span),
+    /// The span of the repeated cell and of its first occurrence
+    LeachExprOntoItself(Span, Span),
+    ChainLeachEndWithoutChainLeach(Span),
+    TripleSixEqNotExpected(Span),
+    TripleSixNotExpected(Span),
 
-pub fn err_invalid_primitive_access_region<T>(pos: T) -> String where T: Add + Display {
-    format!(
-        "Attempting to access primitive outside the Layers Region at the \
-        nth token, where n is around {}",
-        pos
-    )
-}
+    // Codegen errors
+    /// What was being coded when LLVM didn't give back what it should have
+    Codegen(String),
+    TargetCreation(String),
+    ObjectWrite(String),
+    Link(String),
 
-pub fn err_invalid_primitive_access_gates<T>(pos: T) -> String where T: Add + Display {
-    format!(
-        "Attempting to access primitive when the Layers gates aren't fully open at the \
-        nth token, where n is around {}", pos
-    )
-}
+    // Input errors
+    /// The reason the source couldn't be read
+    Read(String),
+    /// The span of the include expression and the path it names
+    IncludeNotFound(Span, String),
+    IncludeCycle(Span, String),
+    /// The point in the source where it stops being UTF-8
+    InvalidUtf8(Span),
+    /// The program is nothing but the death of the organism expression
+    OnlyDeath,
 
-pub fn err_invalid_cell_access_region<T>(pos: T) -> String where T: Add + Display {
-    format!("Attempting to access cell outside the Cells Region at the nth token, where n is around {}", pos)
+    // Macro errors
+    /// The span of the call and the macro's name
+    UndefinedMacro(Span, String),
+    /// The span of the first definition, the duplicate and the macro's name
+    DuplicateMacro(Span, Span, String),
+    /// The span where the argument was expected, the macro's name and the parameter
+    ExpectedMacroArgument(Span, String, String),
+    UnknownPlaceholder(Span, String),
+    /// The span of the start of the definition
+    MacroNotClosed(Span),
+    EndMacroWithoutMacro(Span)
 }
 
-pub fn err_unrecognized_region(pos: usize, found: String) -> String {
-    format!("Use of unrecognized region {} at the nth token, where n is around {}", found.as_str(), pos)
-}
+impl CompileError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            CompileError::Whitespace(..) => "MB0001",
+            CompileError::UnrecognizedToken(..) => "MB0002",
+            CompileError::Expected(..) => "MB0003",
+            CompileError::InvalidPrimitive(..) => "MB0004",
+            CompileError::OrgExprMustEndInDeath(..) => "MB0005",
+            CompileError::InvalidPrimitiveAccessRegion(..) => "MB0006",
+            CompileError::InvalidPrimitiveAccessGates(..) => "MB0007",
+            CompileError::InvalidCellAccessRegion(..) => "MB0008",
+            CompileError::UnrecognizedRegion(..) => "MB0009",
+            CompileError::ExpectedCellExpressionAfter(..) => "MB0010",
+            CompileError::ExpectedCellExpression(..) => "MB0011",
+            CompileError::UnrecognizedCell(..) => "MB0012",
+            CompileError::DrillInCells(..) => "MB0013",
+            CompileError::ChainedLeachMustEndInMassacre(..) => "MB0014",
+            CompileError::JumpToNonExistentLabel(..) => "MB0015",
+            CompileError::DuplicateLabel(..) => "MB0016",
+            CompileError::LeachMustStartWithPrimitiveOrCell(..) => "MB0017",
+            CompileError::LeachExprOntoItself(..) => "MB0018",
+            CompileError::ChainLeachEndWithoutChainLeach(..) => "MB0019",
+            CompileError::TripleSixEqNotExpected(..) => "MB0020",
+            CompileError::TripleSixNotExpected(..) => "MB0021",
+            CompileError::TargetCreation(..) => "MB0022",
+            CompileError::ObjectWrite(..) => "MB0023",
+            CompileError::Link(..) => "MB0024",
+            CompileError::Read(..) => "MB0025",
+            CompileError::IncludeNotFound(..) => "MB0026",
+            CompileError::IncludeCycle(..) => "MB0027",
+            CompileError::UndefinedMacro(..) => "MB0028",
+            CompileError::DuplicateMacro(..) => "MB0029",
+            CompileError::ExpectedMacroArgument(..) => "MB0030",
+            CompileError::UnknownPlaceholder(..) => "MB0031",
+            CompileError::MacroNotClosed(..) => "MB0032",
+            CompileError::EndMacroWithoutMacro(..) => "MB0033",
+            CompileError::InvalidStoreRegion(..) => "MB0034",
+            CompileError::LeachDeathExpression(..) => "MB0035",
+            CompileError::NonFunctionMassacre(..) => "MB0036",
+            CompileError::MassacreArity(..) => "MB0037",
+            CompileError::InvalidUtf8(..) => "MB0038",
+            CompileError::Codegen(..) => "MB0039",
+            CompileError::OnlyDeath => "MB0040"
+        }
+    }
 
-pub fn err_expected_cell_expression_after(pos: usize) -> String {
-    format!("Expected cell expression after the nth token, where n is around {}", pos)
-}
-
-pub fn err_expected_cell_expression(pos: usize) -> String {
-    format!("Expected cell expression at the nth token, where n is around {}", pos)
-}
-
-pub fn err_unrecognized_cell(pos: usize, found: String) -> String {
-    format!("Use of unrecognized cell {} at the nth token, where n is around {}", found.as_str(), pos)
-}
-
-pub fn err_drill_in_cells(pos: usize) -> String {
-    format!(
-        "Attempt to drill in the Cells Region at the nth token, where n is around {}", pos
-    )
-}
-
-pub fn err_org_expr_must_end_in_death() -> String {
-    format!("A Mindbend program must end in the death of the Organism Expression")
-}
-
-pub fn err_chained_leach_expression_must_end_in_massacre(pos: usize) -> String {
-    format!("\
-        Chained leach expression at the nth token, where n is around {}, does not end in \
-        a massacre. A chained leach expression must end in a massacre",
-        pos
-    )
-}
-
-pub fn err_attempt_to_jump_to_non_existent_label(pos: usize) -> String {
-    format!(
-        "Attempt to jump to non existent label at the nth token, where n is around {}",
-        pos
-    )
-}
-
-pub fn err_duplicate_label(pos1: usize, pos2: usize) -> String {
-    format!(
-        "Label name at the nth token duplicated in the label name at the mth token, \
-        where n is around {} and m is around {}",
-        pos1, pos2
-    )
-}
-
-pub fn err_leach_expression_must_start_with_primitive_or_cell(pos: usize) -> String {
-    format!(
-        "The leach expression at the nth token, where n is around {}, \
-        does not begin with a primitive or Cell",
-        pos
-    )
-}
-
-pub fn err_attempt_to_leach_expr_onto_itself(pos: usize) -> String {
-    format!(
-        "Attempt to leach expression onto itself at the nth token, \
-        where n is around {}",
-        pos
-    )
-}
-
-pub fn err_chain_leach_expression_ending_without_chain_leach_expression(pos: usize) -> String {
-    format!(
-        "Ending a chain leach expression without a chain leach expression at the nth token, \
-        where n is around {}",
-        pos
-    )
-}
+    /// Where in the source the error was found
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            CompileError::Whitespace(span) |
+            CompileError::UnrecognizedToken(span) |
+            CompileError::Expected(span, _) |
+            CompileError::InvalidPrimitive(span) |
+            CompileError::OrgExprMustEndInDeath(span) |
+            CompileError::InvalidPrimitiveAccessRegion(span) |
+            CompileError::InvalidPrimitiveAccessGates(span) |
+            CompileError::InvalidCellAccessRegion(span) |
+            CompileError::InvalidStoreRegion(span, _) |
+            CompileError::LeachDeathExpression(span) |
+            CompileError::NonFunctionMassacre(span, _) |
+            CompileError::MassacreArity(_, span, _) |
+            CompileError::UnrecognizedRegion(span, _) |
+            CompileError::ExpectedCellExpressionAfter(span) |
+            CompileError::ExpectedCellExpression(span) |
+            CompileError::UnrecognizedCell(span, _) |
+            CompileError::DrillInCells(span) |
+            CompileError::ChainedLeachMustEndInMassacre(span, _) |
+            CompileError::JumpToNonExistentLabel(span, _) |
+            CompileError::DuplicateLabel(_, span, _) |
+            CompileError::LeachMustStartWithPrimitiveOrCell(span) |
+            CompileError::LeachExprOntoItself(span, _) |
+            CompileError::ChainLeachEndWithoutChainLeach(span) |
+            CompileError::TripleSixEqNotExpected(span) |
+            CompileError::TripleSixNotExpected(span) |
+            CompileError::IncludeNotFound(span, _) |
+            CompileError::IncludeCycle(span, _) |
+            CompileError::InvalidUtf8(span) |
+            CompileError::UndefinedMacro(span, _) |
+            CompileError::DuplicateMacro(_, span, _) |
+            CompileError::ExpectedMacroArgument(span, _, _) |
+            CompileError::UnknownPlaceholder(span, _) |
+            CompileError::MacroNotClosed(span) |
+            CompileError::EndMacroWithoutMacro(span) => Some(*span),
+            CompileError::Codegen(_) |
+            CompileError::TargetCreation(_) |
+            CompileError::ObjectWrite(_) |
+            CompileError::Link(_) |
+            CompileError::Read(_) |
+            CompileError::OnlyDeath => None
+        }
+    }
 
-pub fn err_triple_six_eq_not_expected_here(pos: usize) -> String {
-    format!(
-        "^^^^^^666^^^^^^= not expected at the nth token, where n is around {}",
-        pos
-    )
+    /// The error with labels pointing at the offending parts of the source
+    pub fn to_diagnostic(&self) -> Diagnostic {
+        let diagnostic = Diagnostic::new(self.to_string()).with_code(self.code());
+        match self {
+            CompileError::Whitespace(span) => diagnostic
+                .with_label(Label::primary(*span, format!("whitespace isn't allowed")))
+                .with_suggestion(format!("remove the whitespace"), *span, String::new()),
+            CompileError::UnrecognizedToken(span) =>
+                diagnostic.with_label(Label::primary(*span, format!("not part of any Mindbend token"))),
+            CompileError::Expected(span, expected) => {
+                let diagnostic = diagnostic
+                    .with_label(Label::primary(*span, format!("expected {} here", expected)));
+                // Only a missing char, found at the end of the input, is certainly fixed
+                // by inserting what was expected
+                if span.len() == 0 && expected.chars().count() == 1 {
+                    diagnostic.with_suggestion(format!("insert `{}`", expected), *span, expected.clone())
+                } else {
+                    diagnostic
+                }
+            },
+            CompileError::InvalidPrimitive(span) =>
+                diagnostic.with_label(Label::primary(*span, format!("not a primitive symbol or index"))),
+            CompileError::OrgExprMustEndInDeath(span) => diagnostic
+                .with_label(Label::primary(*span, format!("expected ^^^^^^666^^^^^^=O after this")))
+                .with_suggestion(
+                    format!("end the program with the death of the Organism Expression"),
+                    span.end_point(),
+                    format!("^^^^^^666^^^^^^=O")
+                ),
+            CompileError::InvalidPrimitiveAccessRegion(span) => diagnostic
+                .with_label(Label::primary(*span, format!("not in the Layers Region here")))
+                .with_suggestion(format!("move to the Layers Region first"), span.start_point(), format!("->L")),
+            CompileError::InvalidPrimitiveAccessGates(span) =>
+                diagnostic.with_label(Label::primary(*span, format!("the gates aren't fully open here"))),
+            CompileError::InvalidCellAccessRegion(span) => diagnostic
+                .with_label(Label::primary(*span, format!("not in the Cells Region here")))
+                .with_suggestion(format!("move to the Cells Region first"), span.start_point(), format!("->C")),
+            CompileError::InvalidStoreRegion(span, region_changes_end) => diagnostic
+                .with_label(Label::primary(*span, format!("not in the Cells Region here")))
+                .with_suggestion(
+                    format!("move to the Cells Region before the ~"),
+                    *region_changes_end,
+                    format!("->C")
+                ),
+            CompileError::LeachDeathExpression(span) =>
+                diagnostic.with_label(Label::primary(*span, format!("holds the Death Expression here"))),
+            CompileError::NonFunctionMassacre(span, holds) =>
+                diagnostic.with_label(Label::primary(*span, format!("holds {} here", holds))),
+            CompileError::MassacreArity(head, cells, description) => diagnostic
+                .with_label(Label::primary(*cells, description.clone()))
+                .with_label(Label::secondary(*head, format!("the massacre's primitive comes from this cell"))),
+            CompileError::UnrecognizedRegion(span, found) =>
+                diagnostic.with_label(Label::primary(*span, format!("no region named {}", found))),
+            CompileError::ExpectedCellExpressionAfter(span) =>
+                diagnostic.with_label(Label::primary(*span, format!("expected a cell expression after this"))),
+            CompileError::ExpectedCellExpression(span) =>
+                diagnostic.with_label(Label::primary(*span, format!("expected a cell expression"))),
+            CompileError::UnrecognizedCell(span, found) =>
+                diagnostic.with_label(Label::primary(*span, format!("no cell named {}", found))),
+            CompileError::DrillInCells(span) => diagnostic
+                .with_label(Label::primary(*span, format!("gates can only be drilled in the Layers Region")))
+                .with_suggestion(format!("move to the Layers Region first"), span.start_point(), format!("->L")),
+            CompileError::ChainedLeachMustEndInMassacre(span, last_cell_span) => diagnostic
+                .with_label(Label::primary(*span, format!("this chained leach expression")))
+                .with_label(Label::secondary(*last_cell_span, format!("expected ^^^^^^666^^^^^^=M after this")))
+                .with_suggestion(
+                    format!("end the massacre"),
+                    last_cell_span.end_point(),
+                    format!("^^^^^^666^^^^^^=M")
+                ),
+            CompileError::JumpToNonExistentLabel(span, label) =>
+                diagnostic.with_label(Label::primary(*span, format!("no label named `{}`", label))),
+            CompileError::DuplicateLabel(first, duplicate, label) => diagnostic
+                .with_label(Label::primary(*duplicate, format!("label `{}` defined again here", label)))
+                .with_label(Label::secondary(*first, format!("first defined here"))),
+            CompileError::LeachMustStartWithPrimitiveOrCell(span) =>
+                diagnostic.with_label(Label::primary(*span, format!("expected a primitive or cell before this"))),
+            CompileError::LeachExprOntoItself(span, first) => diagnostic
+                .with_label(Label::primary(*span, format!("leached onto again here")))
+                .with_label(Label::secondary(*first, format!("the expression comes from this cell"))),
+            CompileError::ChainLeachEndWithoutChainLeach(span) =>
+                diagnostic.with_label(Label::primary(*span, format!("no chain leach expression to end"))),
+            CompileError::TripleSixEqNotExpected(span) | CompileError::TripleSixNotExpected(span) =>
+                diagnostic.with_label(Label::primary(*span, format!("not expected here"))),
+            CompileError::IncludeNotFound(span, path) => diagnostic
+                .with_label(Label::primary(*span, format!("no file named {} here or in the search path", path)))
+                .with_note(format!("add the directory the file is in to the search path with -I")),
+            CompileError::IncludeCycle(span, path) =>
+                diagnostic.with_label(Label::primary(*span, format!("{} is already being included", path))),
+            CompileError::InvalidUtf8(span) =>
+                diagnostic.with_label(Label::primary(*span, format!("the next byte isn't valid UTF-8"))),
+            CompileError::UndefinedMacro(span, name) =>
+                diagnostic.with_label(Label::primary(*span, format!("no macro named `{}` is defined before this", name))),
+            CompileError::DuplicateMacro(first, duplicate, name) => diagnostic
+                .with_label(Label::primary(*duplicate, format!("macro `{}` defined again here", name)))
+                .with_label(Label::secondary(*first, format!("first defined here"))),
+            CompileError::ExpectedMacroArgument(span, _, param) =>
+                diagnostic.with_label(Label::primary(*span, format!("expected a cell or primitive for <{}>", param))),
+            CompileError::UnknownPlaceholder(span, _) =>
+                diagnostic.with_label(Label::primary(*span, format!("not a parameter of the macro this is in"))),
+            CompileError::MacroNotClosed(span) =>
+                diagnostic.with_label(Label::primary(*span, format!("this definition is never closed"))),
+            CompileError::EndMacroWithoutMacro(span) => diagnostic
+                .with_label(Label::primary(*span, format!("no macro definition to close")))
+                .with_suggestion(format!("remove it"), *span, String::new()),
+            CompileError::Codegen(_) | CompileError::TargetCreation(_) | CompileError::ObjectWrite(_) |
+            CompileError::Link(_) | CompileError::Read(_) | CompileError::OnlyDeath =>
+                diagnostic
+        }
+    }
 }
 
-pub fn err_triple_six_not_expected_here(pos: usize) -> String {
-    format!(
-        "^^^^^^666^^^^^^ not expected at the nth token, where n is around {}",
-        pos
-    )
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CompileError::Whitespace(span) => write!(f, "Invalid whitespace at {}", span),
+            CompileError::UnrecognizedToken(span) => write!(f, "Unrecognized token at {}", span),
+            CompileError::Expected(span, expected) => write!(f, "Expected {} at {}", expected, span),
+            CompileError::InvalidPrimitive(span) => write!(f, "Invalid primitive at {}", span),
+            CompileError::OrgExprMustEndInDeath(span) => write!(f,
+                "A Mindbend program must end in the death of the Organism Expression, \
+                but the last expression is at {}",
+                span
+            ),
+            CompileError::InvalidPrimitiveAccessRegion(span) => write!(f,
+                "Attempting to access primitive outside the Layers Region at {}", span
+            ),
+            CompileError::InvalidPrimitiveAccessGates(span) => write!(f,
+                "Attempting to access primitive when the Layers gates aren't fully open at {}", span
+            ),
+            CompileError::InvalidCellAccessRegion(span) => write!(f,
+                "Attempting to access cell outside the Cells Region at {}", span
+            ),
+            CompileError::InvalidStoreRegion(span, _) => write!(f,
+                "Attempting to store a primitive in a cell outside the Cells Region at {}", span
+            ),
+            CompileError::LeachDeathExpression(span) => write!(f,
+                "Attempt to leach death expression onto another Cell at {}", span
+            ),
+            CompileError::NonFunctionMassacre(span, _) => write!(f,
+                "Attempt to use non-function primitive to massacre at {}", span
+            ),
+            CompileError::MassacreArity(head, _, description) => write!(f,
+                "Massacre at {} given the wrong number of cells: {}", head, description
+            ),
+            CompileError::UnrecognizedRegion(span, found) => write!(f,
+                "Use of unrecognized region {} at {}", found, span
+            ),
+            CompileError::ExpectedCellExpressionAfter(span) => write!(f,
+                "Expected cell expression after {}", span
+            ),
+            CompileError::ExpectedCellExpression(span) => write!(f, "Expected cell expression at {}", span),
+            CompileError::UnrecognizedCell(span, found) => write!(f,
+                "Use of unrecognized cell {} at {}", found, span
+            ),
+            CompileError::DrillInCells(span) => write!(f, "Attempt to drill in the Cells Region at {}", span),
+            CompileError::ChainedLeachMustEndInMassacre(span, _) => write!(f,
+                "Chained leach expression at {}, does not end in \
+                a massacre. A chained leach expression must end in a massacre",
+                span
+            ),
+            CompileError::JumpToNonExistentLabel(span, _) => write!(f,
+                "Attempt to jump to non existent label at {}", span
+            ),
+            CompileError::DuplicateLabel(first, duplicate, _) => write!(f,
+                "Label name at {} duplicated in the label name at {}", first, duplicate
+            ),
+            CompileError::LeachMustStartWithPrimitiveOrCell(span) => write!(f,
+                "The leach expression at {}, does not begin with a primitive or Cell", span
+            ),
+            CompileError::LeachExprOntoItself(span, _) => write!(f,
+                "Attempt to leach expression onto itself at {}", span
+            ),
+            CompileError::ChainLeachEndWithoutChainLeach(span) => write!(f,
+                "Ending a chain leach expression without a chain leach expression at {}", span
+            ),
+            CompileError::TripleSixEqNotExpected(span) => write!(f, "^^^^^^666^^^^^^= not expected at {}", span),
+            CompileError::TripleSixNotExpected(span) => write!(f, "^^^^^^666^^^^^^ not expected at {}", span),
+            CompileError::Codegen(what) => write!(f, "Something went wrong while coding {}", what),
+            CompileError::TargetCreation(reason) => write!(f,
+                "Something went wrong while creating the target machine: {}", reason
+            ),
+            CompileError::ObjectWrite(reason) => write!(f,
+                "Something went wrong while writing to a file: {}", reason
+            ),
+            CompileError::Link(reason) => write!(f,
+                "Something went wrong while linking the program: {}", reason
+            ),
+            CompileError::Read(reason) => write!(f,
+                "Something went wrong while reading the input file: {}", reason
+            ),
+            CompileError::IncludeNotFound(span, path) => write!(f,
+                "The file {} included at {} couldn't be found", path, span
+            ),
+            CompileError::IncludeCycle(span, path) => write!(f,
+                "The file {} included at {} ends up including itself", path, span
+            ),
+            CompileError::InvalidUtf8(span) => write!(f, "The source isn't valid UTF-8 at {}", span),
+            CompileError::OnlyDeath => write!(f, "Only Organism death."),
+            CompileError::UndefinedMacro(span, name) => write!(f,
+                "Call of undefined macro {} at {}", name, span
+            ),
+            CompileError::DuplicateMacro(first, duplicate, _) => write!(f,
+                "Macro name at {} duplicated in the macro name at {}", first, duplicate
+            ),
+            CompileError::ExpectedMacroArgument(span, name, param) => write!(f,
+                "Expected the argument for {} of macro {} at {}", param, name, span
+            ),
+            CompileError::UnknownPlaceholder(span, name) => write!(f,
+                "Placeholder <{}> at {} isn't a parameter of the macro it's in", name, span
+            ),
+            CompileError::MacroNotClosed(span) => write!(f,
+                "The macro definition at {} isn't closed with endmacro:", span
+            ),
+            CompileError::EndMacroWithoutMacro(span) => write!(f,
+                "endmacro: at {} doesn't close any macro definition", span
+            )
+        }
+    }
 }
 
 pub fn err_invalid_primitive_access_region_not_layers_runtime() -> String {
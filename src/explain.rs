@@ -0,0 +1,614 @@
+//! Long form explanations of the errors and lints, printed by `mindbend explain`
+
+use crate::lints;
+
+/// The explanation of the error with the code or the lint with the name, if there is
+/// any such error or lint
+pub fn explain(code: &str) -> Option<&'static str> {
+    let upper_code = code.to_uppercase();
+    EXPLANATIONS.iter()
+        .find(|(c, _)| *c == upper_code)
+        .map(|(_, explanation)| *explanation)
+        .or_else(|| lints::find(&code.to_lowercase()).map(|lint| lint.explanation))
+}
+
+const EXPLANATIONS: [(&str, &str); 40] = [
+    ("MB0001", "\
+Whitespace was found in the program.
+
+Whitespace is not allowed anywhere in a Mindbend program, not even a trailing
+newline. Every token must follow the one before it directly.
+
+Erroneous code example:
+
+    ->L \\\\|//^^^^^^666^^^^^^=O
+
+Fixed example:
+
+    ->L\\\\|//^^^^^^666^^^^^^=O
+"),
+    ("MB0002", "\
+A character that doesn't start or continue any Mindbend token was found.
+
+The tokens are the primitive operator `$`, region expressions like `->L`,
+drills `\\\\|//`, the leach operator `~`, cells `0` to `E`, `label:x:`,
+`jmp:x:`, `ijmp:x:` and the ^^^^^^666^^^^^^ family of tokens. Partial
+tokens, like a drill with a missing slash, are unrecognized too.
+
+Erroneous code example:
+
+    ->L\\\\|/^^^^^^666^^^^^^=O
+
+Fixed example:
+
+    ->L\\\\|//^^^^^^666^^^^^^=O
+"),
+    ("MB0003", "\
+A token was cut short where a particular character was expected.
+
+Labels and jumps are written `label:name:`, `jmp:name:` and `ijmp:name:`,
+with the name closed by a `:`. A `$` must be followed by a primitive symbol
+or index.
+
+Erroneous code example:
+
+    label:x^^^^^^666^^^^^^=O
+
+Fixed example:
+
+    label:x:^^^^^^666^^^^^^=O
+"),
+    ("MB0004", "\
+The primitive operator `$` was followed by something that isn't a primitive.
+
+Primitives are obtained by their symbol or their index:
+
+    symbol  index  primitive
+    !       D      1
+    @       C      2
+    #       B      3
+    +       A      4
+    %       9      5
+    `       8      6
+    &       7      7
+    *       6      8
+    (       5      9
+    )       4      0
+    <>      3      input
+    ><      2      output
+    }       1      addition
+    {       0      subtraction
+
+Erroneous code example:
+
+    ->L\\\\|//\\\\|//\\\\|//$E^^^^^^666^^^^^^=O
+
+Fixed example:
+
+    ->L\\\\|//\\\\|//\\\\|//$D^^^^^^666^^^^^^=O
+"),
+    ("MB0005", "\
+The program doesn't end in the death of the Organism Expression.
+
+A Mindbend program is itself an expression, the Organism Expression, and
+every program must end with its death, ^^^^^^666^^^^^^=O.
+Nothing can come after it.
+
+Erroneous code example:
+
+    ->L\\\\|//
+
+Fixed example:
+
+    ->L\\\\|//^^^^^^666^^^^^^=O
+"),
+    ("MB0006", "\
+A primitive was accessed outside the Layers Region.
+
+The primitives live at the bottom of the Layers Region. Every program starts
+in the Cells Region, so a region expression, ->L, must come before a
+primitive can be obtained.
+
+Erroneous code example:
+
+    $!->C~0^^^^^^666^^^^^^=O
+
+Fixed example:
+
+    ->L\\\\|//\\\\|//\\\\|//$!->C~0^^^^^^666^^^^^^=O
+"),
+    ("MB0007", "\
+A primitive was accessed when the Layers gates weren't fully open.
+
+There are 3 gates above the primitives and all of them are closed when the
+program starts. Each drill expression, \\\\|//, opens one gate, so three
+drills are needed before a primitive can be accessed. After the gates have
+been opened, they close again once 5 active expressions (region
+expressions, leach expressions and jumps) have been created.
+
+Erroneous code example:
+
+    ->L\\\\|//$!->C~0^^^^^^666^^^^^^=O
+
+Fixed example:
+
+    ->L\\\\|//\\\\|//\\\\|//$!->C~0^^^^^^666^^^^^^=O
+"),
+    ("MB0008", "\
+A cell was accessed outside the Cells Region.
+
+The cells, 0 to E, exist only in the Cells Region. After going to the Layers
+Region to get a primitive, a region expression, ->C, is needed to get back
+to the cells.
+
+Erroneous code example:
+
+    ->L0~1^^^^^^666^^^^^^=O
+
+Fixed example:
+
+    ->L\\\\|//\\\\|//\\\\|//$!->C~00~1^^^^^^666^^^^^^=O
+"),
+    ("MB0009", "\
+A region expression names a region that doesn't exist.
+
+There are only 2 regions: the Cells Region, ->C, and the Layers Region, ->L.
+The lexer only produces region expressions for these 2, reporting anything
+else after a -> as an unrecognized token (MB0002), so this error is only
+seen when a token stream is built by something other than the lexer.
+"),
+    ("MB0010", "\
+A leach operator, ~, isn't followed by anything.
+
+The expression being leached must be leached onto a cell, so a cell must
+come after every ~.
+
+Erroneous code example:
+
+    ->L\\\\|//\\\\|//\\\\|//$!->C~^^^^^^666^^^^^^=O
+
+Fixed example:
+
+    ->L\\\\|//\\\\|//\\\\|//$!->C~0^^^^^^666^^^^^^=O
+"),
+    ("MB0011", "\
+A leach operator, ~, is followed by something that isn't a cell.
+
+Expressions can only be leached onto cells, 0 to E. Region expressions are
+only allowed between a primitive and the ~, never after it.
+
+Erroneous code example:
+
+    ->L\\\\|//\\\\|//\\\\|//$!~->C0^^^^^^666^^^^^^=O
+
+Fixed example:
+
+    ->L\\\\|//\\\\|//\\\\|//$!->C~0^^^^^^666^^^^^^=O
+"),
+    ("MB0012", "\
+A cell that doesn't exist was used.
+
+There are 15 cells, numbered in hex digits from 0 to E. The lexer reports
+anything else as an unrecognized token (MB0002), so this error is only seen
+when a token stream is built by something other than the lexer.
+"),
+    ("MB0013", "\
+A drill expression was used in the Cells Region.
+
+The gates are in the Layers Region, so they can only be drilled there.
+
+Erroneous code example:
+
+    \\\\|//^^^^^^666^^^^^^=O
+
+Fixed example:
+
+    ->L\\\\|//^^^^^^666^^^^^^=O
+"),
+    ("MB0014", "\
+A chained leach expression doesn't end in a massacre.
+
+When an expression is leached through more than one cell, like 9~B~A, the
+expression in the first cell goes on a massacre, consuming the expressions in
+the cells after it. The massacre must be ended with ^^^^^^666^^^^^^=M.
+
+Erroneous code example:
+
+    0~1~2^^^^^^666^^^^^^=O
+
+Fixed example:
+
+    ->L\\\\|//\\\\|//\\\\|//$}->C~00~1~2^^^^^^666^^^^^^=M^^^^^^666^^^^^^=O
+"),
+    ("MB0015", "\
+A jump expression jumps to a label that doesn't exist.
+
+Both jmp:x: and ijmp:x: need a label:x: somewhere in the program.
+
+Erroneous code example:
+
+    jmp:x:^^^^^^666^^^^^^=O
+
+Fixed example:
+
+    label:x:jmp:x:^^^^^^666^^^^^^=O
+"),
+    ("MB0016", "\
+The same label name was defined more than once.
+
+A jump must know exactly where it goes, so every label name must be unique.
+
+Erroneous code example:
+
+    label:x:label:x:^^^^^^666^^^^^^=O
+
+Fixed example:
+
+    label:x:label:y:^^^^^^666^^^^^^=O
+"),
+    ("MB0017", "\
+A leach expression doesn't begin with a primitive or a cell.
+
+Only primitive expressions, like $!, and cell expressions, like 0, can be
+leached onto a cell. A ~ after anything else, like a region expression that
+isn't preceded by a primitive, has nothing to leach.
+
+Erroneous code example:
+
+    ->L\\\\|//\\\\|//\\\\|//->C~0^^^^^^666^^^^^^=O
+
+Fixed example:
+
+    ->L\\\\|//\\\\|//\\\\|//$!->C~0^^^^^^666^^^^^^=O
+"),
+    ("MB0018", "\
+An expression is leached onto a cell that it has already been through.
+
+A leach expression reproduces the expression in a cell and kills it, so the
+expression can't be leached back onto the cell it came from, not even
+further down a chain.
+
+Erroneous code example:
+
+    0~1~0^^^^^^666^^^^^^=M^^^^^^666^^^^^^=O
+
+Fixed example:
+
+    ->L\\\\|//\\\\|//\\\\|//$}->C~00~1~2^^^^^^666^^^^^^=M^^^^^^666^^^^^^=O
+"),
+    ("MB0019", "\
+A massacre was ended, ^^^^^^666^^^^^^=M, without a chained leach expression.
+
+Only chained leach expressions go on massacres, so =M can only come right
+after the last cell of a leach expression.
+
+Erroneous code example:
+
+    0^^^^^^666^^^^^^=M^^^^^^666^^^^^^=O
+
+Fixed example:
+
+    ->L\\\\|//\\\\|//\\\\|//$}->C~00~1~2^^^^^^666^^^^^^=M^^^^^^666^^^^^^=O
+"),
+    ("MB0020", "\
+A bare ^^^^^^666^^^^^^= was found.
+
+The ^^^^^^666^^^^^^= token is only meaningful as the start of
+^^^^^^666^^^^^^=M, the end of a massacre, or ^^^^^^666^^^^^^=O, the
+death of the Organism Expression.
+
+Erroneous code example:
+
+    0^^^^^^666^^^^^^=^^^^^^666^^^^^^=O
+
+Fixed example:
+
+    0^^^^^^666^^^^^^=O
+"),
+    ("MB0021", "\
+A bare ^^^^^^666^^^^^^ was found.
+
+The ^^^^^^666^^^^^^ token is only meaningful as the start of
+^^^^^^666^^^^^^=M, the end of a massacre, or ^^^^^^666^^^^^^=O, the
+death of the Organism Expression.
+
+Erroneous code example:
+
+    0^^^^^^666^^^^^^^^^^^^666^^^^^^=O
+
+Fixed example:
+
+    0^^^^^^666^^^^^^=O
+"),
+    ("MB0022", "\
+A target machine couldn't be created for the target.
+
+The compiler asks LLVM for a target machine for the triple given with
+--target, or the host's without one. This fails when the triple is malformed
+or the LLVM the compiler was built with doesn't support the target.
+`mindbend --print targets` lists the ones it does. It also fails when
+--target-cpu=native is used with a --target other than the host, as the
+native CPU is the host's. There is nothing wrong with the program.
+"),
+    ("MB0023", "\
+The generated code, or something else asked for with --emit, couldn't be
+written.
+
+Before linking, the compiled program is written to an object file next to the
+output file, with a .tmp extension. The code asked for with --emit, as LLVM
+IR, LLVM bitcode, assembly or an object file, is written to the path given
+with it, as are the expanded program, its tokens and its AST. This fails when
+the directory doesn't exist or isn't writable, or when LLVM can't generate
+code in that form. There is nothing wrong with the program.
+"),
+    ("MB0024", "\
+The program couldn't be linked.
+
+The object file is linked into an executable with the --linker or, without
+one, with gcc or, for a --target other than the host, the cross gcc named
+after the triple, like aarch64-linux-gnu-gcc. This fails when the linker isn't
+installed or reports an error, which is included in the message, like when a
+library asked for with --link-arg or the static libraries --static needs
+aren't there. There is nothing wrong with the program. Use -c to stop at the
+object file and link it some other way.
+"),
+    ("MB0025", "\
+The input file couldn't be read to the end.
+
+The source is read bit by bit while it's compiled, so this is reported when
+reading fails part of the way through, like when the file is on a device that
+was disconnected. It's also reported when the input file doesn't exist, can't
+be opened or is empty. There is nothing wrong with the program.
+"),
+    ("MB0026", "\
+The file named in an include expression couldn't be found.
+
+An include expression, `include:path:`, is replaced by the tokens of the file
+at the path. The path is looked for relative to the directory of the file
+the include expression is in, then relative to each directory given with
+-I, in the order they were given.
+
+Erroneous code example:
+
+    include:no/such/file.mb:^^^^^^666^^^^^^=O
+
+Either fix the path or pass the directory the file is in to -I.
+"),
+    ("MB0027", "\
+A file ends up including itself.
+
+The tokens of an included file go in place of the include expression, so a
+file that includes itself, directly or through the files it includes, would
+never end. For example, if a.mb is
+
+    include:b.mb:^^^^^^666^^^^^^=O
+
+and b.mb is
+
+    include:a.mb:
+
+then compiling a.mb includes b.mb, which includes a.mb again. Move what the
+files share into a third file that both of them include.
+"),
+    ("MB0028", "\
+A macro was called, but no macro with that name was defined before the call.
+
+Macros are expanded as the program is read, so a macro must be defined
+before it's called, even when the call is in the body of another macro.
+
+Erroneous code example:
+
+    call:save:macro:save::->C0~1endmacro:^^^^^^666^^^^^^=O
+
+Fixed example:
+
+    macro:save::->C0~1endmacro:->L\\\\|//\\\\|//\\\\|//$!->C~0call:save:^^^^^^666^^^^^^=O
+"),
+    ("MB0029", "\
+Two macros were defined with the same name.
+
+A call must know exactly which macro it expands, so every macro name must be
+unique.
+
+Erroneous code example:
+
+    macro:m::->C0~1endmacro:macro:m::->C2~3endmacro:call:m:^^^^^^666^^^^^^=O
+
+Fixed example:
+
+    macro:m::->C0~1endmacro:macro:n::->C2~3endmacro:->L\\\\|//\\\\|//\\\\|//$!->C~0->L$@->C~2call:m:call:n:^^^^^^666^^^^^^=O
+"),
+    ("MB0030", "\
+A macro call wasn't followed by an argument for each of the macro's parameters.
+
+A call, `call:name:`, is followed by its arguments, one for each parameter of
+the macro in the order they were declared. Each argument is a cell, like `3`,
+or a primitive, like `$!`.
+
+Erroneous code example:
+
+    macro:copy:a,b:->C<a>~<b>endmacro:call:copy:0^^^^^^666^^^^^^=O
+
+Fixed example:
+
+    macro:copy:a,b:->C<a>~<b>endmacro:->L\\\\|//\\\\|//\\\\|//$!->C~0call:copy:01^^^^^^666^^^^^^=O
+"),
+    ("MB0031", "\
+A placeholder was used that isn't a parameter of the macro it's in.
+
+Placeholders, like `<a>`, mark where the arguments go in the body of a macro,
+so they can only name the parameters of the macro, and can't be used outside
+of a macro at all.
+
+Erroneous code example:
+
+    macro:copy:a,b:->C<a>~<c>endmacro:call:copy:01^^^^^^666^^^^^^=O
+
+Fixed example:
+
+    macro:copy:a,b:->C<a>~<b>endmacro:->L\\\\|//\\\\|//\\\\|//$!->C~0call:copy:01^^^^^^666^^^^^^=O
+"),
+    ("MB0032", "\
+A macro definition wasn't closed with `endmacro:`.
+
+Everything from `macro:name:params:` up to the next `endmacro:` is the body of
+the macro. Definitions can't be nested, so a definition that is still open
+when another one starts is reported too.
+
+Erroneous code example:
+
+    macro:copy:a,b:->C<a>~<b>^^^^^^666^^^^^^=O
+
+Fixed example:
+
+    macro:copy:a,b:->C<a>~<b>endmacro:->L\\\\|//\\\\|//\\\\|//$!->C~0call:copy:01^^^^^^666^^^^^^=O
+"),
+    ("MB0033", "\
+An `endmacro:` was found outside of a macro definition.
+
+Erroneous code example:
+
+    ->L\\\\|//\\\\|//\\\\|//$!->C~00~1endmacro:^^^^^^666^^^^^^=O
+
+Fixed example:
+
+    ->L\\\\|//\\\\|//\\\\|//$!->C~00~1^^^^^^666^^^^^^=O
+"),
+    ("MB0034", "\
+A primitive was leached onto a cell outside the Cells Region.
+
+The region expressions between a primitive and the ~ after it are carried out
+before the primitive is stored, so the cell it's leached onto is reached in
+whatever region they end in. After getting the primitive from the Layers
+Region, a region expression, ->C, is needed before the ~ to get back to the
+cells.
+
+Erroneous code example:
+
+    ->L\\\\|//\\\\|//\\\\|//$!~0^^^^^^666^^^^^^=O
+
+Fixed example:
+
+    ->L\\\\|//\\\\|//\\\\|//$!->C~0^^^^^^666^^^^^^=O
+"),
+    ("MB0035", "\
+A cell holding the Death Expression was leached onto another cell.
+
+Every cell holds the Death Expression until something is leached onto it. A
+cell leached onto another gives its expression away and holds the Death
+Expression again, and the Death Expression can't be leached anywhere. A cell
+whose expression has lived out its time holds it too, but that's only checked
+at runtime, and the dead-cell-read lint warns about it.
+
+Erroneous code example:
+
+    ->L\\\\|//\\\\|//\\\\|//$@->C~00~10~2^^^^^^666^^^^^^=O
+
+Fixed example:
+
+    ->L\\\\|//\\\\|//\\\\|//$@->C~00~11~2^^^^^^666^^^^^^=O
+"),
+    ("MB0036", "\
+A massacre was started by a cell that doesn't hold a rampage primitive.
+
+The first cell of a massacre chain has to hold one of the rampage primitives:
+input, output, addition or subtraction. The primitives are nothing but their
+indices, so the numbers 3, 2, 1 and 0 do just as well. A cell holding any
+other number, or the Death Expression, has nothing to go on a massacre with.
+
+Erroneous code example:
+
+    ->L\\\\|//\\\\|//\\\\|//$(->C~0->L$@->C~10~1^^^^^^666^^^^^^=M^^^^^^666^^^^^^=O
+
+Fixed example:
+
+    ->L\\\\|//\\\\|//\\\\|//${->C~0->L$@->C~10~1^^^^^^666^^^^^^=M^^^^^^666^^^^^^=O
+"),
+    ("MB0037", "\
+A massacre was given a number of cells its rampage primitive doesn't take.
+
+Input stores what the user typed in exactly 1 cell, and addition adds exactly
+2 cells. Output prints the cells in pairs, with a cell left over printed on
+its own, and subtraction subtracts every cell after the first, so they take
+as many cells as they are given. When the primitive the first cell of the
+chain holds is known, so is how many cells it takes.
+
+Erroneous code example:
+
+    ->L\\\\|//\\\\|//\\\\|//$}->C~0->L$@->C~10~1~2~3^^^^^^666^^^^^^=M^^^^^^666^^^^^^=O
+
+Fixed example:
+
+    ->L\\\\|//\\\\|//\\\\|//$}->C~0->L$@->C~10~1~2^^^^^^666^^^^^^=M^^^^^^666^^^^^^=O
+"),
+    ("MB0038", "\
+A source file isn't valid UTF-8.
+
+Mindbend source is read as UTF-8. A file is only read up to the first byte
+that isn't part of a valid UTF-8 char, since nothing after it could be shown
+where it is, so the errors in the rest of the file are only found once it's
+fixed. This usually means the file was saved in another encoding, like
+Latin-1, or isn't a Mindbend program at all.
+"),
+    ("MB0039", "\
+The code for a part of the program couldn't be generated.
+
+LLVM didn't give back something the compiler asked it for, like a parameter
+of one of the runtime routines or the result of calling one. This is a bug in
+the compiler, not in the program.
+"),
+    ("MB0040", "\
+The program is nothing but the death of the organism expression.
+
+Every program ends with the death of the organism expression,
+^^^^^^666^^^^^^=O, and one with nothing before it has nothing to compile.
+")
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::contents;
+    use crate::errors::CompileError;
+    use crate::lexer::{self, Span};
+    use crate::macros;
+    use crate::parser::Parser;
+
+    fn compile(source: &str) -> Result<(), Vec<CompileError>> {
+        let tokens = macros::expand(lexer::tokenize(source)?)?;
+        let (org_expr, _) = Parser::new(tokens).parse()?;
+        match contents::check(&org_expr) {
+            (errors, ..) if !errors.is_empty() => Err(errors),
+            _ => Ok(())
+        }
+    }
+
+    /// The indented line after the heading
+    fn example<'a>(explanation: &'a str, heading: &str) -> Option<&'a str> {
+        explanation.split(heading).nth(1)
+            .and_then(|rest| rest.lines().find(|line| line.starts_with("    ")))
+            .map(|line| line.trim())
+    }
+
+    #[test]
+    fn test_explain(){
+        let err = CompileError::DrillInCells(Span::default());
+        assert!(explain(err.code()).unwrap().starts_with("A drill expression was used in the Cells Region"));
+        assert_eq!(explain("mb0013"), explain("MB0013"));
+        assert_eq!(explain("MB9999"), None);
+        assert_eq!(explain("redundant-drill"), Some(lints::REDUNDANT_DRILL.explanation));
+    }
+
+    #[test]
+    fn test_examples(){
+        for (code, explanation) in EXPLANATIONS.iter() {
+            if let Some(erroneous) = example(explanation, "Erroneous code example:") {
+                let errors = compile(erroneous).expect_err(code);
+                assert_eq!(errors[0].code(), *code, "{}", erroneous);
+            }
+            if let Some(fixed) = example(explanation, "Fixed example:") {
+                assert_eq!(compile(fixed), Ok(()), "{}", code);
+            }
+        }
+    }
+}
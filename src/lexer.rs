@@ -1,10 +1,77 @@
-use std::str::Chars;
-use std::iter::Peekable;
-use crate::errors;
-use self::Token::*;
+use std::{fmt, fs, io, iter, str};
+use std::io::BufRead;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use crate::errors::{CompileError, DEFAULT_ERROR_LIMIT};
+use crate::source_map::{FileId, SourceMap};
+use self::TokenKind::*;
+
+/// The region of the source a token or an expression was read from.
+/// `start` and `end` are byte offsets, `end` being exclusive.
+/// `line` and `column` are where `start` is, both starting from 1.
+/// `file` is the file of the program the span is in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub column: usize,
+    pub file: FileId
+}
+
+impl Span {
+    /// A span in the root file
+    pub fn new(start: usize, end: usize, line: usize, column: usize) -> Span {
+        Span {
+            start,
+            end,
+            line,
+            column,
+            file: FileId::ROOT
+        }
+    }
+    /// The same span, but in the file
+    pub fn in_file(self, file: FileId) -> Span {
+        Span {
+            file,
+            ..self
+        }
+    }
+    /// A span from the start of self to the end of other
+    pub fn to(&self, other: Span) -> Span {
+        Span {
+            start: self.start,
+            end: other.end,
+            line: self.line,
+            column: self.column,
+            file: self.file
+        }
+    }
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+    /// An empty span where self starts
+    pub fn start_point(&self) -> Span {
+        Span::new(self.start, self.start, self.line, self.column).in_file(self.file)
+    }
+    /// An empty span where self ends.
+    /// Tokens never go past the end of a line, so the column is only off
+    /// when a span has non-ascii chars in it
+    pub fn end_point(&self) -> Span {
+        Span::new(self.end, self.end, self.line, self.column + self.len()).in_file(self.file)
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}, column {}", self.line, self.column)
+    }
+}
 
 #[derive(Debug, PartialEq, Clone)]
-pub enum Token {
+pub enum TokenKind {
     TripleSix,
     TripleSixEq,
     TripleSixEqM,
@@ -14,11 +81,27 @@ pub enum Token {
     Label(String),
     Jump(String),
     ConditionalJump(String),
+    /// The path of a file whose tokens go in place of this one. The lexer never
+    /// hands it out, since it reads the file's tokens instead
+    Include(String),
+    /// The start of a macro definition, with the macro's name and parameters
+    Macro(String, Vec<String>),
+    EndMacro,
+    /// A call of the macro with the name. The arguments are the tokens after it
+    Call(String),
+    /// Where an argument goes in the body of a macro
+    Placeholder(String),
     CellIdent(String),
     RegionIdent(String),
     PrimitiveIdent(String)
 }
 
+#[derive(Debug, PartialEq, Clone)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub span: Span
+}
+
 #[macro_export]
 macro_rules! p_ident {
     ($x:expr) => {
@@ -29,259 +112,587 @@ macro_rules! p_ident {
 #[macro_export]
 macro_rules! c_ident {
     ($x:expr) => {
-        Token::CellIdent(String::from($x))
+        TokenKind::CellIdent(String::from($x))
     }
 }
 
 #[macro_export]
 macro_rules! r_ident {
     ($x:expr) => {
-        Token::RegionIdent(String::from($x))
+        TokenKind::RegionIdent(String::from($x))
     }
 }
 
 impl Token {
+    pub fn new(kind: TokenKind, span: Span) -> Token {
+        Token {
+            kind,
+            span
+        }
+    }
+    pub fn is_region(&self) -> bool {
+        self.kind.is_region()
+    }
+}
+
+impl TokenKind {
     pub fn is_region(&self) -> bool {
         match self {
-            Token::RegionIdent(_) => true,
+            TokenKind::RegionIdent(_) => true,
             _ => false
         }
     }
 }
 
-pub fn tokenize(input: &str) -> Result<Vec<Token>, String> {
-    let mut tokens: Vec<Token> = vec![];
-    let mut input = input.chars().peekable();
-    let mut pos = 0;
-    while input.peek().is_some(){
-        let c = input.next().unwrap();
-        pos += 1;
-        match c {
-            ' ' | '\n' => return Err(errors::err_whitespace(pos)),
-            '~' => tokens.push(Token::Tilde),
-            '$' => match tokenize_primitive_ident(&mut tokens, &mut input, &mut pos){
-                Ok(()) => (),
-                Err(err) => return Err(err)
-            },
-            '^' => {
-                match tokenize_666(&mut tokens, &mut input, &mut pos){
-                    Ok(()) => (),
-                    Err(err) => return Err(err)
-                };
-            },
-            '-' => {
-                match tokenize_right_arrow_and_region_ident(&mut tokens, &mut input, &mut pos){
-                    Ok(()) => (),
-                    Err(err) => return Err(err)
-                };
-            },
-            '\\' => {
-                match tokenize_drill(&mut tokens, &mut input, &mut pos){
-                    Ok(()) => (),
-                    Err(err) => return Err(err)
-                };
-            },
-            'l' => {
-                match tokenize_label(&mut tokens, &mut input, &mut pos){
-                    Ok(()) => (),
-                    Err(err) => return Err(err)
-                };
-            },
-            'i' => {
-                match tokenize_ijmp(&mut tokens, &mut input, &mut pos){
-                    Ok(()) => (),
-                    Err(err) => return Err(err)
-                };
-            },
-            'j' => {
-                match tokenize_jmp(&mut tokens, &mut input, &mut pos){
-                    Ok(()) => (),
-                    Err(err) => return Err(err)
+/// Walks through the source, keeping track of where it is.
+/// The chars of the token being read are remembered, so the cursor can go back to
+/// where an error was found in it
+struct Cursor<R: BufRead> {
+    reader: R,
+    /// Chars to be read again before the rest of the reader, with their lengths in bytes
+    pending: VecDeque<(char, usize)>,
+    /// The chars read since the start of the token, with where each one started
+    token_chars: Vec<(Span, char, usize)>,
+    /// The error the reader gave, if it gave one. Nothing else is read after it
+    read_error: Option<io::Error>,
+    /// Where the source stops being UTF-8, if it does. Nothing else is read after it,
+    /// since the spans of anything after it wouldn't point into the source as it's shown
+    invalid_utf8: Option<Span>,
+    /// The file being read
+    file: FileId,
+    offset: usize,
+    line: usize,
+    column: usize
+}
+
+impl<R: BufRead> Cursor<R> {
+    fn new(reader: R, file: FileId) -> Cursor<R> {
+        Cursor {
+            reader,
+            pending: VecDeque::new(),
+            token_chars: vec![],
+            read_error: None,
+            invalid_utf8: None,
+            file,
+            offset: 0,
+            line: 1,
+            column: 1
+        }
+    }
+    fn peek(&mut self) -> Option<char> {
+        if self.pending.is_empty() {
+            let decoded = self.decode_char()?;
+            self.pending.push_back(decoded);
+        }
+        self.pending.front().map(|(c, _)| *c)
+    }
+    fn next(&mut self) -> Option<char> {
+        self.peek()?;
+        let (c, len) = self.pending.pop_front().unwrap();
+        self.token_chars.push((self.mark(), c, len));
+        self.offset += len;
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        Some(c)
+    }
+    /// Forgets the chars read so far, since a new token is starting
+    fn start_token(&mut self){
+        self.token_chars.clear();
+    }
+    /// Goes back over the chars of the current token read from the offset on
+    fn go_back_to(&mut self, offset: usize){
+        while let Some((mark, c, len)) = self.token_chars.pop() {
+            if mark.start < offset {
+                self.token_chars.push((mark, c, len));
+                break;
+            }
+            self.pending.push_front((c, len));
+            self.offset = mark.start;
+            self.line = mark.line;
+            self.column = mark.column;
+        }
+    }
+    /// Reads the next char from the reader, with its length in bytes.
+    /// Reading stops at the first byte that isn't valid UTF-8
+    fn decode_char(&mut self) -> Option<(char, usize)> {
+        if self.read_error.is_some() || self.invalid_utf8.is_some() {
+            return None;
+        }
+        let first = self.read_byte()?;
+        let width = match first {
+            0x00..=0x7f => 1,
+            0xc0..=0xdf => 2,
+            0xe0..=0xef => 3,
+            0xf0..=0xf7 => 4,
+            _ => return self.stop_at_invalid_utf8()
+        };
+        let mut bytes = [first, 0, 0, 0];
+        for i in 1..width {
+            match self.peek_byte() {
+                Some(byte) if byte & 0xc0 == 0x80 => {
+                    bytes[i] = byte;
+                    self.reader.consume(1);
+                }
+                _ => return self.stop_at_invalid_utf8()
+            };
+        }
+        match str::from_utf8(&bytes[..width]) {
+            Ok(decoded) => decoded.chars().next().map(|c| (c, width)),
+            Err(_) => self.stop_at_invalid_utf8()
+        }
+    }
+    /// Marks where the source stops being UTF-8, after the chars read ahead
+    fn stop_at_invalid_utf8(&mut self) -> Option<(char, usize)> {
+        let mut mark = self.mark();
+        for (c, len) in self.pending.iter() {
+            mark.start += len;
+            if *c == '\n' {
+                mark.line += 1;
+                mark.column = 1;
+            } else {
+                mark.column += 1;
+            }
+        }
+        mark.end = mark.start;
+        self.invalid_utf8 = Some(mark);
+        None
+    }
+    fn peek_byte(&mut self) -> Option<u8> {
+        loop {
+            match self.reader.fill_buf() {
+                Ok(buf) => return buf.first().copied(),
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => {
+                    self.read_error = Some(err);
+                    return None;
+                }
+            };
+        }
+    }
+    fn read_byte(&mut self) -> Option<u8> {
+        let byte = self.peek_byte()?;
+        self.reader.consume(1);
+        Some(byte)
+    }
+    /// An empty span at the current position
+    fn mark(&self) -> Span {
+        Span::new(self.offset, self.offset, self.line, self.column).in_file(self.file)
+    }
+    /// The span from the mark to the current position
+    fn span_from(&self, mark: Span) -> Span {
+        Span::new(mark.start, self.offset, mark.line, mark.column).in_file(self.file)
+    }
+    /// Consumes the next char, returning it with its span
+    fn next_with_span(&mut self) -> Option<(char, Span)> {
+        let mark = self.mark();
+        let c = self.next()?;
+        Some((c, self.span_from(mark)))
+    }
+}
+
+/// Reads tokens from the source as they are asked for, so that only the token being
+/// read has to be in memory, no matter how big the source is.
+/// After an error, it skips to the next token, so all the errors can be found.
+/// Include expressions are replaced by the tokens of the files they include
+pub struct Lexer<R: BufRead> {
+    input: Cursor<R>,
+    /// The files being included, the innermost last. Tokens are read from the innermost one
+    includes: Vec<Include>,
+    source_map: Rc<RefCell<SourceMap>>,
+    /// Where included files are looked for when they aren't next to the file including them
+    search_path: Vec<PathBuf>,
+    /// Tokens and errors ready to be handed out
+    ready: VecDeque<Result<Token, CompileError>>,
+    /// The last token read. It's held back until the next one is read, since the
+    /// death of the organism expression at the end isn't handed out
+    last_token: Option<Token>,
+    /// The span of the last token read from the root file
+    last_root_span: Option<Span>,
+    done: bool
+}
+
+/// A file being read in place of an include expression
+struct Include {
+    input: Cursor<Box<dyn BufRead>>,
+    /// The canonical path of the file, to find files that end up including themselves
+    path: PathBuf
+}
+
+impl<R: BufRead> Lexer<R> {
+    pub fn new(reader: R) -> Lexer<R> {
+        let source_map = SourceMap::from_source("<input>", "");
+        Lexer::with_source_map(reader, Rc::new(RefCell::new(source_map)))
+    }
+    /// A lexer for the root file of the source map, which must be its first file.
+    /// The files it includes are added to the source map as they are read
+    pub fn with_source_map(reader: R, source_map: Rc<RefCell<SourceMap>>) -> Lexer<R> {
+        Lexer {
+            input: Cursor::new(reader, FileId::ROOT),
+            includes: vec![],
+            source_map,
+            search_path: vec![],
+            ready: VecDeque::new(),
+            last_token: None,
+            last_root_span: None,
+            done: false
+        }
+    }
+    /// Sets the directories, in the order they are searched, included files are looked
+    /// for in when they aren't found next to the file including them
+    pub fn with_search_path(mut self, search_path: Vec<PathBuf>) -> Lexer<R> {
+        self.search_path = search_path;
+        self
+    }
+    /// Reads the next token, or finds the next error
+    fn lex(&mut self){
+        let result = match self.includes.last_mut() {
+            Some(include) => lex_token(&mut include.input),
+            None => lex_token(&mut self.input)
+        };
+        let tokens = match result {
+            Some(Ok(tokens)) => tokens,
+            Some(Err(err)) => {
+                self.ready.push_back(Err(err));
+                return;
+            }
+            None => {
+                self.end_file();
+                return;
+            }
+        };
+        for token in tokens {
+            if token.span.file == FileId::ROOT {
+                self.last_root_span = Some(token.span);
+            }
+            if let Include(path) = &token.kind {
+                match self.open_include(path, token.span) {
+                    Ok(include) => self.includes.push(include),
+                    Err(err) => self.ready.push_back(Err(err))
                 };
-            },
-            '0'..='9' | 'A'..='E' => tokens.push(Token::CellIdent(c.to_string())),
-            _ => return Err(errors::err_unrecognized_token(pos))
+            } else if let Some(prev_token) = self.last_token.replace(token) {
+                self.ready.push_back(Ok(prev_token));
+            }
         }
     }
-    if tokens.pop().unwrap() != Token::TripleSixEqO {
-        Err(errors::err_org_expr_must_end_in_death())
-    } else {
+    /// Goes back to the file that included the one that ended, or finishes if it was
+    /// the root file
+    fn end_file(&mut self){
+        let mut include = match self.includes.pop() {
+            Some(include) => include,
+            None => return self.finish()
+        };
+        if let Some(err) = include.input.read_error.take() {
+            let name = self.source_map.borrow().name(include.input.file).to_string();
+            self.ready.push_back(Err(CompileError::Read(format!("{}: {}", name, err))));
+        }
+        if let Some(span) = include.input.invalid_utf8.take() {
+            self.ready.push_back(Err(CompileError::InvalidUtf8(span)));
+        }
+    }
+    /// Checks that the root file ended in the death of the organism expression.
+    /// Included files don't have to end in it
+    fn finish(&mut self){
+        self.done = true;
+        if let Some(err) = self.input.read_error.take() {
+            self.ready.push_back(Err(CompileError::Read(err.to_string())));
+            return;
+        }
+        if let Some(span) = self.input.invalid_utf8.take() {
+            self.ready.push_back(Err(CompileError::InvalidUtf8(span)));
+            return;
+        }
+        match (self.last_token.take(), self.last_root_span) {
+            (Some(Token { kind: TripleSixEqO, span }), _) if span.file == FileId::ROOT => (),
+            (_, Some(span)) => self.ready.push_back(Err(CompileError::OrgExprMustEndInDeath(span))),
+            (_, None) => self.ready.push_back(Err(CompileError::OrgExprMustEndInDeath(self.input.mark())))
+        };
+    }
+    /// Finds the file the include expression at the span names and starts reading it.
+    /// The file is looked for next to the file including it, then in the search path
+    fn open_include(&self, name: &str, span: Span) -> Result<Include, CompileError> {
+        let including_dir = self.source_map.borrow().path(span.file)
+            .and_then(|path| path.parent())
+            .map(Path::to_path_buf)
+            .unwrap_or_default();
+        let path = iter::once(&including_dir)
+            .chain(self.search_path.iter())
+            .map(|dir| dir.join(name))
+            .find(|path| path.is_file())
+            .ok_or_else(|| CompileError::IncludeNotFound(span, name.to_string()))?;
+        let read_error = |err: io::Error| CompileError::Read(format!("{}: {}", path.display(), err));
+        let canonical_path = path.canonicalize().map_err(read_error)?;
+        let root_path = self.source_map.borrow().path(FileId::ROOT)
+            .and_then(|path| path.canonicalize().ok());
+        let is_cycle = root_path.as_ref() == Some(&canonical_path) ||
+            self.includes.iter().any(|include| include.path == canonical_path);
+        if is_cycle {
+            return Err(CompileError::IncludeCycle(span, name.to_string()));
+        }
+        let file = fs::File::open(&path).map_err(read_error)?;
+        let file_id = self.source_map.borrow_mut().add_file(&path);
+        Ok(Include {
+            input: Cursor::new(Box::new(io::BufReader::new(file)), file_id),
+            path: canonical_path
+        })
+    }
+}
+
+/// Reads the next token from the input, or finds the next error.
+/// None at the end of the input
+fn lex_token<R: BufRead>(input: &mut Cursor<R>) -> Option<Result<Vec<Token>, CompileError>> {
+    input.start_token();
+    input.peek()?;
+    let mut tokens: Vec<Token> = vec![];
+    let token_offset = input.offset;
+    let start = input.mark();
+    let c = input.next().unwrap();
+    let result = match c {
+        ' ' | '\n' => Err(CompileError::Whitespace(input.span_from(start))),
+        '~' => {
+            tokens.push(Token::new(Tilde, input.span_from(start)));
+            Ok(())
+        },
+        '$' => tokenize_primitive_ident(&mut tokens, input, start),
+        '^' => tokenize_666(&mut tokens, input, start),
+        '-' => tokenize_right_arrow_and_region_ident(&mut tokens, input, start),
+        '\\' => tokenize_drill(&mut tokens, input, start),
+        'l' => tokenize_label(&mut tokens, input, start),
+        'i' if input.peek() == Some('n') => tokenize_include(&mut tokens, input, start),
+        'i' => tokenize_ijmp(&mut tokens, input, start),
+        'j' => tokenize_jmp(&mut tokens, input, start),
+        'm' => tokenize_macro(&mut tokens, input, start),
+        'e' => tokenize_endmacro(&mut tokens, input, start),
+        'c' => tokenize_call(&mut tokens, input, start),
+        '<' => tokenize_placeholder(&mut tokens, input, start),
+        '0'..='9' | 'A'..='E' => {
+            tokens.push(Token::new(CellIdent(c.to_string()), input.span_from(start)));
+            Ok(())
+        },
+        _ => Err(CompileError::UnrecognizedToken(input.span_from(start)))
+    };
+    if let Err(err) = result {
+        recover(input, token_offset, &err);
+        return Some(Err(err));
+    }
+    Some(Ok(tokens))
+}
+
+impl<R: BufRead> Iterator for Lexer<R> {
+    type Item = Result<Token, CompileError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.ready.is_empty() && !self.done {
+            self.lex();
+        }
+        self.ready.pop_front()
+    }
+}
+
+pub fn tokenize(input: &str) -> Result<Vec<Token>, Vec<CompileError>> {
+    tokenize_with_error_limit(input, DEFAULT_ERROR_LIMIT)
+}
+
+/// Tokenizes the input, skipping to the next token after each error so that
+/// all the errors are reported, until there are error_limit of them
+pub fn tokenize_with_error_limit(input: &str, error_limit: usize) -> Result<Vec<Token>, Vec<CompileError>> {
+    collect_tokens(Lexer::new(input.as_bytes()), error_limit)
+}
+
+/// Reads the whole stream of tokens, or all its errors until there are error_limit of them
+pub fn collect_tokens<I: Iterator<Item=Result<Token, CompileError>>>(stream: I, error_limit: usize) -> Result<Vec<Token>, Vec<CompileError>> {
+    let mut tokens: Vec<Token> = vec![];
+    let mut errors: Vec<CompileError> = vec![];
+    for result in stream {
+        match result {
+            Ok(token) => tokens.push(token),
+            Err(err) => errors.push(err)
+        };
+        if errors.len() >= error_limit {
+            break;
+        }
+    }
+    if errors.is_empty() {
         Ok(tokens)
+    } else {
+        Err(errors)
+    }
+}
+
+/// Moves the cursor back from where it stopped in the token that caused the error to
+/// where the error was found, then on to the start of the next token.
+/// The offending char is skipped if it's the token's first one, so the same error
+/// won't be found again
+fn recover<R: BufRead>(input: &mut Cursor<R>, token_offset: usize, err: &CompileError) {
+    let err_offset = err.span().map(|span| span.start).unwrap_or(token_offset);
+    input.go_back_to(err_offset);
+    if input.offset == token_offset {
+        input.next();
+    }
+    while let Some(c) = input.peek() {
+        if is_token_start(c) {
+            break;
+        }
+        input.next();
+    }
+}
+
+fn is_token_start(c: char) -> bool {
+    match c {
+        '~' | '$' | '^' | '-' | '\\' | 'l' | 'i' | 'j' | 'm' | 'e' | 'c' | '<' |
+        '0'..='9' | 'A'..='E' => true,
+        _ => false
     }
 }
 
-fn tokenize_primitive_ident(tokens: &mut Vec<Token>, input: &mut Peekable<Chars>, pos: &mut i32) -> Result<(), String> {
+fn tokenize_primitive_ident<R: BufRead>(tokens: &mut Vec<Token>, input: &mut Cursor<R>, start: Span) -> Result<(), CompileError> {
     if input.peek().is_none(){
-        return Err(errors::err_expected(*pos, "primitive identifier or index"));
+        return Err(CompileError::Expected(input.mark(), "primitive identifier or index".to_string()));
     }
-    *pos += 1;
-    let c = input.next().unwrap();
+    let (c, c_span) = input.next_with_span().unwrap();
     match c {
         '!' | '@' | '#' | '+' | '%' | '`' | '&' | '*' | '(' | ')' |
         '}' | '{' | '0'..='9' | 'A'..='D' => {
-            tokens.push(p_ident!(c));
+            tokens.push(Token::new(p_ident!(c), input.span_from(start)));
             Ok(())
         },
         '>' => {
             if input.peek().is_some(){
-                *pos += 1;
-                let next_char = input.next().unwrap();
+                let (next_char, next_char_span) = input.next_with_span().unwrap();
                 match next_char {
                     '<' => {
-                        tokens.push(p_ident!("><"));
+                        tokens.push(Token::new(p_ident!("><"), input.span_from(start)));
                         Ok(())
                     },
-                    _ => Err(errors::err_unrecognized_token(*pos))
+                    _ => Err(CompileError::UnrecognizedToken(next_char_span))
                 }
             } else {
-                Err(errors::err_unrecognized_token(*pos))
+                Err(CompileError::UnrecognizedToken(c_span))
             }
         },
         '<' => {
             if input.peek().is_some(){
-                *pos += 1;
-                let next_char = input.next().unwrap();
+                let (next_char, next_char_span) = input.next_with_span().unwrap();
                 match next_char {
                     '>' => {
-                        tokens.push(p_ident!("<>"));
+                        tokens.push(Token::new(p_ident!("<>"), input.span_from(start)));
                         Ok(())
                     },
-                    _ => Err(errors::err_unrecognized_token(*pos))
+                    _ => Err(CompileError::UnrecognizedToken(next_char_span))
                 }
             } else {
-                Err(errors::err_unrecognized_token(*pos))
+                Err(CompileError::UnrecognizedToken(c_span))
             }
         },
-        _ => Err(errors::err_invalid_primitive(*pos))
+        _ => Err(CompileError::InvalidPrimitive(c_span))
     }
 }
 
-fn tokenize_666(tokens: &mut Vec<Token>, mut input: &mut Peekable<Chars>, mut pos: &mut i32) -> Result<(), String> {
+fn tokenize_666<R: BufRead>(tokens: &mut Vec<Token>, input: &mut Cursor<R>, start: Span) -> Result<(), CompileError> {
     // 5 because the first has already been checked in the main loop    
-    match check_for_n_carets(&mut input, &mut pos, 5){
+    match check_for_n_chars(input, '^', 5){
         Ok(()) => (),
         Err(err) => return Err(err)
     };
-    match check_for_3_sixes(&mut input, &mut pos){
+    match check_for_n_chars(input, '6', 3){
         Ok(()) => (),
         Err(err) => return Err(err)
     };
-    match check_for_n_carets(&mut input, &mut pos, 6){
+    match check_for_n_chars(input, '^', 6){
         Ok(()) => (),
         Err(err) => return Err(err)
     };
-    if input.peek().is_some() && *input.peek().unwrap() == '=' {
-        *pos += 1;
+    if input.peek().is_some() && input.peek().unwrap() == '=' {
         input.next();
-        if input.peek().is_some(){
-            let next_token_ref = input.peek().unwrap();
-            if *next_token_ref == 'M' {
+        match input.peek() {
+            Some('M') => {
                 input.next();
-                tokens.push(Token::TripleSixEqM);
-            } else if *next_token_ref == 'O' {
+                tokens.push(Token::new(TripleSixEqM, input.span_from(start)));
+            },
+            Some('O') => {
                 input.next();
-                tokens.push(Token::TripleSixEqO);
-            } else {
-                tokens.push(Token::TripleSixEq);
-            }
-        } else {
-            tokens.push(Token::TripleSixEq);
-        }
+                tokens.push(Token::new(TripleSixEqO, input.span_from(start)));
+            },
+            _ => tokens.push(Token::new(TripleSixEq, input.span_from(start)))
+        };
     } else {
-        tokens.push(Token::TripleSix);
+        tokens.push(Token::new(TripleSix, input.span_from(start)));
     }
     Ok(())
 }
 
-fn check_for_n_carets(input: &mut &mut Peekable<Chars>, pos: &mut &mut i32, n: i32) -> Result<(), String> {
+fn check_for_n_chars<R: BufRead>(input: &mut Cursor<R>, expected_char: char, n: i32) -> Result<(), CompileError> {
     for _ in 0..n {
         if input.peek().is_none(){
-            return Err(errors::err_unrecognized_token(**pos));
+            return Err(CompileError::UnrecognizedToken(input.mark()));
         }
-        **pos += 1;
-        let next_char = input.next().unwrap();
-        if next_char != '^' {
-            return Err(errors::err_unrecognized_token(**pos));
+        let (next_char, next_char_span) = input.next_with_span().unwrap();
+        if next_char != expected_char {
+            return Err(CompileError::UnrecognizedToken(next_char_span));
         }
     }
     Ok(())
 }
 
-fn check_for_3_sixes(input: &mut &mut Peekable<Chars>, pos: &mut &mut i32) -> Result<(), String> {
-    for _ in 0..3 {
-        if input.peek().is_none(){
-            return Err(errors::err_unrecognized_token(**pos));
-        }
-        **pos += 1;
-        let next_char = input.next().unwrap();
-        if next_char != '6' {
-            return Err(errors::err_unrecognized_token(**pos));
-        }
-    }
-    Ok(())
-}
-
-fn tokenize_right_arrow_and_region_ident(tokens: &mut Vec<Token>, input: &mut Peekable<Chars>, pos: &mut i32) -> Result<(), String> {
+fn tokenize_right_arrow_and_region_ident<R: BufRead>(tokens: &mut Vec<Token>, input: &mut Cursor<R>, start: Span) -> Result<(), CompileError> {
     if input.peek().is_some(){
-        *pos += 1;
-        match input.next(){
-            Some('>') => {
+        match input.next_with_span(){
+            Some(('>', _)) => {
                 if input.peek().is_some() {
                     let next_char = input.peek().unwrap();
-                    if *next_char == 'L' || *next_char == 'C' {
-                        *pos += 1;
-                        let next_char = input.next().unwrap();
-                        tokens.push(Token::RegionIdent(next_char.to_string()));
+                    if next_char == 'L' || next_char == 'C' {
+                        input.next();
+                        tokens.push(Token::new(RegionIdent(next_char.to_string()), input.span_from(start)));
                     }
                 }
             },
-            _ => return Err(errors::err_unrecognized_token(*pos))
+            Some((_, span)) => return Err(CompileError::UnrecognizedToken(span)),
+            None => unreachable!()
         }
     } else {
-        return Err(errors::err_unrecognized_token(*pos));
+        return Err(CompileError::UnrecognizedToken(start));
     }
     Ok(())
 }
 
-fn tokenize_drill(tokens: &mut Vec<Token>, input: &mut Peekable<Chars>, pos: &mut i32) -> Result<(), String> {
-    match input.next(){
-        Some('\\') => (),
-        _ => return Err(errors::err_unrecognized_token(*pos))
+fn tokenize_drill<R: BufRead>(tokens: &mut Vec<Token>, input: &mut Cursor<R>, start: Span) -> Result<(), CompileError> {
+    match input.next_with_span(){
+        Some(('\\', _)) => (),
+        Some((_, span)) => return Err(CompileError::UnrecognizedToken(span)),
+        None => return Err(CompileError::UnrecognizedToken(input.mark()))
     };
-    *pos += 1;
-    match input.next(){
-        Some('|') => (),
-        _ => return Err(errors::err_unrecognized_token(*pos))
+    match input.next_with_span(){
+        Some(('|', _)) => (),
+        Some((_, span)) => return Err(CompileError::UnrecognizedToken(span)),
+        None => return Err(CompileError::UnrecognizedToken(input.mark()))
     };
-    *pos += 1;
-    match input.next(){
-        Some('/') => (),
-        _ => return Err(errors::err_unrecognized_token(*pos))
+    match input.next_with_span(){
+        Some(('/', _)) => (),
+        Some((_, span)) => return Err(CompileError::UnrecognizedToken(span)),
+        None => return Err(CompileError::UnrecognizedToken(input.mark()))
     };
-    *pos += 1;
-    match input.next(){
-        Some('/') => (),
-        _ => return Err(errors::err_unrecognized_token(*pos))
+    match input.next_with_span(){
+        Some(('/', _)) => (),
+        Some((_, span)) => return Err(CompileError::UnrecognizedToken(span)),
+        None => return Err(CompileError::UnrecognizedToken(input.mark()))
     };
-    tokens.push(Token::Drill);
+    tokens.push(Token::new(Drill, input.span_from(start)));
     Ok(())
 }
 
-fn tokenize_jmp(tokens: &mut Vec<Token>, input: &mut Peekable<Chars>, pos: &mut i32) -> Result<(), String> {
+fn tokenize_jmp<R: BufRead>(tokens: &mut Vec<Token>, input: &mut Cursor<R>, start: Span) -> Result<(), CompileError> {
     let expected_chars = ['m', 'p', ':'];
     for expected_char in expected_chars {
         if input.peek().is_none(){
-            return Err(errors::err_expected(*pos, expected_char));
+            return Err(CompileError::Expected(input.mark(), expected_char.to_string()));
+        }
+        let (c, span) = input.next_with_span().unwrap();
+        if c != expected_char {
+            return Err(CompileError::UnrecognizedToken(span));
         }
-        match input.next(){
-            Some(c) => {
-                if c != expected_char {
-                    return Err(errors::err_unrecognized_token(*pos));
-                }
-            },
-            _ => return Err(errors::err_unrecognized_token(*pos))
-        };
-        *pos += 1;
     }
     let mut label = String::new();
     while input.peek().is_some(){
@@ -289,62 +700,118 @@ fn tokenize_jmp(tokens: &mut Vec<Token>, input: &mut Peekable<Chars>, pos: &mut
         if c != ':' {
             label.push(c);
         } else {
-            tokens.push(Token::Jump(label));
+            tokens.push(Token::new(Jump(label), input.span_from(start)));
             return Ok(());
         }
-        *pos += 1;
     }
-    return Err(errors::err_expected(*pos, ":"))
+    return Err(CompileError::Expected(input.mark(), ":".to_string()))
 }
 
-fn tokenize_label(tokens: &mut Vec<Token>, input: &mut Peekable<Chars>, pos: &mut i32) -> Result<(), String> {
+fn tokenize_label<R: BufRead>(tokens: &mut Vec<Token>, input: &mut Cursor<R>, start: Span) -> Result<(), CompileError> {
     let expected_chars = String::from("abel:");
     let expected_chars = expected_chars.chars();
     for expected_char in expected_chars {
         if input.peek().is_none(){
-            return Err(errors::err_expected(*pos, expected_char));
+            return Err(CompileError::Expected(input.mark(), expected_char.to_string()));
+        }
+        let (c, span) = input.next_with_span().unwrap();
+        if c != expected_char {
+            return Err(CompileError::Expected(span, expected_char.to_string()));
         }
-        match input.next(){
-            Some(c) => {
-                if c != expected_char {
-                    return Err(errors::err_expected(*pos, expected_char));
-                }
-            },
-            _ => return Err(errors::err_expected(*pos, expected_char))
-        };
-        *pos += 1;
     }
     let mut label = String::new();
     while input.peek().is_some(){
         let next_char = input.next().unwrap();
         if next_char == ':' {
-            tokens.push(Token::Label(label));
+            tokens.push(Token::new(Label(label), input.span_from(start)));
             return Ok(());
         } else {
             label.push(next_char);
         }
-        *pos += 1;
     }
-    Err(errors::err_expected(*pos, ":"))
+    Err(CompileError::Expected(input.mark(), ":".to_string()))
+}
+
+fn tokenize_include<R: BufRead>(tokens: &mut Vec<Token>, input: &mut Cursor<R>, start: Span) -> Result<(), CompileError> {
+    expect_chars(input, "nclude:")?;
+    let path = read_until(input, ':')?;
+    tokens.push(Token::new(Include(path), input.span_from(start)));
+    Ok(())
+}
+
+fn tokenize_macro<R: BufRead>(tokens: &mut Vec<Token>, input: &mut Cursor<R>, start: Span) -> Result<(), CompileError> {
+    expect_chars(input, "acro:")?;
+    let name = read_until(input, ':')?;
+    let params_start = input.mark();
+    let params = read_until(input, ':')?;
+    let params: Vec<String> = if params.is_empty() {
+        vec![]
+    } else {
+        params.split(',').map(String::from).collect()
+    };
+    if params.iter().any(|param| param.is_empty()) {
+        return Err(CompileError::Expected(input.span_from(params_start), "parameter names between the commas".to_string()));
+    }
+    tokens.push(Token::new(Macro(name, params), input.span_from(start)));
+    Ok(())
+}
+
+fn tokenize_endmacro<R: BufRead>(tokens: &mut Vec<Token>, input: &mut Cursor<R>, start: Span) -> Result<(), CompileError> {
+    expect_chars(input, "ndmacro:")?;
+    tokens.push(Token::new(EndMacro, input.span_from(start)));
+    Ok(())
+}
+
+fn tokenize_call<R: BufRead>(tokens: &mut Vec<Token>, input: &mut Cursor<R>, start: Span) -> Result<(), CompileError> {
+    expect_chars(input, "all:")?;
+    let name = read_until(input, ':')?;
+    tokens.push(Token::new(Call(name), input.span_from(start)));
+    Ok(())
+}
+
+fn tokenize_placeholder<R: BufRead>(tokens: &mut Vec<Token>, input: &mut Cursor<R>, start: Span) -> Result<(), CompileError> {
+    let name = read_until(input, '>')?;
+    tokens.push(Token::new(Placeholder(name), input.span_from(start)));
+    Ok(())
+}
+
+/// Consumes the chars, which must come next
+fn expect_chars<R: BufRead>(input: &mut Cursor<R>, expected_chars: &str) -> Result<(), CompileError> {
+    for expected_char in expected_chars.chars() {
+        if input.peek().is_none(){
+            return Err(CompileError::Expected(input.mark(), expected_char.to_string()));
+        }
+        let (c, span) = input.next_with_span().unwrap();
+        if c != expected_char {
+            return Err(CompileError::UnrecognizedToken(span));
+        }
+    }
+    Ok(())
+}
+
+/// Consumes the chars up to and including the end char, returning the ones before it
+fn read_until<R: BufRead>(input: &mut Cursor<R>, end: char) -> Result<String, CompileError> {
+    let mut read = String::new();
+    while let Some(c) = input.next() {
+        if c == end {
+            return Ok(read);
+        }
+        read.push(c);
+    }
+    Err(CompileError::Expected(input.mark(), end.to_string()))
 }
 
-fn tokenize_ijmp(tokens: &mut Vec<Token>, input: &mut Peekable<Chars>, pos: &mut i32) -> Result<(), String> {
-    *pos += 1;
+fn tokenize_ijmp<R: BufRead>(tokens: &mut Vec<Token>, input: &mut Cursor<R>, start: Span) -> Result<(), CompileError> {
     let expected_chars = ['j', 'm', 'p', ':'];
 
     for expected_char in expected_chars {
         if input.peek().is_none(){
-            return Err(errors::err_expected(*pos, expected_char));
+            return Err(CompileError::Expected(input.mark(), expected_char.to_string()));
+        }
+        let (c, span) = input.next_with_span().unwrap();
+        if c != expected_char {
+            return Err(CompileError::UnrecognizedToken(span));
         }
-        match input.next(){
-            Some(c) => {
-                if c != expected_char {
-                    return Err(errors::err_unrecognized_token(*pos));
-                }
-            },
-            _ => return Err(errors::err_unrecognized_token(*pos))
-        };
-        *pos += 1;
     }
     let mut label = String::new();
     while input.peek().is_some(){
@@ -352,31 +819,33 @@ fn tokenize_ijmp(tokens: &mut Vec<Token>, input: &mut Peekable<Chars>, pos: &mut
         if c != ':' {
             label.push(c);
         } else {
-            tokens.push(Token::ConditionalJump(label));
+            tokens.push(Token::new(ConditionalJump(label), input.span_from(start)));
             return Ok(());
         }
-        *pos += 1;
     }
-    return Err(errors::err_expected(*pos, ":"))
+    return Err(CompileError::Expected(input.mark(), ":".to_string()))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::{r_ident, p_ident, c_ident};
+    fn kinds(tokens: Vec<Token>) -> Vec<TokenKind> {
+        tokens.into_iter().map(|token| token.kind).collect()
+    }
     #[test]
     fn test_valid1(){
         let input = r"$!$@$#$+$%$`$&$*$($)$<>$><${$}label:hello:^^^^^^666^^^^^^^^^^^^666^^^^^^=->L~\\|//jmp:hello:ijmp:hello:^^^^^^666^^^^^^=M^^^^^^666^^^^^^=O";
-        let result = tokenize(input);
+        let result = tokenize(input).map(kinds);
         let expected_result = Ok(vec![
             p_ident!("!"), p_ident!("@"), p_ident!("#"),
             p_ident!("+"), p_ident!("%"), p_ident!("`"),
             p_ident!("&"), p_ident!("*"), p_ident!("("),
             p_ident!(")"), p_ident!("<>"), p_ident!("><"),
-            p_ident!("{"), p_ident!("}"), Token::Label(format!("hello")),
-            Token::TripleSix, Token::TripleSixEq, r_ident!("L"),
-            Token::Tilde, Token::Drill, Token::Jump(format!("hello")),
-            Token::ConditionalJump(format!("hello")), Token::TripleSixEqM
+            p_ident!("{"), p_ident!("}"), Label(format!("hello")),
+            TripleSix, TripleSixEq, r_ident!("L"),
+            Tilde, Drill, Jump(format!("hello")),
+            ConditionalJump(format!("hello")), TripleSixEqM
         ]);
         assert!(result.is_ok());
         assert_eq!(result, expected_result);
@@ -384,7 +853,7 @@ mod tests {
     #[test]
     fn test_valid2(){
         let input = "0123456789ABCDE^^^^^^666^^^^^^=O";
-        let result = tokenize(input);
+        let result = tokenize(input).map(kinds);
         let expected_result = Ok(vec![
             c_ident!('0'), c_ident!('1'), c_ident!('2'), c_ident!('3'),
             c_ident!('4'), c_ident!('5'), c_ident!('6'), c_ident!('7'),
@@ -397,7 +866,7 @@ mod tests {
     #[test]
     fn test_valid3(){
         let input = "->C->L^^^^^^666^^^^^^=O";
-        let result = tokenize(input);
+        let result = tokenize(input).map(kinds);
         let expected_result = Ok(vec![
             r_ident!('C'), r_ident!('L')
         ]);
@@ -407,7 +876,7 @@ mod tests {
     #[test]
     fn test_valid4(){
         let input = "C^^^^^^666^^^^^^=O";
-        let result = tokenize(input);
+        let result = tokenize(input).map(kinds);
         let expected_result = Ok(vec![
             c_ident!('C')
         ]);
@@ -418,14 +887,14 @@ mod tests {
     fn test_valid5(){
         let input = "L^^^^^^666^^^^^^=O";
         let result = tokenize(input);
-        let expected_result = Err(errors::err_unrecognized_token(1));
+        let expected_result = Err(vec![CompileError::UnrecognizedToken(Span::new(0, 1, 1, 1))]);
         assert!(result.is_err());
         assert_eq!(result, expected_result);
     }
     #[test]
     fn test_valid6(){
         let input = "$0$1$2$3$4$5$6$7$8$9$A$B$C$D^^^^^^666^^^^^^=O";
-        let result = tokenize(input);
+        let result = tokenize(input).map(kinds);
         let expected_result = Ok(vec![
             p_ident!('0'), p_ident!('1'), p_ident!('2'), p_ident!('3'),
             p_ident!('4'), p_ident!('5'), p_ident!('6'), p_ident!('7'),
@@ -438,11 +907,11 @@ mod tests {
     #[test]
     fn test_valid7(){
         let input = r"->L\\|//\\|//\\|//$`->C~0->L$><->C~10~1^^^^^^666^^^^^^=O";
-        let result = tokenize(input);
+        let result = tokenize(input).map(kinds);
         let expected_result = Ok(vec![
-            r_ident!("L"), Token::Drill, Token::Drill, Token::Drill, p_ident!("`"),
-            r_ident!("C"), Token::Tilde, c_ident!("0"), r_ident!("L"), p_ident!("><"),
-            r_ident!("C"), Token::Tilde, c_ident!("1"), c_ident!("0"), Token::Tilde, c_ident!("1")
+            r_ident!("L"), Drill, Drill, Drill, p_ident!("`"),
+            r_ident!("C"), Tilde, c_ident!("0"), r_ident!("L"), p_ident!("><"),
+            r_ident!("C"), Tilde, c_ident!("1"), c_ident!("0"), Tilde, c_ident!("1")
         ]);
         assert!(result.is_ok());
         assert_eq!(result, expected_result);
@@ -450,18 +919,18 @@ mod tests {
     #[test]
     fn test_valid8(){
         let input = r"label:hello:->L\\|//\\|//\\|//$+->C~0->L$(->C~10~2->L\\|//->C1~32~4->L\\|//->C3~54~6->L\\|//->C5~76~8->L$><->C~99~7~8^^^^^^666^^^^^^=Mjmp:hello:^^^^^^666^^^^^^=O";
-        let result = tokenize(input);
+        let result = tokenize(input).map(kinds);
         let expected_result = Ok(vec![
-            Token::Label(format!("hello")), r_ident!("L"), Token::Drill, Token::Drill,
-            Token::Drill, p_ident!("+"), r_ident!("C"), Token::Tilde, c_ident!("0"), r_ident!("L"),
-            p_ident!("("), r_ident!("C"), Token::Tilde, c_ident!("1"), c_ident!("0"), Token::Tilde,
-            c_ident!("2"), r_ident!("L"), Token::Drill, r_ident!("C"), c_ident!("1"), Token::Tilde,
-            c_ident!("3"), c_ident!("2"), Token::Tilde, c_ident!("4"), r_ident!("L"), Token::Drill,
-            r_ident!("C"), c_ident!("3"), Token::Tilde, c_ident!("5"), c_ident!("4"), Token::Tilde,
-            c_ident!("6"), r_ident!("L"), Token::Drill, r_ident!("C"), c_ident!("5"), Token::Tilde,
-            c_ident!("7"), c_ident!("6"), Token::Tilde, c_ident!("8"), r_ident!("L"), p_ident!("><"),
-            r_ident!("C"), Token::Tilde, c_ident!("9"), c_ident!("9"), Token::Tilde, c_ident!("7"),
-            Token::Tilde, c_ident!("8"), TripleSixEqM, Jump(format!("hello"))
+            Label(format!("hello")), r_ident!("L"), Drill, Drill,
+            Drill, p_ident!("+"), r_ident!("C"), Tilde, c_ident!("0"), r_ident!("L"),
+            p_ident!("("), r_ident!("C"), Tilde, c_ident!("1"), c_ident!("0"), Tilde,
+            c_ident!("2"), r_ident!("L"), Drill, r_ident!("C"), c_ident!("1"), Tilde,
+            c_ident!("3"), c_ident!("2"), Tilde, c_ident!("4"), r_ident!("L"), Drill,
+            r_ident!("C"), c_ident!("3"), Tilde, c_ident!("5"), c_ident!("4"), Tilde,
+            c_ident!("6"), r_ident!("L"), Drill, r_ident!("C"), c_ident!("5"), Tilde,
+            c_ident!("7"), c_ident!("6"), Tilde, c_ident!("8"), r_ident!("L"), p_ident!("><"),
+            r_ident!("C"), Tilde, c_ident!("9"), c_ident!("9"), Tilde, c_ident!("7"),
+            Tilde, c_ident!("8"), TripleSixEqM, Jump(format!("hello"))
         ]);
         assert!(result.is_ok());
         assert_eq!(result, expected_result);
@@ -470,7 +939,11 @@ mod tests {
     fn test_err1(){
         let input = "!@l";
         let result = tokenize(input);
-        let expected_result = Err(errors::err_unrecognized_token(1));
+        let expected_result = Err(vec![
+            CompileError::UnrecognizedToken(Span::new(0, 1, 1, 1)),
+            CompileError::Expected(Span::new(3, 3, 1, 4), format!("a")),
+            CompileError::OrgExprMustEndInDeath(Span::new(3, 3, 1, 4))
+        ]);
         assert!(result.is_err());
         assert_eq!(result, expected_result);
     }
@@ -478,7 +951,10 @@ mod tests {
     fn test_err2(){
         let input = " ";
         let result = tokenize(input);
-        let expected_result = Err(errors::err_whitespace(1));
+        let expected_result = Err(vec![
+            CompileError::Whitespace(Span::new(0, 1, 1, 1)),
+            CompileError::OrgExprMustEndInDeath(Span::new(1, 1, 1, 2))
+        ]);
         assert!(result.is_err());
         assert_eq!(result, expected_result);
     }
@@ -486,7 +962,10 @@ mod tests {
     fn test_err3(){
         let input = "AB E";
         let result = tokenize(input);
-        let expected_result = Err(errors::err_whitespace(3));
+        let expected_result = Err(vec![
+            CompileError::Whitespace(Span::new(2, 3, 1, 3)),
+            CompileError::OrgExprMustEndInDeath(Span::new(3, 4, 1, 4))
+        ]);
         assert!(result.is_err());
         assert_eq!(result, expected_result);
     }
@@ -494,8 +973,150 @@ mod tests {
     fn test_err4(){
         let input = "$E";
         let result = tokenize(input);
-        let expected_result = Err(errors::err_invalid_primitive(2));
+        let expected_result = Err(vec![
+            CompileError::InvalidPrimitive(Span::new(1, 2, 1, 2)),
+            CompileError::OrgExprMustEndInDeath(Span::new(1, 2, 1, 2))
+        ]);
         assert!(result.is_err());
         assert_eq!(result, expected_result);
     }
+    #[test]
+    fn test_spans(){
+        let input = r"->L\\|//$><label:x:^^^^^^666^^^^^^=Mjmp:x:^^^^^^666^^^^^^=O";
+        let result = tokenize(input);
+        let expected_result = Ok(vec![
+            Token::new(r_ident!("L"), Span::new(0, 3, 1, 1)),
+            Token::new(Drill, Span::new(3, 8, 1, 4)),
+            Token::new(p_ident!("><"), Span::new(8, 11, 1, 9)),
+            Token::new(Label(format!("x")), Span::new(11, 19, 1, 12)),
+            Token::new(TripleSixEqM, Span::new(19, 36, 1, 20)),
+            Token::new(Jump(format!("x")), Span::new(36, 42, 1, 37))
+        ]);
+        assert_eq!(result, expected_result);
+    }
+    #[test]
+    fn test_err5(){
+        let input = r"->L\\|/x^^^^^^666^^^^^^=O";
+        let result = tokenize(input);
+        let expected_result = Err(vec![CompileError::UnrecognizedToken(Span::new(7, 8, 1, 8))]);
+        assert_eq!(result, expected_result);
+    }
+    #[test]
+    fn test_err6(){
+        let input = "0~1";
+        let result = tokenize(input);
+        let expected_result = Err(vec![CompileError::OrgExprMustEndInDeath(Span::new(2, 3, 1, 3))]);
+        assert_eq!(result, expected_result);
+    }
+    #[test]
+    fn test_recovery(){
+        let input = r"->L\\|/$!->X~0 0~1^^^^^^666^^^^^^=O";
+        let result = tokenize(input);
+        let expected_result = Err(vec![
+            CompileError::UnrecognizedToken(Span::new(7, 8, 1, 8)),
+            CompileError::UnrecognizedToken(Span::new(11, 12, 1, 12)),
+            CompileError::Whitespace(Span::new(14, 15, 1, 15))
+        ]);
+        assert_eq!(result, expected_result);
+    }
+    #[test]
+    fn test_error_limit(){
+        let input = r"\\|/x\\|/x\\|/x^^^^^^666^^^^^^=O";
+        let result = tokenize_with_error_limit(input, 2);
+        let expected_result = Err(vec![
+            CompileError::UnrecognizedToken(Span::new(4, 5, 1, 5)),
+            CompileError::UnrecognizedToken(Span::new(9, 10, 1, 10))
+        ]);
+        assert_eq!(result, expected_result);
+    }
+    #[test]
+    fn test_stream(){
+        // A 1 byte buffer makes every multibyte char cross a refill
+        let input = "label:\u{e9}\u{1f600}:jmp:\u{e9}\u{1f600}:^^^^^^666^^^^^^=O";
+        let reader = io::BufReader::with_capacity(1, input.as_bytes());
+        let result: Result<Vec<Token>, CompileError> = Lexer::new(reader).collect();
+        assert_eq!(result, Ok(vec![
+            Token::new(Label(format!("\u{e9}\u{1f600}")), Span::new(0, 13, 1, 1)),
+            Token::new(Jump(format!("\u{e9}\u{1f600}")), Span::new(13, 24, 1, 10))
+        ]));
+    }
+    #[test]
+    fn test_stream_is_lazy(){
+        // The input never ends, so this only finishes if the tokens are read as needed
+        let reader = io::BufReader::new(io::repeat(b'0'));
+        let kinds: Vec<TokenKind> = Lexer::new(reader).take(3).map(|result| result.unwrap().kind).collect();
+        assert_eq!(kinds, vec![c_ident!("0"), c_ident!("0"), c_ident!("0")]);
+    }
+    #[test]
+    fn test_invalid_utf8(){
+        // Nothing after the first invalid byte is read, so the whitespace isn't reported
+        let input: &[u8] = b"0~\xc3\xa9\n1\xff~1 ^^^^^^666^^^^^^=O";
+        let errors: Vec<CompileError> = Lexer::new(input).filter_map(Result::err).collect();
+        assert_eq!(errors, vec![
+            CompileError::UnrecognizedToken(Span::new(2, 4, 1, 3)),
+            CompileError::InvalidUtf8(Span::new(6, 6, 2, 2))
+        ]);
+        let input: &[u8] = b"0~1\xe2\x82^^^^^^666^^^^^^=O";
+        let errors: Vec<CompileError> = Lexer::new(input).filter_map(Result::err).collect();
+        assert_eq!(errors, vec![CompileError::InvalidUtf8(Span::new(3, 3, 1, 4))]);
+    }
+    #[test]
+    fn test_read_error(){
+        struct Failing;
+        impl io::Read for Failing {
+            fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+                Err(io::Error::new(io::ErrorKind::Other, "disconnected"))
+            }
+        }
+        let input = io::BufReader::new(io::Read::chain("0~1".as_bytes(), Failing));
+        let errors: Vec<CompileError> = Lexer::new(input).filter_map(Result::err).collect();
+        assert_eq!(errors, vec![CompileError::Read(format!("disconnected"))]);
+    }
+
+    #[test]
+    fn test_include(){
+        let tokens = tokenize("0include:sampleprog/lib/save_cells.mb:1^^^^^^666^^^^^^=O").unwrap();
+        assert_eq!(tokens.len(), 27);
+        assert_eq!(tokens[0], Token::new(c_ident!("0"), Span::new(0, 1, 1, 1)));
+        assert_eq!(tokens[1], Token::new(r_ident!("C"), Span::new(0, 3, 1, 1).in_file(FileId(1))));
+        assert_eq!(tokens[25], Token::new(c_ident!("8"), Span::new(42, 43, 1, 43).in_file(FileId(1))));
+        assert_eq!(tokens[26], Token::new(c_ident!("1"), Span::new(38, 39, 1, 39)));
+        let source = "include:save_cells.mb:^^^^^^666^^^^^^=O";
+        let tokens: Vec<Token> = Lexer::new(source.as_bytes())
+            .with_search_path(vec![PathBuf::from("sampleprog"), PathBuf::from("sampleprog/lib")])
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(tokens.len(), 25);
+    }
+
+    #[test]
+    fn test_include_errors(){
+        assert_eq!(
+            tokenize("include:no/such/file.mb:^^^^^^666^^^^^^=O"),
+            Err(vec![CompileError::IncludeNotFound(Span::new(0, 24, 1, 1), format!("no/such/file.mb"))])
+        );
+        assert_eq!(
+            tokenize("include:sampleprog/lib/cycle.mb:^^^^^^666^^^^^^=O"),
+            Err(vec![CompileError::IncludeCycle(Span::new(0, 17, 1, 1).in_file(FileId(1)), format!("cycle.mb"))])
+        );
+        // Only the root file has to end in the death of the organism expression
+        assert_eq!(
+            tokenize("0include:sampleprog/lib/save_cells.mb:"),
+            Err(vec![CompileError::OrgExprMustEndInDeath(Span::new(1, 38, 1, 2))])
+        );
+    }
+
+    #[test]
+    fn test_macro_tokens(){
+        let tokens = tokenize("macro:m:a,b:<a>~<b>endmacro:call:m:01^^^^^^666^^^^^^=O").unwrap();
+        assert_eq!(kinds(tokens), vec![
+            Macro(format!("m"), vec![format!("a"), format!("b")]), Placeholder(format!("a")), Tilde,
+            Placeholder(format!("b")), EndMacro, Call(format!("m")), c_ident!("0"), c_ident!("1")
+        ]);
+        assert_eq!(kinds(tokenize("macro:m::^^^^^^666^^^^^^=O").unwrap()), vec![Macro(format!("m"), vec![])]);
+        assert_eq!(
+            tokenize("macro:m:a,:^^^^^^666^^^^^^=O"),
+            Err(vec![CompileError::Expected(Span::new(8, 11, 1, 9), format!("parameter names between the commas"))])
+        );
+    }
 }
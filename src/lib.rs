@@ -0,0 +1,396 @@
+//! The Mindbend compiler, as a library.
+//!
+//! A program goes through the same stages as it does in the compiler. It's read in
+//! with `tokenize`, parsed with `parse`, linted with `check` and finally compiled with
+//! `compile_to_object` or `compile_to_executable`, or generated in a `Context` with
+//! `generate`. The modules behind the stages are public too, for tools that need more
+//! than the stages give them
+
+use std::{fmt, fs};
+use std::cell::RefCell;
+use std::io::BufRead;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+pub mod lexer;
+pub mod source_map;
+pub mod errors;
+pub mod diagnostics;
+pub mod explain;
+pub mod lints;
+pub mod macros;
+pub mod printer;
+pub mod emit;
+pub mod dump;
+pub mod message_format;
+pub mod parser;
+pub mod cfg;
+pub mod dataflow;
+pub mod access;
+pub mod lifetimes;
+pub mod contents;
+mod codegen;
+
+pub use codegen::Context;
+
+use diagnostics::{Diagnostic, Severity};
+use emit::CodeKind;
+use errors::CompileError;
+use lexer::{Lexer, Token};
+use lints::{Findings, LintLevels};
+use macros::MacroExpander;
+use parser::{OrganismExpression, Parser};
+use source_map::SourceMap;
+
+/// How a program is read in, parsed and checked
+#[derive(Debug, Clone)]
+pub struct Options {
+    /// The directories included files are looked for in, in order, when they aren't
+    /// next to the file including them
+    pub search_path: Vec<PathBuf>,
+    /// The number of errors to report before giving up
+    pub error_limit: usize,
+    pub lint_levels: LintLevels
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            search_path: vec![],
+            error_limit: errors::DEFAULT_ERROR_LIMIT,
+            lint_levels: LintLevels::new()
+        }
+    }
+}
+
+/// How a checked program is compiled
+#[derive(Debug, Clone, Default)]
+#[non_exhaustive]
+pub struct CompileOptions {
+    pub opt_level: OptLevel,
+    /// The target triple the code is generated for. The host's without one
+    pub target: Option<String>,
+    /// The root the cross linker looks for the target's libraries in
+    pub sysroot: Option<PathBuf>,
+    /// The CPU the code may use the instructions of, `native` for the host's. A generic
+    /// CPU of the target without one
+    pub target_cpu: Option<String>,
+    /// Features turned on or off on top of the CPU's, as +feature or -feature
+    pub target_features: Vec<String>,
+    /// The program the executable is linked with, like cc, clang or ld. gcc, or the
+    /// target's cross gcc, without one
+    pub linker: Option<PathBuf>,
+    /// Args passed to the linker after the ones the compiler passes
+    pub link_args: Vec<String>,
+    /// Whether the executable is position independent, with the code generated for it
+    pub pie: bool,
+    /// Whether the executable is linked statically
+    pub static_link: bool
+}
+
+/// How hard the generated code is optimized, as with -O
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OptLevel {
+    /// No optimization passes over the code as it's generated, though the machine
+    /// code is still generated at the target's default level
+    O0,
+    O1,
+    O2,
+    O3,
+    /// Optimized like O2, but favouring smaller code
+    Os
+}
+
+impl OptLevel {
+    pub const NAMES: [&'static str; 5] = ["0", "1", "2", "3", "s"];
+
+    pub fn from_name(name: &str) -> Option<OptLevel> {
+        match name {
+            "0" => Some(OptLevel::O0),
+            "1" => Some(OptLevel::O1),
+            "2" => Some(OptLevel::O2),
+            "3" => Some(OptLevel::O3),
+            "s" => Some(OptLevel::Os),
+            _ => None
+        }
+    }
+}
+
+impl Default for OptLevel {
+    fn default() -> OptLevel {
+        OptLevel::O0
+    }
+}
+
+/// A parsed program
+#[derive(Debug, Clone, PartialEq)]
+pub struct Program {
+    pub ast: OrganismExpression,
+    /// The labels, in the order they are in the program
+    pub labels: Vec<String>,
+    /// What's only wrong on some of the paths through the program, for the lints
+    pub findings: Findings
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    /// The errors found in the program, up to the error limit
+    Compile(Vec<CompileError>),
+    /// Lints at the deny level found something. The warnings are what the other
+    /// lints found
+    Denied {
+        denied: Vec<Diagnostic>,
+        warnings: Vec<Diagnostic>
+    },
+    /// The program is nothing but the death of the organism expression
+    OnlyDeath
+}
+
+impl From<CompileError> for Error {
+    fn from(err: CompileError) -> Error {
+        Error::Compile(vec![err])
+    }
+}
+
+impl From<Vec<CompileError>> for Error {
+    fn from(errs: Vec<CompileError>) -> Error {
+        Error::Compile(errs)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Compile(errs) => match errs.as_slice() {
+                [err] => write!(f, "{}", err),
+                errs => write!(f, "{} errors, the first: {}", errs.len(), errs[0])
+            },
+            Error::Denied { denied, .. } => write!(f, "{} denied lint warnings", denied.len()),
+            Error::OnlyDeath => write!(f, "Only Organism death.")
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// The tokens of a program, read in as they are needed
+pub type Tokens<R> = MacroExpander<Lexer<R>>;
+
+/// Reads in the tokens of the program, with the files it includes read in and its
+/// macros expanded. The input is the root of the source map, the first file in it,
+/// and the files it includes are added to the map as they are read
+pub fn tokenize<R: BufRead>(input: R, source_map: Rc<RefCell<SourceMap>>, options: &Options) -> Tokens<R> {
+    MacroExpander::new(
+        Lexer::with_source_map(input, source_map).with_search_path(options.search_path.clone())
+    )
+}
+
+/// Reads in all the tokens of the program, like tokenize, reporting all the errors
+/// up to the error limit
+pub fn tokenize_all<R: BufRead>(
+    input: R,
+    source_map: Rc<RefCell<SourceMap>>,
+    options: &Options
+) -> Result<Vec<Token>, Error> {
+    lexer::collect_tokens(tokenize(input, source_map, options), options.error_limit).map_err(Error::from)
+}
+
+/// Parses the tokens, pulling them from the stream as they are needed
+pub fn parse<I>(tokens: I, options: &Options) -> Result<Program, Error>
+where
+    I: Iterator<Item = Result<Token, CompileError>>
+{
+    let mut parser = Parser::from_stream(tokens);
+    parser.set_error_limit(options.error_limit);
+    if parser.is_empty() {
+        return Err(Error::OnlyDeath);
+    }
+    let (ast, labels) = parser.parse()?;
+    // What the cells hold is only known once every path through the program is,
+    // so it's checked after everything else has been parsed
+    let (mut errors, maybe_invalid_leaches, expired) = contents::check(&ast);
+    if !errors.is_empty() {
+        errors.truncate(options.error_limit);
+        return Err(Error::Compile(errors));
+    }
+    let findings = Findings {
+        maybe_invalid: parser.take_maybe_invalid(),
+        expired,
+        maybe_invalid_leaches
+    };
+    Ok(Program { ast, labels, findings })
+}
+
+/// Runs the lints on the program, at the levels in the options. Returns the warnings
+/// if none of the lints are denied
+pub fn check(program: &Program, options: &Options) -> Result<Vec<Diagnostic>, Error> {
+    let (denied, warnings): (Vec<Diagnostic>, Vec<Diagnostic>) = lints::check(&program.ast, &program.findings).iter()
+        .filter_map(|warning| warning.to_diagnostic(&options.lint_levels))
+        .partition(|diagnostic| diagnostic.severity == Severity::Error);
+    if denied.is_empty() {
+        Ok(warnings)
+    } else {
+        Err(Error::Denied { denied, warnings })
+    }
+}
+
+/// The code generated for a program, to be written out in any of the forms the
+/// compiler emits
+pub struct Code<'a> {
+    codegen: codegen::CodeGen<'a>,
+    options: CompileOptions
+}
+
+impl Code<'_> {
+    /// The code in the form, as it's written to a file
+    pub fn emit(&self, kind: CodeKind) -> Result<Vec<u8>, Error> {
+        Ok(self.codegen.emit(kind, &self.options)?)
+    }
+
+    /// Writes the code in the form to the path
+    pub fn write(&self, kind: CodeKind, path: &Path) -> Result<(), Error> {
+        let code = self.emit(kind)?;
+        fs::write(path, code).map_err(|err| {
+            Error::from(CompileError::ObjectWrite(format!("{}: {}", path.display(), err)))
+        })
+    }
+
+    /// Links the code into an executable at the path
+    pub fn link(&self, path: &Path) -> Result<(), Error> {
+        let mut object = path.as_os_str().to_owned();
+        object.push(".tmp");
+        let object = PathBuf::from(object);
+        self.write(CodeKind::Obj, &object)?;
+        let linked = codegen::link(&object, path, &self.options);
+        // The object file is of no use once linking has been attempted
+        let _ = fs::remove_file(&object);
+        Ok(linked?)
+    }
+}
+
+/// Generates the code for the program in the context and optimizes it at the options'
+/// level. The context, created with `Context::create`, holds the code until both are
+/// dropped
+pub fn generate<'a>(
+    program: &'a Program,
+    context: &'a Context,
+    options: &CompileOptions
+) -> Result<Code<'a>, Error> {
+    let mut codegen = codegen::CodeGen::new(&program.ast, program.labels.clone(), context);
+    codegen.code()?;
+    codegen.set_target(options)?;
+    codegen.optimize(options.opt_level);
+    Ok(Code { codegen, options: options.clone() })
+}
+
+/// The triple of the host, which code is generated for without a target
+pub fn host_target() -> String {
+    codegen::host_target()
+}
+
+/// The names and descriptions of the targets code can be generated for
+pub fn targets() -> Vec<(String, String)> {
+    codegen::targets()
+}
+
+/// Compiles the program to an object file at the path
+pub fn compile_to_object(program: &Program, path: &Path, options: &CompileOptions) -> Result<(), Error> {
+    generate(program, &Context::create(), options)?.write(CodeKind::Obj, path)
+}
+
+/// Compiles the program to an executable at the path
+pub fn compile_to_executable(program: &Program, path: &Path, options: &CompileOptions) -> Result<(), Error> {
+    generate(program, &Context::create(), options)?.link(path)
+}
+
+/// What the tests of the analyses share
+#[cfg(test)]
+mod test_util {
+    use crate::contents;
+    use crate::errors::CompileError;
+    use crate::lexer::tokenize;
+    use crate::lints::Findings;
+    use crate::parser::{OrganismExpression, Parser};
+
+    /// Enough drills to open all the gates
+    pub const DRILLS: &'static str = r"\\|//\\|//\\|//";
+
+    /// The organism expression, or the errors the parser found in it
+    pub fn try_parse(source: &str) -> Result<OrganismExpression, Vec<CompileError>> {
+        Parser::new(tokenize(source).unwrap()).parse().map(|(org_expr, _)| org_expr)
+    }
+
+    pub fn parse(source: &str) -> OrganismExpression {
+        try_parse(source).unwrap()
+    }
+
+    /// The organism expression and what the analyses found in it for the lints. Unlike
+    /// parse in the crate, the leaches the contents make errors of are left in
+    pub fn parse_with_findings(source: &str) -> (OrganismExpression, Findings) {
+        let mut parser = Parser::new(tokenize(source).unwrap());
+        let (org_expr, _) = parser.parse().unwrap();
+        let (_, maybe_invalid_leaches, expired) = contents::check(&org_expr);
+        let findings = Findings {
+            maybe_invalid: parser.take_maybe_invalid(),
+            expired,
+            maybe_invalid_leaches
+        };
+        (org_expr, findings)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn source_map(name: &str, source: &str) -> Rc<RefCell<SourceMap>> {
+        Rc::new(RefCell::new(SourceMap::from_source(name, source)))
+    }
+
+    #[test]
+    fn test_stages(){
+        // Read from a local string, since the tokens needn't outlive what they're read from
+        let source = String::from(r"->L\\|//\\|//\\|//\\|//$!->C~0^^^^^^666^^^^^^=O");
+        let options = Options::default();
+        let tokens = tokenize(source.as_bytes(), source_map("prog.mb", &source), &options);
+        let program = parse(tokens, &options).unwrap();
+        assert_eq!(program.ast.exprs.len(), 6);
+        let warnings = check(&program, &options).unwrap();
+        assert_eq!(warnings.iter().map(|warning| warning.code).collect::<Vec<_>>(), vec![Some("redundant-drill")]);
+        let mut options = options;
+        options.lint_levels.set("warnings", lints::Level::Deny).unwrap();
+        match check(&program, &options) {
+            Err(Error::Denied { denied, warnings }) => assert_eq!((denied.len(), warnings.len()), (1, 0)),
+            result => panic!("expected the lint to be denied, got {:?}", result)
+        };
+    }
+
+    #[test]
+    fn test_opt_level(){
+        let levels: Vec<_> = OptLevel::NAMES.iter().map(|name| OptLevel::from_name(name)).collect();
+        assert_eq!(levels, vec![
+            Some(OptLevel::O0), Some(OptLevel::O1), Some(OptLevel::O2), Some(OptLevel::O3), Some(OptLevel::Os)
+        ]);
+        assert_eq!(OptLevel::from_name("4"), None);
+        assert_eq!(CompileOptions::default().opt_level, OptLevel::O0);
+    }
+
+    #[test]
+    fn test_errors(){
+        let options = Options { error_limit: 1, ..Options::default() };
+        let source = r"~0~0^^^^^^666^^^^^^=O";
+        let tokens = tokenize_all(source.as_bytes(), source_map("prog.mb", source), &options).unwrap();
+        assert_eq!(
+            parse(tokens.into_iter().map(Ok), &options),
+            Err(Error::Compile(vec![CompileError::LeachMustStartWithPrimitiveOrCell(lexer::Span::new(0, 1, 1, 1))]))
+        );
+        let source = r"^^^^^^666^^^^^^=O";
+        let tokens = tokenize(source.as_bytes(), source_map("prog.mb", source), &options);
+        assert_eq!(parse(tokens, &options), Err(Error::OnlyDeath));
+        let source = r"0~1";
+        assert_eq!(
+            tokenize_all(source.as_bytes(), source_map("prog.mb", source), &options),
+            Err(Error::Compile(vec![CompileError::OrgExprMustEndInDeath(lexer::Span::new(2, 3, 1, 3))]))
+        );
+    }
+}
@@ -0,0 +1,402 @@
+//! Simulates the lives of the expressions leached onto cells and how long the gates
+//! stay open, the TTL table and TTSO the compiled program keeps at runtime.
+//!
+//! Every active expression ages the expressions in the cells, and every one but a
+//! drill brings the gates closer to closing, just as the state update routine does.
+//! The lives are followed through jumps as well, so a cell can be found to be read
+//! after its expression has died on some of the paths to the read, or a primitive
+//! accessed after the gates have closed again. Neither is an error, since the runtime
+//! still checks them, so they are left to the lints to warn about. That holds for an
+//! expression that died of old age on every path too. One leached onto another cell
+//! or consumed in a massacre on every path leaves the Death Expression behind, which
+//! the contents analysis rejects.
+//!
+//! A cell that has never had anything leached onto it still holds the Death
+//! Expression it started with. Reading it isn't a matter of lifetimes, so it isn't
+//! reported here
+
+use crate::cfg::ControlFlowGraph;
+use crate::dataflow::{self, Analysis};
+use crate::lexer::Span;
+use crate::parser::{OrganismExpression, Expr, LeachExpression, PassiveExpression, PrimitiveValue};
+
+/// The number of cells in the Cells Region
+pub const CELLS: usize = 15;
+/// The number of active expressions an expression lives for after being leached
+const TTL: u8 = 5;
+/// The number of gates there are to drill open
+const GATES: u8 = 3;
+/// The number of active expressions, drills aside, the gates stay open for once all
+/// of them have been drilled open
+const TTSO: u8 = 5;
+
+/// The bit of the Death Expression a cell starts with. The bits after it are the TTLs
+/// the expression in the cell may have left
+const NEVER_LEACHED: u8 = 1;
+/// The bit of an expression killed by a leach
+const KILLED: u8 = 1 << (TTL + 1);
+/// The bit of an expression that died of old age
+const EXPIRED: u8 = KILLED << 1;
+/// The bits of the TTLs of a living expression
+const ALIVE: u8 = !(NEVER_LEACHED | KILLED | EXPIRED);
+
+/// What the expression in a cell may be
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CellLife {
+    lives: u8,
+    /// The leach that created the living expression
+    leached: Option<Span>,
+    /// The leach that created an expression that died and the expression it died in
+    died: Option<(Span, Span)>
+}
+
+impl CellLife {
+    fn new() -> CellLife {
+        CellLife {
+            lives: NEVER_LEACHED,
+            leached: None,
+            died: None
+        }
+    }
+    pub fn may_be_dead(&self) -> bool {
+        self.lives & (KILLED | EXPIRED) != 0
+    }
+    /// Whether the expression in the cell may have died of old age
+    pub fn may_have_expired(&self) -> bool {
+        self.lives & EXPIRED != 0
+    }
+    pub fn may_be_alive(&self) -> bool {
+        self.lives & ALIVE != 0
+    }
+    /// Whether the cell may still hold the Death Expression it started with
+    pub fn may_be_unleached(&self) -> bool {
+        self.lives & NEVER_LEACHED != 0
+    }
+    fn join(&mut self, other: &CellLife) {
+        self.lives |= other.lives;
+        self.leached = self.leached.or(other.leached);
+        self.died = self.died.or(other.died);
+    }
+    /// Ages the living expression by one active expression. An expression with 1 TTL
+    /// left dies in the expression
+    fn age(&mut self, span: Span) {
+        let alive = self.lives & ALIVE;
+        let mut lives = (self.lives & !ALIVE) | ((alive >> 1) & ALIVE);
+        if alive & (NEVER_LEACHED << 1) != 0 {
+            lives |= EXPIRED;
+            self.died = self.leached.map(|leached| (leached, span)).or(self.died);
+        }
+        self.lives = lives;
+    }
+    fn leach(&mut self, span: Span) {
+        self.lives = NEVER_LEACHED << TTL;
+        self.leached = Some(span);
+        self.died = None;
+    }
+    fn kill(&mut self, span: Span) {
+        if self.may_be_alive() {
+            self.lives = (self.lives & !ALIVE) | KILLED;
+            self.died = self.leached.map(|leached| (leached, span)).or(self.died);
+        }
+    }
+}
+
+/// The states the gates may be in, one bit for each number of open gates and TTSO,
+/// along with whether the gates have closed since they were last all open
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GatesLife {
+    states: u64,
+    /// The drill that last opened all the gates
+    opened: Option<Span>,
+    /// The drill that opened all the gates before they closed, and the expression
+    /// they closed in
+    closed: Option<(Span, Span)>
+}
+
+impl GatesLife {
+    fn bit(open_gates: u8, ttso: u8, closed: bool) -> u64 {
+        let closed = if closed { 1 } else { 0 };
+        1 << ((closed * (GATES + 1) + open_gates) * (TTSO + 1) + ttso)
+    }
+    /// The states the gates may be in, as the number of open gates, the TTSO and
+    /// whether they have closed
+    fn states(self) -> impl Iterator<Item = (u8, u8, bool)> {
+        [false, true].iter()
+            .flat_map(|&closed| (0..=GATES).map(move |open_gates| (open_gates, closed)))
+            .flat_map(|(open_gates, closed)| (0..=TTSO).map(move |ttso| (open_gates, ttso, closed)))
+            .filter(move |&(open_gates, ttso, closed)| self.states & GatesLife::bit(open_gates, ttso, closed) != 0)
+    }
+    fn map<F>(&mut self, f: F)
+    where
+        F: Fn((u8, u8, bool)) -> (u8, u8, bool)
+    {
+        self.states = self.states()
+            .map(f)
+            .fold(0, |bits, (open_gates, ttso, closed)| bits | GatesLife::bit(open_gates, ttso, closed));
+    }
+    /// Whether the gates may have closed again by the time they are needed open
+    pub fn may_be_closed(self) -> bool {
+        self.states().any(|(open_gates, _, closed)| closed && open_gates < GATES)
+    }
+    pub fn may_be_open(self) -> bool {
+        self.states().any(|(open_gates, _, _)| open_gates == GATES)
+    }
+    fn join(&mut self, other: &GatesLife) {
+        self.states |= other.states;
+        self.opened = self.opened.or(other.opened);
+        self.closed = self.closed.or(other.closed);
+    }
+    /// Opens another gate. The TTSO starts over when the last of them opens
+    fn drill(&mut self, span: Span) {
+        if self.states().any(|(open_gates, _, _)| open_gates == GATES - 1) {
+            self.opened = Some(span);
+        }
+        self.map(|(open_gates, ttso, closed)| match open_gates {
+            open_gates if open_gates == GATES - 1 => (GATES, TTSO, false),
+            open_gates if open_gates < GATES => (open_gates + 1, ttso, closed),
+            _ => (open_gates, ttso, closed)
+        });
+    }
+    /// Brings the gates one expression closer to closing. They all close when the TTSO
+    /// runs out
+    fn age(&mut self, span: Span) {
+        if self.states().any(|(_, ttso, _)| ttso == 1) {
+            self.closed = self.opened.map(|opened| (opened, span)).or(self.closed);
+        }
+        self.map(|(open_gates, ttso, closed)| match ttso {
+            0 => (open_gates, ttso, closed),
+            1 => (0, 0, true),
+            ttso => (open_gates, ttso - 1, closed)
+        });
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lives {
+    pub cells: [CellLife; CELLS],
+    pub gates: GatesLife
+}
+
+impl Lives {
+    /// An active expression. Drills don't age the gates
+    fn active_expression(&mut self, span: Span, ages_gates: bool) {
+        if ages_gates {
+            self.gates.age(span);
+        }
+        for cell in self.cells.iter_mut() {
+            cell.age(span);
+        }
+    }
+    fn cell(&mut self, ident: u8) -> &mut CellLife {
+        &mut self.cells[ident as usize]
+    }
+}
+
+/// How the expressions in the cells and the gates age
+pub struct ExpressionLives;
+
+impl Analysis for ExpressionLives {
+    type State = Lives;
+
+    /// Every cell starts with the Death Expression, and every gate closed
+    fn entry_state(&self) -> Lives {
+        Lives {
+            cells: [CellLife::new(); CELLS],
+            gates: GatesLife {
+                states: GatesLife::bit(0, 0, false),
+                opened: None,
+                closed: None
+            }
+        }
+    }
+    fn join(&self, state: &mut Lives, other: &Lives) {
+        for (cell, other_cell) in state.cells.iter_mut().zip(other.cells.iter()) {
+            cell.join(other_cell);
+        }
+        state.gates.join(&other.gates);
+    }
+    fn transfer(&self, state: &mut Lives, expr: &Expr) {
+        match expr {
+            Expr::Region(region_expr) => state.active_expression(region_expr.span, true),
+            Expr::Jump { span, .. } => state.active_expression(*span, true),
+            Expr::Drill(span) => {
+                state.gates.drill(*span);
+                state.active_expression(*span, false);
+            }
+            Expr::Leach(leach_expr) => leach(state, leach_expr),
+            Expr::Label(_) | Expr::Cell(_) | Expr::Primitive(_) => ()
+        };
+    }
+}
+
+/// Carries out the leach as the compiled program does, aging everything before the
+/// expressions it kills die and the one it creates is born
+fn leach(state: &mut Lives, leach_expr: &LeachExpression) {
+    let span = leach_expr.span;
+    match &leach_expr.left {
+        PassiveExpression::Primitive(_) => {
+            for region_expr in leach_expr.region_change.iter().flatten() {
+                state.active_expression(region_expr.span, true);
+            }
+            state.active_expression(span, true);
+            state.cell(leach_expr.onto[0].ident).leach(span);
+        }
+        PassiveExpression::Cell(cell_expr) if leach_expr.is_chain => {
+            // The massacre ages everything once for each cell it consumes
+            for _ in leach_expr.onto.iter() {
+                state.active_expression(span, true);
+            }
+            let (last, consumed) = leach_expr.onto.split_last().unwrap();
+            for consumed_expr in consumed {
+                state.cell(consumed_expr.ident).kill(span);
+            }
+            state.cell(cell_expr.ident).kill(span);
+            state.cell(last.ident).leach(span);
+        }
+        PassiveExpression::Cell(cell_expr) => {
+            state.active_expression(span, true);
+            state.cell(leach_expr.onto[0].ident).leach(span);
+            state.cell(cell_expr.ident).kill(span);
+        }
+    };
+}
+
+/// Something used after it may have died
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expired {
+    /// A cell read when the expression in it may have died, along with the leach that
+    /// created the expression and the expression it died in. Always if it's dead on
+    /// every path that had something leached onto it
+    Cell {
+        span: Span,
+        leached: Span,
+        died: Span,
+        always: bool
+    },
+    /// A primitive accessed when the gates may have closed, along with the drill that
+    /// opened them and the expression they closed in. Always if they're closed on
+    /// every path
+    Gates {
+        span: Span,
+        opened: Span,
+        closed: Span,
+        always: bool
+    }
+}
+
+impl Expired {
+    pub fn span(&self) -> Span {
+        match self {
+            Expired::Cell { span, .. } | Expired::Gates { span, .. } => *span
+        }
+    }
+}
+
+/// The cells read and primitives accessed after they may have died, in the order they
+/// are in the program. Expressions that can't be reached aren't checked
+pub fn check(org_expr: &OrganismExpression) -> Vec<Expired> {
+    let cfg = ControlFlowGraph::new(org_expr);
+    let results = dataflow::solve(&cfg, ExpressionLives);
+    let mut expired = vec![];
+    results.for_each_expr(|_, expr, lives| check_expr(expr, None, lives, &mut expired));
+    expired
+}
+
+/// Adds what the expression uses after it may have died, given the lives before it
+/// and, for a massacre, the function its head holds if that's known. For analyses
+/// that follow the lives along with something else, so they don't have to be solved
+/// twice
+pub fn check_expr(expr: &Expr, head: Option<PrimitiveValue>, lives: &Lives, expired: &mut Vec<Expired>) {
+    match expr {
+        Expr::Primitive(primitive_expr) => check_gates(primitive_expr.span, lives, expired),
+        Expr::Leach(leach_expr) => match &leach_expr.left {
+            PassiveExpression::Primitive(primitive_expr) =>
+                check_gates(primitive_expr.span, lives, expired),
+            PassiveExpression::Cell(cell_expr) => {
+                check_cell(cell_expr.span, &lives.cells[cell_expr.ident as usize], expired);
+                // An input massacre stores into its one cell instead of reading it.
+                // Every other massacre reads all of its cells, so unless the head is
+                // known to be input they're all checked
+                let stores = head == Some(PrimitiveValue::Input) && leach_expr.onto.len() == 1;
+                if leach_expr.is_chain && !stores {
+                    for onto in leach_expr.onto.iter() {
+                        check_cell(onto.span, &lives.cells[onto.ident as usize], expired);
+                    }
+                }
+            }
+        },
+        _ => ()
+    };
+}
+
+fn check_cell(span: Span, life: &CellLife, expired: &mut Vec<Expired>) {
+    if let (true, Some((leached, died))) = (life.may_be_dead(), life.died) {
+        expired.push(Expired::Cell {
+            span,
+            leached,
+            died,
+            always: !life.may_be_alive()
+        });
+    }
+}
+
+fn check_gates(span: Span, lives: &Lives, expired: &mut Vec<Expired>) {
+    if let (true, Some((opened, closed))) = (lives.gates.may_be_closed(), lives.gates.closed) {
+        expired.push(Expired::Gates {
+            span,
+            opened,
+            closed,
+            always: !lives.gates.may_be_open()
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{parse, DRILLS};
+
+    #[test]
+    fn test_cell_dies(){
+        // Cell 0 has 1 TTL left when it's copied
+        assert_eq!(check(&parse(&format!("->L{}$!->C~0->L->C->L->C0~1^^^^^^666^^^^^^=O", DRILLS))), vec![]);
+        assert_eq!(
+            check(&parse(&format!("->L{}$!->C~0->L->C->L->C->L->C0~1^^^^^^666^^^^^^=O", DRILLS))),
+            vec![Expired::Cell {
+                span: Span::new(43, 44, 1, 44),
+                leached: Span::new(18, 25, 1, 19),
+                died: Span::new(37, 40, 1, 38),
+                always: true
+            }]
+        );
+        // The copy kills cell 0, and a cell that has never been leached onto isn't
+        // reported
+        assert_eq!(
+            check(&parse(&format!("->L{}$!->C~00~10~2E~3^^^^^^666^^^^^^=O", DRILLS))),
+            vec![Expired::Cell {
+                span: Span::new(28, 29, 1, 29),
+                leached: Span::new(18, 25, 1, 19),
+                died: Span::new(25, 28, 1, 26),
+                always: true
+            }]
+        );
+    }
+
+    #[test]
+    fn test_gates_close(){
+        assert_eq!(check(&parse(&format!("->L{}$!->C~0->L->C->L$@->C~1^^^^^^666^^^^^^=O", DRILLS))).len(), 1);
+        assert_eq!(check(&parse(&format!("->L{0}$!->C~0->L->C->L{0}$@->C~1^^^^^^666^^^^^^=O", DRILLS))), vec![]);
+    }
+
+    #[test]
+    fn test_loop(){
+        // The gates are opened once, before the loop, and close on the way around it
+        let expired = check(&parse(&format!("->L{}label:x:$!->C~0->Lijmp:x:^^^^^^666^^^^^^=O", DRILLS)));
+        match expired.as_slice() {
+            [Expired::Gates { always: false, .. }] => (),
+            expired => panic!("expected the gates to close on some paths, got {:?}", expired)
+        };
+        let expired = check(&parse(&format!("->Llabel:x:{}$!->C~0->Lijmp:x:^^^^^^666^^^^^^=O", DRILLS)));
+        assert_eq!(expired, vec![]);
+    }
+}
@@ -0,0 +1,627 @@
+//! Checks for programs that are legal but most likely not what was meant
+
+use std::collections::HashMap;
+use crate::access::MaybeInvalid;
+use crate::contents::MaybeInvalidLeach;
+use crate::diagnostics::{Diagnostic, Label, Severity, Suggestion};
+use crate::lexer::Span;
+use crate::lifetimes::Expired;
+use crate::parser::{OrganismExpression, Expr};
+
+/// The name that stands for every lint that warns, as in `-D warnings`
+pub const WARNINGS: &'static str = "warnings";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Level {
+    /// The lint isn't reported
+    Allow,
+    /// The lint is reported, but the program still compiles
+    Warn,
+    /// The lint is reported as an error
+    Deny
+}
+
+impl Level {
+    fn flag(&self) -> &'static str {
+        match self {
+            Level::Allow => "-A",
+            Level::Warn => "-W",
+            Level::Deny => "-D"
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Lint {
+    pub name: &'static str,
+    pub default_level: Level,
+    /// Printed by `mindbend explain`
+    pub explanation: &'static str
+}
+
+pub const JUMP_TO_NEXT_LABEL: Lint = Lint {
+    name: "jump-to-next-label",
+    default_level: Level::Warn,
+    explanation: "\
+A jump goes to the label that comes right after it.
+
+The jump lands exactly where the program would have gone without it, so it
+does nothing but age the expressions around it. It is most likely a jump to
+the wrong label.
+
+Example:
+
+    label:x:ijmp:y:label:y:^^^^^^666^^^^^^=O
+
+Fixed example:
+
+    label:x:ijmp:x:label:y:^^^^^^666^^^^^^=O
+"
+};
+
+pub const REDUNDANT_DRILL: Lint = Lint {
+    name: "redundant-drill",
+    default_level: Level::Warn,
+    explanation: "\
+A drill expression comes when all the Layers gates are already open.
+
+There are only 3 gates and each drill opens one, so after 3 drills in a row
+all of them are open and any more drills have no effect.
+
+Example:
+
+    ->L\\\\|//\\\\|//\\\\|//\\\\|//^^^^^^666^^^^^^=O
+
+Fixed example:
+
+    ->L\\\\|//\\\\|//\\\\|//^^^^^^666^^^^^^=O
+"
+};
+
+pub const LONE_CELL_EXPRESSION: Lint = Lint {
+    name: "lone-cell-expression",
+    default_level: Level::Warn,
+    explanation: "\
+A cell expression isn't part of a leach expression.
+
+A cell on its own is neither leached nor leached onto, so it has no effect.
+It is most likely missing a ~ and the cell it was meant to be leached onto.
+
+Example:
+
+    0^^^^^^666^^^^^^=O
+
+Fixed example:
+
+    0~1^^^^^^666^^^^^^=O
+"
+};
+
+pub const REGION_DEPENDS_ON_PATH: Lint = Lint {
+    name: "region-depends-on-path",
+    default_level: Level::Warn,
+    explanation: "\
+A cell, primitive or drill is reached in the right region on some paths, but
+not on others.
+
+A jump takes the region the program is in along with it, so a label can be
+reached in a different region from the one the expressions before it end in.
+The expression after it then fails at runtime whenever the program gets there
+the wrong way. Changing to the region right before the expression makes every
+path right.
+
+Example:
+
+    label:x:0~1->Ljmp:x:^^^^^^666^^^^^^=O
+
+Fixed example:
+
+    label:x:->C0~1->Ljmp:x:^^^^^^666^^^^^^=O
+"
+};
+
+pub const GATES_DEPEND_ON_PATH: Lint = Lint {
+    name: "gates-depend-on-path",
+    default_level: Level::Warn,
+    explanation: "\
+A primitive is reached with all the Layers gates open on some paths, but not
+on others.
+
+A jump can skip the drills before a primitive, so the primitive fails at
+runtime whenever the program gets there that way. Drilling the gates open
+after the label the jump goes to makes every path right.
+
+Example:
+
+    ->Lijmp:x:\\\\|//\\\\|//\\\\|//label:x:$!->C~0^^^^^^666^^^^^^=O
+
+Fixed example:
+
+    ->Lijmp:x:\\\\|//label:x:\\\\|//\\\\|//\\\\|//$!->C~0^^^^^^666^^^^^^=O
+"
+};
+
+pub const DEAD_CELL_READ: Lint = Lint {
+    name: "dead-cell-read",
+    default_level: Level::Warn,
+    explanation: "\
+A cell is read after the expression leached onto it may have committed suicide.
+
+An expression leached onto a cell dies 5 active expressions later, and the
+Death Expression takes its place. It also dies when it's leached onto another
+cell or consumed in a massacre, which is an error when it happens on every
+path to the read. Otherwise reading the cell after it died fails at runtime,
+or gives a meaningless value to a massacre. Leaching the expression onto
+another cell before it dies keeps its value alive.
+
+Example:
+
+    ->L\\\\|//\\\\|//\\\\|//$!->C~0->L->C->L->C->L->C0~1^^^^^^666^^^^^^=O
+
+Fixed example:
+
+    ->L\\\\|//\\\\|//\\\\|//$!->C~0->L->C->L->C0~1->L->C^^^^^^666^^^^^^=O
+"
+};
+
+pub const CLOSED_GATES: Lint = Lint {
+    name: "closed-gates",
+    default_level: Level::Warn,
+    explanation: "\
+A primitive is accessed after the Layers gates may have closed again.
+
+Once all 3 gates are open, they close by themselves 5 active expressions
+later, drills aside. Accessing a primitive after that fails at runtime.
+Drilling the gates open again before the primitive fixes it.
+
+Example:
+
+    ->L\\\\|//\\\\|//\\\\|//$!->C~0->L->C->L$@->C~1^^^^^^666^^^^^^=O
+
+Fixed example:
+
+    ->L\\\\|//\\\\|//\\\\|//$!->C~0->L->C->L\\\\|//\\\\|//\\\\|//$@->C~1^^^^^^666^^^^^^=O
+"
+};
+
+pub const DEATH_EXPRESSION_LEACH: Lint = Lint {
+    name: "death-expression-leach",
+    default_level: Level::Warn,
+    explanation: "\
+A cell may be leached onto another before anything is leached onto it.
+
+Every cell holds the Death Expression until something is leached onto it, and
+leaching the Death Expression onto another cell fails at runtime. When only
+some of the paths to the leach, through the jumps, leach onto the cell first,
+the compiler can't tell whether it will fail. Leaching onto the cell before
+the paths meet makes sure it holds something.
+
+Example:
+
+    label:x:0~1->L\\\\|//\\\\|//\\\\|//$!->C~0ijmp:x:^^^^^^666^^^^^^=O
+
+Fixed example:
+
+    ->L\\\\|//\\\\|//\\\\|//$!->C~0label:x:0~1->L\\\\|//\\\\|//\\\\|//$!->C~0ijmp:x:^^^^^^666^^^^^^=O
+"
+};
+
+pub const NON_FUNCTION_MASSACRE: Lint = Lint {
+    name: "non-function-massacre",
+    default_level: Level::Warn,
+    explanation: "\
+A massacre may be started by a cell that doesn't hold a rampage primitive.
+
+The first cell of a massacre chain has to hold input, output, addition or
+subtraction, or one of the numbers 0 to 3 that are their indices, or the
+massacre fails at runtime. When the cell holds another number, or nothing has
+been leached onto it, on only some of the paths to the massacre, the compiler
+can't tell whether it will fail.
+
+Example:
+
+    ->L\\\\|//\\\\|//\\\\|//$><->C~0ijmp:x:->L$(->C~0label:x:0~1~2^^^^^^666^^^^^^=M^^^^^^666^^^^^^=O
+
+Fixed example:
+
+    ->L\\\\|//\\\\|//\\\\|//$><->C~0ijmp:x:->L$><->C~0label:x:0~1~2^^^^^^666^^^^^^=M^^^^^^666^^^^^^=O
+"
+};
+
+pub const ODD_OUTPUT_OPERANDS: Lint = Lint {
+    name: "odd-output-operands",
+    default_level: Level::Warn,
+    explanation: "\
+An output massacre is given an odd number of cells, more than 1.
+
+Output prints the cells it's given in pairs, the first cell of a pair being
+the tens and the second the ones of the character's code. The cell left over
+after the last pair is printed on its own, as the code of a character, which
+is most likely not what was meant. Giving output an even number of cells, or
+printing the last cell in a massacre of its own, makes every cell part of the
+character meant.
+
+Example:
+
+    ->L\\\\|//\\\\|//\\\\|//$><->C~0->L$(->C~10~1~1~1^^^^^^666^^^^^^=M^^^^^^666^^^^^^=O
+
+Fixed example:
+
+    ->L\\\\|//\\\\|//\\\\|//$><->C~0->L$(->C~10~1~1^^^^^^666^^^^^^=M^^^^^^666^^^^^^=O
+"
+};
+
+pub const LINTS: [&'static Lint; 10] = [
+    &JUMP_TO_NEXT_LABEL,
+    &REDUNDANT_DRILL,
+    &LONE_CELL_EXPRESSION,
+    &REGION_DEPENDS_ON_PATH,
+    &GATES_DEPEND_ON_PATH,
+    &DEAD_CELL_READ,
+    &CLOSED_GATES,
+    &DEATH_EXPRESSION_LEACH,
+    &NON_FUNCTION_MASSACRE,
+    &ODD_OUTPUT_OPERANDS
+];
+
+/// The lint with the name, if there is any such lint
+pub fn find(name: &str) -> Option<&'static Lint> {
+    LINTS.iter()
+        .find(|lint| lint.name == name)
+        .map(|lint| *lint)
+}
+
+/// Checks that the name can be given to -W, -A or -D
+pub fn validate_name(name: &str) -> Result<(), String> {
+    if name == WARNINGS || find(name).is_some() {
+        Ok(())
+    } else {
+        Err(format!("unknown lint `{}`", name))
+    }
+}
+
+/// The level of every lint, starting from the defaults and changed by the -W, -A and -D
+/// flags in the order they were given
+#[derive(Debug, Clone)]
+pub struct LintLevels {
+    /// The level of each lint set by a flag, along with the flag
+    levels: HashMap<&'static str, (Level, String)>,
+    /// The level the lints that would warn are raised or lowered to by a flag
+    warnings: Option<(Level, String)>
+}
+
+impl LintLevels {
+    pub fn new() -> LintLevels {
+        LintLevels {
+            levels: HashMap::new(),
+            warnings: None
+        }
+    }
+    pub fn set(&mut self, name: &str, level: Level) -> Result<(), String> {
+        validate_name(name)?;
+        let flag = format!("{} {}", level.flag(), name);
+        if name == WARNINGS {
+            self.warnings = Some((level, flag));
+        } else {
+            self.levels.insert(find(name).unwrap().name, (level, flag));
+        }
+        Ok(())
+    }
+    pub fn level(&self, lint: &Lint) -> Level {
+        self.level_and_notes(lint).0
+    }
+    /// The level of the lint and notes on why it is at that level
+    fn level_and_notes(&self, lint: &Lint) -> (Level, Vec<String>) {
+        let (level, reason) = match self.levels.get(lint.name) {
+            Some((level, flag)) => (*level, format!("`{}` was given", flag)),
+            None => (
+                lint.default_level,
+                format!("`{} {}` is on by default", lint.default_level.flag(), lint.name)
+            )
+        };
+        match &self.warnings {
+            Some((Level::Deny, flag)) if level == Level::Warn => (
+                Level::Deny,
+                vec![reason, format!("`{}` turns every warning into an error", flag)]
+            ),
+            Some((warnings_level, _)) if level == Level::Warn => (*warnings_level, vec![reason]),
+            _ => (level, vec![reason])
+        }
+    }
+}
+
+/// Something legal but suspicious found in the program
+#[derive(Debug, Clone, PartialEq)]
+pub struct LintWarning {
+    pub lint: &'static Lint,
+    pub message: String,
+    pub span: Span,
+    pub label: String,
+    /// Labels pointing at what led to the warning
+    pub secondary_labels: Vec<Label>,
+    pub suggestion: Option<Suggestion>
+}
+
+impl LintWarning {
+    /// A warning about something that does nothing, so removing it is suggested
+    fn new(lint: &'static Lint, message: &str, span: Span, label: String) -> LintWarning {
+        LintWarning {
+            lint,
+            message: message.to_string(),
+            span,
+            label,
+            secondary_labels: vec![],
+            suggestion: Some(Suggestion {
+                message: format!("remove it"),
+                span,
+                replacement: String::new()
+            })
+        }
+    }
+    fn with_suggestion(mut self, suggestion: Option<Suggestion>) -> LintWarning {
+        self.suggestion = suggestion;
+        self
+    }
+    fn with_secondary_label(mut self, span: Span, message: String) -> LintWarning {
+        self.secondary_labels.push(Label::secondary(span, message));
+        self
+    }
+    /// The warning as a diagnostic of the lint's level. None if the lint is allowed
+    pub fn to_diagnostic(&self, levels: &LintLevels) -> Option<Diagnostic> {
+        let (level, notes) = levels.level_and_notes(self.lint);
+        let severity = match level {
+            Level::Allow => return None,
+            Level::Warn => Severity::Warning,
+            Level::Deny => Severity::Error
+        };
+        let mut diagnostic = Diagnostic::new(self.message.clone())
+            .with_severity(severity)
+            .with_code(self.lint.name)
+            .with_label(Label::primary(self.span, self.label.clone()));
+        for label in self.secondary_labels.iter() {
+            diagnostic = diagnostic.with_label(label.clone());
+        }
+        if let Some(suggestion) = &self.suggestion {
+            diagnostic = diagnostic.with_suggestion(
+                suggestion.message.clone(),
+                suggestion.span,
+                suggestion.replacement.clone()
+            );
+        }
+        for note in notes {
+            diagnostic = diagnostic.with_note(note);
+        }
+        Some(diagnostic)
+    }
+}
+
+/// What the analyses run while parsing found to be wrong on some of the paths through
+/// the program, but not on all of them
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Findings {
+    pub maybe_invalid: Vec<MaybeInvalid>,
+    pub expired: Vec<Expired>,
+    pub maybe_invalid_leaches: Vec<MaybeInvalidLeach>
+}
+
+/// Runs every lint on the program, warning about the findings of the analyses. The
+/// levels aren't looked at here, so warnings of allowed lints are returned too
+pub fn check(org_expr: &OrganismExpression, findings: &Findings) -> Vec<LintWarning> {
+    let mut warnings = vec![];
+    // Drills in a row. After 3 of them all the gates are open, no matter how many
+    // were open before them
+    let mut drills_in_a_row = 0;
+    let mut prev: Option<&Expr> = None;
+    for expr in org_expr.exprs.iter() {
+        match expr {
+            Expr::Drill(span) => {
+                if drills_in_a_row >= 3 {
+                    warnings.push(LintWarning::new(
+                        &REDUNDANT_DRILL,
+                        "Drill when all the gates are already open",
+                        *span,
+                        format!("the 3 drills before this opened all the gates")
+                    ));
+                }
+                drills_in_a_row += 1;
+            }
+            Expr::Cell(cell_expr) => {
+                warnings.push(LintWarning::new(
+                    &LONE_CELL_EXPRESSION,
+                    "Cell expression has no effect",
+                    cell_expr.span,
+                    format!("not leached and not leached onto")
+                ));
+            }
+            Expr::Label(label_expr) => {
+                if let Some(Expr::Jump { to, span, .. }) = prev {
+                    if *to == label_expr.label {
+                        warnings.push(LintWarning::new(
+                            &JUMP_TO_NEXT_LABEL,
+                            "Jump to the label right after it",
+                            *span,
+                            format!("goes where the program would have gone anyway")
+                        ));
+                    }
+                }
+            }
+            _ => ()
+        };
+        if !matches!(expr, Expr::Drill(_)) {
+            drills_in_a_row = 0;
+        }
+        prev = Some(expr);
+    }
+    warnings.extend(findings.maybe_invalid.iter().map(maybe_invalid_warning));
+    warnings.extend(findings.expired.iter().map(expired_warning));
+    warnings.extend(findings.maybe_invalid_leaches.iter().map(maybe_invalid_leach_warning));
+    warnings.sort_by_key(|warning| warning.span.start);
+    warnings
+}
+
+fn maybe_invalid_warning(maybe_invalid: &MaybeInvalid) -> LintWarning {
+    let move_to = |region: &str, span: Span| Some(Suggestion {
+        message: format!("move to the {} Region first", region),
+        span,
+        replacement: format!("->{}", &region[..1])
+    });
+    let span = maybe_invalid.span();
+    let (lint, message, label, suggestion) = match maybe_invalid {
+        MaybeInvalid::CellRegion(_) => (
+            &REGION_DEPENDS_ON_PATH,
+            "Cell may be accessed outside the Cells Region",
+            "some of the paths here are in the Layers Region",
+            move_to("Cells", span.start_point())
+        ),
+        MaybeInvalid::StoreRegion(_, region_changes_end) => (
+            &REGION_DEPENDS_ON_PATH,
+            "Primitive may be stored in a cell outside the Cells Region",
+            "some of the paths here are in the Layers Region",
+            move_to("Cells", *region_changes_end)
+        ),
+        MaybeInvalid::PrimitiveRegion(_) => (
+            &REGION_DEPENDS_ON_PATH,
+            "Primitive may be accessed outside the Layers Region",
+            "some of the paths here are in the Cells Region",
+            move_to("Layers", span.start_point())
+        ),
+        MaybeInvalid::DrillRegion(_) => (
+            &REGION_DEPENDS_ON_PATH,
+            "Drill may be in the Cells Region",
+            "some of the paths here are in the Cells Region",
+            move_to("Layers", span.start_point())
+        ),
+        MaybeInvalid::PrimitiveGates(_) => (
+            &GATES_DEPEND_ON_PATH,
+            "Primitive may be accessed when the Layers gates aren't fully open",
+            "the gates aren't fully open on some of the paths here",
+            None
+        )
+    };
+    LintWarning::new(lint, message, span, label.to_string()).with_suggestion(suggestion)
+}
+
+fn expired_warning(expired: &Expired) -> LintWarning {
+    match *expired {
+        Expired::Cell { span, leached, died, always } => {
+            let message = if always {
+                "Cell is read after the expression leached onto it has died"
+            } else {
+                "Cell may be read after the expression leached onto it has died"
+            };
+            LintWarning::new(&DEAD_CELL_READ, message, span, format!("read here"))
+                .with_suggestion(None)
+                .with_secondary_label(leached, format!("the expression is leached onto the cell here"))
+                .with_secondary_label(died, format!("and dies here"))
+        }
+        Expired::Gates { span, opened, closed, always } => {
+            let message = if always {
+                "Primitive is accessed after the Layers gates have closed"
+            } else {
+                "Primitive may be accessed after the Layers gates have closed"
+            };
+            LintWarning::new(&CLOSED_GATES, message, span, format!("accessed here"))
+                .with_suggestion(None)
+                .with_secondary_label(opened, format!("the gates are opened here"))
+                .with_secondary_label(closed, format!("and close here"))
+        }
+    }
+}
+
+fn maybe_invalid_leach_warning(maybe_invalid: &MaybeInvalidLeach) -> LintWarning {
+    let (lint, message, label) = match maybe_invalid {
+        MaybeInvalidLeach::DeathExpression(_) => (
+            &DEATH_EXPRESSION_LEACH,
+            format!("Cell may hold the Death Expression when it's leached onto another"),
+            "nothing is leached onto it on some of the paths here"
+        ),
+        MaybeInvalidLeach::NonFunction(_) => (
+            &NON_FUNCTION_MASSACRE,
+            format!("Massacre may be started by a cell that doesn't hold a rampage primitive"),
+            "holds no rampage primitive on some of the paths here"
+        ),
+        MaybeInvalidLeach::OddOutput(_, given) => (
+            &ODD_OUTPUT_OPERANDS,
+            format!("Output given {} operand cells; the last one is printed on its own", given),
+            "not part of a pair"
+        )
+    };
+    LintWarning::new(lint, &message, maybe_invalid.span(), label.to_string()).with_suggestion(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::parse_with_findings;
+
+    fn lints_found(source: &str) -> Vec<&'static str> {
+        let (org_expr, findings) = parse_with_findings(source);
+        check(&org_expr, &findings).iter().map(|warning| warning.lint.name).collect()
+    }
+
+    /// The indented line after the heading
+    fn example<'a>(explanation: &'a str, heading: &str) -> &'a str {
+        explanation.split(heading).nth(1)
+            .and_then(|rest| rest.lines().find(|line| line.starts_with("    ")))
+            .map(|line| line.trim())
+            .unwrap()
+    }
+
+    #[test]
+    fn test_examples(){
+        for lint in LINTS.iter() {
+            assert_eq!(lints_found(example(lint.explanation, "Example:")), vec![lint.name]);
+            assert_eq!(lints_found(example(lint.explanation, "Fixed example:")), Vec::<&str>::new());
+        }
+    }
+
+    #[test]
+    fn test_redundant_drill(){
+        let drill = r"\\|//";
+        let source = format!("->L{0}{0}{0}$!->C~0->L{0}{0}{0}^^^^^^666^^^^^^=O", drill);
+        assert_eq!(lints_found(&source), Vec::<&str>::new());
+        let source = format!("->L{0}{0}{0}{0}{0}^^^^^^666^^^^^^=O", drill);
+        assert_eq!(lints_found(&source), vec!["redundant-drill", "redundant-drill"]);
+    }
+
+    #[test]
+    fn test_jump_to_next_label(){
+        assert_eq!(lints_found("label:x:ijmp:x:label:y:^^^^^^666^^^^^^=O"), Vec::<&str>::new());
+        assert_eq!(lints_found("ijmp:x:label:x:^^^^^^666^^^^^^=O"), vec!["jump-to-next-label"]);
+    }
+
+    #[test]
+    fn test_levels(){
+        let mut levels = LintLevels::new();
+        assert_eq!(levels.level(&REDUNDANT_DRILL), Level::Warn);
+        levels.set("warnings", Level::Deny).unwrap();
+        levels.set("redundant-drill", Level::Allow).unwrap();
+        assert_eq!(levels.level(&REDUNDANT_DRILL), Level::Allow);
+        assert_eq!(levels.level(&LONE_CELL_EXPRESSION), Level::Deny);
+        levels.set("redundant-drill", Level::Warn).unwrap();
+        assert_eq!(levels.level(&REDUNDANT_DRILL), Level::Deny);
+        assert_eq!(levels.set("no-such-lint", Level::Deny), Err(format!("unknown lint `no-such-lint`")));
+    }
+
+    #[test]
+    fn test_to_diagnostic(){
+        let warning = LintWarning::new(&LONE_CELL_EXPRESSION, "Cell expression has no effect", Span::new(0, 1, 1, 1), format!("here"));
+        let mut levels = LintLevels::new();
+        let diagnostic = warning.to_diagnostic(&levels).unwrap();
+        assert_eq!(diagnostic.severity, Severity::Warning);
+        assert_eq!(diagnostic.code, Some("lone-cell-expression"));
+        assert_eq!(diagnostic.notes, vec![format!("`-W lone-cell-expression` is on by default")]);
+        levels.set("warnings", Level::Deny).unwrap();
+        let diagnostic = warning.to_diagnostic(&levels).unwrap();
+        assert_eq!(diagnostic.severity, Severity::Error);
+        assert_eq!(
+            diagnostic.notes,
+            vec![
+                format!("`-W lone-cell-expression` is on by default"),
+                format!("`-D warnings` turns every warning into an error")
+            ]
+        );
+        levels.set("lone-cell-expression", Level::Allow).unwrap();
+        assert_eq!(warning.to_diagnostic(&levels), None);
+    }
+}
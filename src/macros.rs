@@ -0,0 +1,212 @@
+//! Expands macros, between the lexer and the parser.
+//!
+//! A macro is defined with `macro:name:a,b:`, followed by its body and `endmacro:`.
+//! `<a>` in the body is where the argument for the parameter a goes. A call,
+//! `call:name:`, is followed by one cell or primitive for each parameter, and is
+//! replaced by the body with the arguments in place of the placeholders
+
+use std::collections::{HashMap, VecDeque};
+use crate::errors::{CompileError, DEFAULT_ERROR_LIMIT};
+use crate::lexer::{self, Span, Token, TokenKind};
+
+struct Macro {
+    params: Vec<String>,
+    /// The body, with the calls in it already expanded
+    body: Vec<Token>,
+    /// The span of the start of the definition
+    span: Span
+}
+
+/// Hands out the tokens of the stream with the macro definitions taken out and the
+/// calls expanded. Every token of an expansion has the span of the call, so errors
+/// in it point at the call.
+/// A macro must be defined before it's called, so it can't call itself
+pub struct MacroExpander<I: Iterator<Item=Result<Token, CompileError>>> {
+    tokens: I,
+    macros: HashMap<String, Macro>,
+    /// Tokens and errors ready to be handed out
+    ready: VecDeque<Result<Token, CompileError>>
+}
+
+impl<I: Iterator<Item=Result<Token, CompileError>>> MacroExpander<I> {
+    pub fn new(tokens: I) -> MacroExpander<I> {
+        MacroExpander {
+            tokens,
+            macros: HashMap::new(),
+            ready: VecDeque::new()
+        }
+    }
+    /// Reads the definition of the macro, up to its endmacro:
+    fn define(&mut self, name: String, params: Vec<String>, span: Span){
+        let mut body: Vec<Token> = vec![];
+        loop {
+            let token = match self.tokens.next() {
+                Some(Ok(token)) => token,
+                Some(Err(err)) => {
+                    self.ready.push_back(Err(err));
+                    continue;
+                }
+                None => {
+                    self.ready.push_back(Err(CompileError::MacroNotClosed(span)));
+                    return;
+                }
+            };
+            match token.kind {
+                TokenKind::EndMacro => break,
+                // Definitions can't be nested, so this one must have been left open
+                TokenKind::Macro(inner_name, inner_params) => {
+                    self.ready.push_back(Err(CompileError::MacroNotClosed(span)));
+                    self.define(inner_name, inner_params, token.span);
+                    return;
+                }
+                TokenKind::Call(callee) => match self.expand(callee, token.span, &params) {
+                    Ok(expansion) => body.extend(expansion),
+                    Err(err) => self.ready.push_back(Err(err))
+                },
+                TokenKind::Placeholder(ref placeholder) if !params.contains(placeholder) => {
+                    self.ready.push_back(Err(CompileError::UnknownPlaceholder(token.span, placeholder.clone())));
+                }
+                _ => body.push(token)
+            };
+        }
+        match self.macros.get(&name) {
+            Some(first) => self.ready.push_back(Err(CompileError::DuplicateMacro(first.span, span, name))),
+            None => {
+                self.macros.insert(name, Macro { params, body, span });
+            }
+        };
+    }
+    /// The body of the macro called at the span, with the arguments after the call in
+    /// place of the placeholders. Placeholders can only be arguments in the body of a
+    /// macro with those parameters
+    fn expand(&mut self, name: String, span: Span, params: &[String]) -> Result<Vec<Token>, CompileError> {
+        let called = match self.macros.get(&name) {
+            Some(called) => called,
+            None => return Err(CompileError::UndefinedMacro(span, name))
+        };
+        let called_params = called.params.clone();
+        let mut args: HashMap<&str, TokenKind> = HashMap::new();
+        let mut call_span = span;
+        for param in called_params.iter() {
+            let arg = match self.tokens.next() {
+                Some(Ok(arg)) => arg,
+                Some(Err(err)) => return Err(err),
+                None => return Err(CompileError::ExpectedMacroArgument(call_span.end_point(), name, param.clone()))
+            };
+            match &arg.kind {
+                TokenKind::CellIdent(_) | TokenKind::PrimitiveIdent(_) => (),
+                TokenKind::Placeholder(placeholder) if params.contains(placeholder) => (),
+                TokenKind::Placeholder(placeholder) =>
+                    return Err(CompileError::UnknownPlaceholder(arg.span, placeholder.clone())),
+                _ => return Err(CompileError::ExpectedMacroArgument(arg.span, name, param.clone()))
+            };
+            call_span = span.to(arg.span);
+            args.insert(param, arg.kind);
+        }
+        let body = &self.macros[&name].body;
+        Ok(body.iter()
+            .map(|token| {
+                let kind = match &token.kind {
+                    TokenKind::Placeholder(placeholder) => args[placeholder.as_str()].clone(),
+                    kind => kind.clone()
+                };
+                Token::new(kind, call_span)
+            })
+            .collect())
+    }
+}
+
+impl<I: Iterator<Item=Result<Token, CompileError>>> Iterator for MacroExpander<I> {
+    type Item = Result<Token, CompileError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.ready.is_empty() {
+            let token = match self.tokens.next()? {
+                Ok(token) => token,
+                Err(err) => return Some(Err(err))
+            };
+            match token.kind {
+                TokenKind::Macro(name, params) => self.define(name, params, token.span),
+                TokenKind::EndMacro => self.ready.push_back(Err(CompileError::EndMacroWithoutMacro(token.span))),
+                TokenKind::Call(name) => match self.expand(name, token.span, &[]) {
+                    Ok(expansion) => self.ready.extend(expansion.into_iter().map(Ok)),
+                    Err(err) => self.ready.push_back(Err(err))
+                },
+                TokenKind::Placeholder(placeholder) =>
+                    self.ready.push_back(Err(CompileError::UnknownPlaceholder(token.span, placeholder))),
+                _ => return Some(Ok(token))
+            };
+        }
+        self.ready.pop_front()
+    }
+}
+
+/// Expands the macros in the tokens, reporting all the errors, until there are
+/// DEFAULT_ERROR_LIMIT of them
+pub fn expand(tokens: Vec<Token>) -> Result<Vec<Token>, Vec<CompileError>> {
+    lexer::collect_tokens(MacroExpander::new(tokens.into_iter().map(Ok)), DEFAULT_ERROR_LIMIT)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::tokenize;
+    use crate::printer::print_tokens;
+
+    fn expand_source(source: &str) -> Result<String, Vec<CompileError>> {
+        expand(tokenize(source).unwrap()).map(|tokens| print_tokens(&tokens))
+    }
+
+    #[test]
+    fn test_expand(){
+        let source = "macro:rescue:a,b,c,d:->C<a>~<c><b>~<d>endmacro:call:rescue:1234->L\\\\|//call:rescue:3456^^^^^^666^^^^^^=O";
+        assert_eq!(expand_source(source), Ok(format!("->C1~32~4->L\\\\|//->C3~54~6^^^^^^666^^^^^^=O")));
+        let source = "macro:store:p,c:->L<p>->C~<c>endmacro:call:store:$!0^^^^^^666^^^^^^=O";
+        assert_eq!(expand_source(source), Ok(format!("->L$!->C~0^^^^^^666^^^^^^=O")));
+    }
+
+    #[test]
+    fn test_nested_calls(){
+        let source = "macro:copy:a,b:<a>~<b>endmacro:macro:swap:a,b:call:copy:<a>Ecall:copy:<b><a>call:copy:E<b>endmacro:->Ccall:swap:01^^^^^^666^^^^^^=O";
+        assert_eq!(expand_source(source), Ok(format!("->C0~E1~0E~1^^^^^^666^^^^^^=O")));
+    }
+
+    #[test]
+    fn test_call_span(){
+        let source = "macro:copy:a,b:<a>~<b>endmacro:->Ccall:copy:01^^^^^^666^^^^^^=O";
+        let tokens = expand(tokenize(source).unwrap()).unwrap();
+        let call_span = Span::new(34, 46, 1, 35);
+        assert_eq!(tokens[1], Token::new(TokenKind::CellIdent(format!("0")), call_span));
+        assert_eq!(tokens[3], Token::new(TokenKind::CellIdent(format!("1")), call_span));
+    }
+
+    #[test]
+    fn test_errors(){
+        assert_eq!(
+            expand_source("call:m:macro:m::0~1endmacro:^^^^^^666^^^^^^=O"),
+            Err(vec![CompileError::UndefinedMacro(Span::new(0, 7, 1, 1), format!("m"))])
+        );
+        assert_eq!(
+            expand_source("macro:m::0~1endmacro:macro:m::0~2endmacro:^^^^^^666^^^^^^=O"),
+            Err(vec![CompileError::DuplicateMacro(Span::new(0, 9, 1, 1), Span::new(21, 30, 1, 22), format!("m"))])
+        );
+        assert_eq!(
+            expand_source("macro:m:a:<a>~1endmacro:call:m:~^^^^^^666^^^^^^=O"),
+            Err(vec![CompileError::ExpectedMacroArgument(Span::new(31, 32, 1, 32), format!("m"), format!("a"))])
+        );
+        assert_eq!(
+            expand_source("macro:m:a:<b>~1endmacro:<a>^^^^^^666^^^^^^=O"),
+            Err(vec![
+                CompileError::UnknownPlaceholder(Span::new(10, 13, 1, 11), format!("b")),
+                CompileError::UnknownPlaceholder(Span::new(24, 27, 1, 25), format!("a"))
+            ])
+        );
+        assert_eq!(
+            expand_source("macro:m::0~1macro:n::0~2endmacro:endmacro:^^^^^^666^^^^^^=O"),
+            Err(vec![
+                CompileError::MacroNotClosed(Span::new(0, 9, 1, 1)),
+                CompileError::EndMacroWithoutMacro(Span::new(33, 42, 1, 34))
+            ])
+        );
+    }
+}
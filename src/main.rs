@@ -1,12 +1,17 @@
 #[macro_use]
 extern crate clap;
 use std::{fs, process, io};
-use clap::{App, Arg};
+use std::cell::{Cell, RefCell};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+use mindbend::{diagnostics, dump, errors, explain, lints, message_format, printer};
+use mindbend::{CompileOptions, Error, OptLevel, Options};
+use mindbend::message_format::MessageFormat;
+use mindbend::diagnostics::Diagnostic;
+use mindbend::source_map::SourceMap;
+use mindbend::emit::{CodeKind, Emit, EmitKind};
 
-mod lexer;
-mod errors;
-mod parser;
-mod codegen;
 #[cfg(test)]
 mod tests;
 
@@ -17,10 +22,11 @@ fn main(){
         .version(crate_version!())
         .author(crate_authors!())
         .about(crate_description!())
+        .setting(AppSettings::SubcommandsNegateReqs)
         .arg(
             Arg::with_name("input file")
                 .help("Source file to be compiled")
-                .required(true)
+                .required_unless("print")
                 .index(1)
         )
         .arg(
@@ -31,58 +37,451 @@ fn main(){
                 .required(false)
                 .default_value(DEFAULT_OUT_FILENAME)
         )
+        .arg(
+            Arg::with_name("error limit")
+                .help("Number of errors to report before giving up")
+                .long("error-limit")
+                .takes_value(true)
+                .validator(|limit| match limit.parse::<usize>() {
+                    Ok(limit) if limit > 0 => Ok(()),
+                    _ => Err(format!("the error limit must be a number greater than 0"))
+                })
+        )
+        .arg(
+            Arg::with_name("message format")
+                .help("How errors are reported. json prints one JSON object per line and sarif prints a SARIF 2.1.0 log, both on stdout, so nothing else can be emitted there")
+                .long("message-format")
+                .takes_value(true)
+                .possible_values(&MessageFormat::NAMES)
+                .default_value("human")
+        )
+        .arg(
+            Arg::with_name("emit")
+                .help("What to output, as KIND or KIND=PATH. expanded is the program with the files it includes read in and its macros expanded. tokens and ast are its tokens and the parsed program, as JSON. llvm-ir, llvm-bc, asm and obj are the generated code as LLVM IR, LLVM bitcode, assembly and an object file. link is the executable. Without a path, llvm-bc and obj are written next to the output file with their extension and the rest are printed on stdout. Only the executable is output by default")
+                .long("emit")
+                .value_name("KIND[=PATH]")
+                .takes_value(true)
+                .multiple(true)
+                .use_delimiter(true)
+                .validator(|value| Emit::parse(&value).map(|_| ()))
+        )
+        .arg(
+            Arg::with_name("opt level")
+                .help("How hard the generated code is optimized, from 0, with no optimization passes, to 3. s optimizes like 2 but for smaller code")
+                .short("O")
+                .long("opt-level")
+                .value_name("LEVEL")
+                .takes_value(true)
+                .possible_values(&OptLevel::NAMES)
+                .default_value("0")
+        )
+        .arg(
+            Arg::with_name("object only")
+                .help("Stops after writing the object file, to the output file if it's given and next to it with the .o extension if it isn't")
+                .short("c")
+                .conflicts_with("emit")
+        )
+        .arg(
+            Arg::with_name("linker")
+                .help("The program the executable is linked with, like cc, clang or ld, instead of gcc. A linker like ld is run on its own, without the C runtime a compiler driver adds, so its start files, -lc and the dynamic linker have to be passed with --link-arg")
+                .long("linker")
+                .value_name("PATH")
+                .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("link arg")
+                .help("An arg to pass to the linker, after the ones the compiler passes")
+                .long("link-arg")
+                .value_name("ARG")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .allow_hyphen_values(true)
+        )
+        .arg(
+            Arg::with_name("pie")
+                .help("Links a position independent executable, generating position independent code for it")
+                .long("pie")
+        )
+        .arg(
+            Arg::with_name("static")
+                .help("Links the executable statically")
+                .long("static")
+        )
+        .arg(
+            Arg::with_name("target")
+                .help("The target triple to generate code for, like aarch64-linux-gnu, instead of the host. The executable is linked with the cross gcc named after the triple, like aarch64-linux-gnu-gcc")
+                .long("target")
+                .value_name("TRIPLE")
+                .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("target cpu")
+                .help("The CPU the generated code may use the instructions of, like x86-64-v3 or cortex-a72. native is the host's CPU. Without one, the code is for any CPU of the target")
+                .long("target-cpu")
+                .value_name("CPU")
+                .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("target feature")
+                .help("A feature of the target to turn on, as +feature, or off, as -feature, on top of the CPU's")
+                .long("target-feature")
+                .value_name("FEATURE")
+                .takes_value(true)
+                .multiple(true)
+                .use_delimiter(true)
+                .validator(|feature| if feature.starts_with('+') || feature.starts_with('-') {
+                    Ok(())
+                } else {
+                    Err(format!("expected +{0} or -{0}", feature))
+                })
+        )
+        .arg(
+            Arg::with_name("sysroot")
+                .help("The directory the cross linker looks for the target's libraries in")
+                .long("sysroot")
+                .value_name("DIR")
+                .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("print")
+                .help("Prints information about the compiler instead of compiling. targets lists the targets code can be generated for")
+                .long("print")
+                .value_name("INFO")
+                .takes_value(true)
+                .possible_values(&["targets"])
+        )
+        .arg(
+            Arg::with_name("include dir")
+                .help("Looks for included files in the directory, if they aren't next to the file including them. Directories are searched in the order they are given")
+                .short("I")
+                .value_name("DIR")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+        )
+        .arg(lint_level_arg("warn", "W", "Reports the lint as a warning"))
+        .arg(lint_level_arg("allow", "A", "Doesn't report the lint"))
+        .arg(lint_level_arg("deny", "D", "Reports the lint as an error"))
+        .subcommand(
+            SubCommand::with_name("explain")
+                .about("Explains an error code, like MB0007, or a lint, like redundant-drill, in detail")
+                .arg(
+                    Arg::with_name("code")
+                        .help("The error code or lint name")
+                        .required(true)
+                        .index(1)
+                )
+        )
         .get_matches();
-    let input_file = args.value_of("input file").unwrap();
-    let input: String;
-    match fs::read_to_string(&input_file){
-        Ok(source) => {
-            if source.len() == 0 {
-                eprintln!("The input file is empty");
+    if let Some(explain_args) = args.subcommand_matches("explain") {
+        let code = explain_args.value_of("code").unwrap();
+        match explain::explain(code){
+            Some(explanation) => print!("{}", explanation),
+            None => {
+                eprintln!("{} is not a Mindbend error code or lint", code);
                 process::exit(1);
             }
-            input = source;
+        };
+        return;
+    }
+    if args.value_of("print") == Some("targets") {
+        print_targets();
+        return;
+    }
+    let input_file = args.value_of("input file").unwrap();
+    let options = Options {
+        search_path: args.values_of("include dir")
+            .map(|dirs| dirs.map(PathBuf::from).collect())
+            .unwrap_or_default(),
+        error_limit: args.value_of("error limit")
+            .map(|limit| limit.parse().unwrap())
+            .unwrap_or(errors::DEFAULT_ERROR_LIMIT),
+        lint_levels: lint_levels(&args)
+    };
+    let format = MessageFormat::from_name(args.value_of("message format").unwrap()).unwrap();
+    let source_map = Rc::new(RefCell::new(SourceMap::new()));
+    source_map.borrow_mut().add_file(Path::new(input_file));
+    let report = Report {
+        format,
+        error_limit: options.error_limit,
+        source_map: Rc::clone(&source_map),
+        rendered: Cell::new(false)
+    };
+    let emits: Vec<Emit> = match args.values_of("emit") {
+        // The values have already been validated
+        Some(values) => values.map(|value| Emit::parse(value).unwrap()).collect(),
+        None if args.is_present("object only") => {
+            // Without a path, the object file is written next to the output file
+            let path = Some(PathBuf::from(args.value_of("output file").unwrap()))
+                .filter(|_| args.occurrences_of("output file") > 0);
+            vec![Emit { kind: EmitKind::Code(CodeKind::Obj), path }]
         },
-        Err(e) => {
-            match e.kind() {
-                io::ErrorKind::NotFound => eprintln!("The input file doesn't exist"),
-                _ => eprintln!("An error occured. (It's most likely your fault)")
+        None => vec![Emit { kind: EmitKind::Link, path: None }]
+    };
+    // The JSON and SARIF diagnostics are printed on stdout, and what's emitted there
+    // would get mixed up with them
+    if format != MessageFormat::Human && emits.iter().any(Emit::is_printed) {
+        eprintln!(
+            "Can't print what's emitted along with the {} diagnostics on stdout. Give each emit a path, as KIND=PATH",
+            args.value_of("message format").unwrap()
+        );
+        process::exit(1);
+    }
+    let find_emit = |kind: EmitKind| emits.iter().find(|emit| emit.kind == kind);
+    let expanded = find_emit(EmitKind::Expanded);
+    let tokens_emit = find_emit(EmitKind::Tokens);
+    let ast_emit = find_emit(EmitKind::Ast);
+    let link = find_emit(EmitKind::Link);
+    let code_emits: Vec<(CodeKind, Option<&PathBuf>)> = emits.iter()
+        .filter_map(|emit| match emit.kind {
+            EmitKind::Code(kind) => Some((kind, emit.path.as_ref())),
+            _ => None
+        })
+        .collect();
+    let generates_code = link.is_some() || !code_emits.is_empty();
+    let input = match fs::File::open(&input_file){
+        Ok(file) => file,
+        Err(err) => {
+            let reason = match err.kind() {
+                io::ErrorKind::NotFound => format!("{} doesn't exist", input_file),
+                _ => format!("{}: {}", input_file, err)
             };
-            process::exit(1);
+            report_errs_and_exit(&[errors::CompileError::Read(reason)], &[], &report);
         }
     };
-    let tokens: Vec<lexer::Token>;
-    match lexer::tokenize(&input){
-        Ok(t) => tokens = t,
-        Err(err) => {
-            eprintln!("{}", err.as_str());
-            process::exit(1);
+    if input.metadata().map(|metadata| metadata.len() == 0).unwrap_or(false) {
+        let reason = format!("{} is empty", input_file);
+        report_errs_and_exit(&[errors::CompileError::Read(reason)], &[], &report);
+    }
+    // The source is tokenized and its macros expanded as it's parsed, never being in
+    // memory all at once, unless the expanded program or its tokens are to be written out
+    let input = io::BufReader::new(input);
+    let program = if expanded.is_some() || tokens_emit.is_some() {
+        let tokens = match mindbend::tokenize_all(input, source_map, &options) {
+            Ok(tokens) => tokens,
+            Err(err) => report_error_and_exit(err, &[], &report)
+        };
+        if let Some(expanded) = expanded {
+            // No newline, since whitespace isn't allowed in Mindbend
+            write_emit(expanded, &printer::print_tokens(&tokens), "expanded program", &report);
+        }
+        if let Some(tokens_emit) = tokens_emit {
+            let json = dump::tokens_to_json(&tokens, &report.source_map.borrow());
+            write_emit(tokens_emit, &format!("{:#}\n", json), "tokens", &report);
         }
+        if !generates_code && ast_emit.is_none() {
+            return;
+        }
+        mindbend::parse(tokens.into_iter().map(Ok), &options)
+    } else {
+        mindbend::parse(mindbend::tokenize(input, source_map, &options), &options)
     };
-    if tokens.len() == 0 {
-        eprintln!("Only Organism death.");
-        process::exit(1);
+    let program = match program {
+        Ok(program) => program,
+        Err(err) => report_error_and_exit(err, &[], &report)
+    };
+    if let Some(ast_emit) = ast_emit {
+        let json = dump::program_to_json(&program, &report.source_map.borrow());
+        write_emit(ast_emit, &format!("{:#}\n", json), "AST", &report);
+        if !generates_code {
+            return;
+        }
+    }
+    let warnings = match mindbend::check(&program, &options) {
+        Ok(warnings) => warnings,
+        Err(err) => report_error_and_exit(err, &[], &report)
+    };
+    report_warnings(&warnings, &report);
+    let mut compile_options = CompileOptions::default();
+    compile_options.opt_level = OptLevel::from_name(args.value_of("opt level").unwrap()).unwrap();
+    compile_options.target = args.value_of("target").map(String::from);
+    compile_options.sysroot = args.value_of("sysroot").map(PathBuf::from);
+    compile_options.target_cpu = args.value_of("target cpu").map(String::from);
+    compile_options.target_features = args.values_of("target feature")
+        .map(|features| features.map(String::from).collect())
+        .unwrap_or_default();
+    compile_options.linker = args.value_of("linker").map(PathBuf::from);
+    compile_options.link_args = args.values_of("link arg")
+        .map(|link_args| link_args.map(String::from).collect())
+        .unwrap_or_default();
+    compile_options.pie = args.is_present("pie");
+    compile_options.static_link = args.is_present("static");
+    let context = mindbend::Context::create();
+    let code = match mindbend::generate(&program, &context, &compile_options) {
+        Ok(code) => code,
+        Err(err) => report_error_and_exit(err, &warnings, &report)
+    };
+    let out_filename = PathBuf::from(args.value_of("output file").unwrap());
+    for (kind, path) in code_emits {
+        let written = match path {
+            Some(path) => code.write(kind, path),
+            None if kind.is_text() => code.emit(kind).map(|text| print!("{}", String::from_utf8_lossy(&text))),
+            None => code.write(kind, &out_filename.with_extension(kind.extension()))
+        };
+        if let Err(err) = written {
+            report_error_and_exit(err, &warnings, &report);
+        }
+    }
+    if let Some(link) = link {
+        let path = link.path.as_ref().unwrap_or(&out_filename);
+        if let Err(err) = code.link(path) {
+            report_error_and_exit(err, &warnings, &report);
+        }
+    }
+    if report.format == MessageFormat::Sarif {
+        println!("{}", message_format::to_sarif(&warnings, &report.source_map.borrow()));
+    }
+}
+
+/// Lists the targets code can be generated for, with the host's triple
+fn print_targets() {
+    println!("Default target: {}", mindbend::host_target());
+    println!();
+    println!("Registered targets:");
+    let targets = mindbend::targets();
+    let width = targets.iter().map(|(name, _)| name.len()).max().unwrap_or(0);
+    for (name, description) in targets {
+        println!("  {:width$} - {}", name, description, width = width);
     }
-    let mut parser = parser::Parser::new(tokens);
-    let ast: parser::OrganismExpression;
-    let labels: Vec<String>;
-    match parser.parse(){
-        Ok(oe) => {
-            ast = oe.0;
-            labels = oe.1;
+}
+
+/// Writes what was emitted to the emit's path, or prints it if it has none.
+/// What it is, is only for the error message
+fn write_emit(emit: &Emit, contents: &str, what: &str, report: &Report) {
+    match &emit.path {
+        Some(path) => if let Err(err) = fs::write(path, contents) {
+            let reason = format!("the {} to {}: {}", what, path.display(), err);
+            report_errs_and_exit(&[errors::CompileError::ObjectWrite(reason)], &[], report);
         },
-        Err(err) => {
-            eprintln!("{}", err.as_str());
-            process::exit(1);
+        None => print!("{}", contents)
+    };
+}
+
+/// An arg for one of -W, -A and -D, which set the level of a lint, or of all the
+/// lints that warn with `warnings`
+fn lint_level_arg<'a, 'b>(name: &'a str, short: &'a str, help: &'b str) -> Arg<'a, 'b> {
+    Arg::with_name(name)
+        .help(help)
+        .long(name)
+        .short(short)
+        .value_name("LINT")
+        .takes_value(true)
+        .multiple(true)
+        .number_of_values(1)
+        .validator(|name| lints::validate_name(&name))
+}
+
+/// The lint levels set by the -W, -A and -D flags. A later flag overrides an earlier one
+fn lint_levels(args: &ArgMatches) -> lints::LintLevels {
+    let mut flags: Vec<(usize, lints::Level, &str)> = vec![];
+    for (arg, level) in [
+        ("warn", lints::Level::Warn),
+        ("allow", lints::Level::Allow),
+        ("deny", lints::Level::Deny)
+    ].iter() {
+        if let (Some(indices), Some(names)) = (args.indices_of(arg), args.values_of(arg)) {
+            flags.extend(indices.zip(names).map(|(index, name)| (index, *level, name)));
         }
+    }
+    flags.sort_by_key(|(index, _, _)| *index);
+    let mut levels = lints::LintLevels::new();
+    for (_, level, name) in flags {
+        // The names have already been validated
+        levels.set(name, level).unwrap();
+    }
+    levels
+}
+
+/// Reports what a stage of the compilation failed with and exits. The warnings are the
+/// ones reported before the stage
+fn report_error_and_exit(err: Error, warnings: &[Diagnostic], report: &Report) -> ! {
+    match err {
+        Error::Compile(errs) => report_errs_and_exit(&errs, warnings, report),
+        Error::Denied { denied, warnings } => {
+            report_warnings(&warnings, report);
+            report_and_exit(&denied, false, &warnings, report);
+        }
+        Error::OnlyDeath => report_errs_and_exit(&[errors::CompileError::OnlyDeath], warnings, report)
     };
-    let mut codegen = codegen::CodeGen::new(ast, labels);
-    match codegen.code(){
-        Ok(()) => (),
-        Err(err) => {
-            eprintln!("{}", err.as_str());
-            process::exit(1);
+}
+
+fn report_errs_and_exit(errs: &[errors::CompileError], warnings: &[Diagnostic], report: &Report) -> ! {
+    let diagnostics: Vec<Diagnostic> = errs.iter().map(|err| err.to_diagnostic()).collect();
+    report_and_exit(&diagnostics, errs.len() >= report.error_limit, warnings, report);
+}
+
+/// How errors are to be reported, and the files of the program they point into.
+/// The files are only read again when there is something to show
+struct Report {
+    format: MessageFormat,
+    error_limit: usize,
+    source_map: Rc<RefCell<SourceMap>>,
+    /// Whether a diagnostic has been rendered for a human, so the next one is
+    /// separated from it by a blank line
+    rendered: Cell<bool>
+}
+
+/// Reports the warnings as soon as they are found. In the SARIF format they are only
+/// reported in the log written at the end
+fn report_warnings(warnings: &[Diagnostic], report: &Report) {
+    if warnings.is_empty() {
+        return;
+    }
+    let source_map = report.source_map.borrow();
+    match report.format {
+        MessageFormat::Human => for warning in warnings.iter() {
+            render_human(warning, report, &source_map);
+        },
+        MessageFormat::Json => print!("{}", message_format::to_json_lines(warnings, &source_map)),
+        MessageFormat::Sarif => ()
+    };
+}
+
+/// Reports the errors and exits. The warnings must have already been reported with
+/// report_warnings
+fn report_and_exit(
+    errors: &[Diagnostic],
+    hit_error_limit: bool,
+    warnings: &[Diagnostic],
+    report: &Report
+) -> ! {
+    let source_map = report.source_map.borrow();
+    match report.format {
+        MessageFormat::Human => report_human(errors, hit_error_limit, report, &source_map),
+        MessageFormat::Json => print!("{}", message_format::to_json_lines(errors, &source_map)),
+        MessageFormat::Sarif => {
+            let diagnostics: Vec<Diagnostic> = warnings.iter().chain(errors.iter()).cloned().collect();
+            println!("{}", message_format::to_sarif(&diagnostics, &source_map));
         }
     };
-    let out_filename = args.value_of("output file").unwrap();
-    codegen.write_code_to_file(out_filename);
+    process::exit(1);
+}
+
+fn report_human(errors: &[Diagnostic], hit_error_limit: bool, report: &Report, source_map: &SourceMap) {
+    for diagnostic in errors.iter() {
+        render_human(diagnostic, report, source_map);
+    }
+    if hit_error_limit {
+        eprintln!("Stopped at the error limit of {}. Use --error-limit to see more", report.error_limit);
+    }
+    let mut codes: Vec<&str> = errors.iter().filter_map(|diagnostic| diagnostic.code).collect();
+    codes.sort();
+    codes.dedup();
+    if codes.len() == 1 {
+        eprintln!("For more information about this error, try `mindbend explain {}`", codes[0]);
+    } else if codes.len() > 1 {
+        eprintln!(
+            "For more information about these errors, try `mindbend explain` with one of {}",
+            codes.join(", ")
+        );
+    }
+}
+
+/// Renders the diagnostic on stderr, with a blank line between it and the one before
+fn render_human(diagnostic: &Diagnostic, report: &Report, source_map: &SourceMap) {
+    if report.rendered.replace(true) {
+        eprintln!();
+    }
+    eprint!("{}", diagnostics::render_in(diagnostic, source_map));
 }
@@ -0,0 +1,282 @@
+//! Machine readable forms of the diagnostics, for tools that can't be
+//! bothered to read what the compiler has to say
+
+use serde_json::{json, Value};
+use crate::diagnostics::{Diagnostic, Label, LabelStyle, Suggestion};
+use crate::explain;
+use crate::lexer::Span;
+use crate::source_map::SourceMap;
+
+const SARIF_SCHEMA: &'static str = "https://json.schemastore.org/sarif-2.1.0.json";
+const SARIF_VERSION: &'static str = "2.1.0";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageFormat {
+    Human,
+    Json,
+    Sarif
+}
+
+impl MessageFormat {
+    pub const NAMES: [&'static str; 3] = ["human", "json", "sarif"];
+
+    pub fn from_name(name: &str) -> Option<MessageFormat> {
+        match name {
+            "human" => Some(MessageFormat::Human),
+            "json" => Some(MessageFormat::Json),
+            "sarif" => Some(MessageFormat::Sarif),
+            _ => None
+        }
+    }
+}
+
+/// One JSON object per diagnostic, each on its own line
+pub fn to_json_lines(diagnostics: &[Diagnostic], source_map: &SourceMap) -> String {
+    diagnostics.iter()
+        .map(|diagnostic| format!("{}\n", to_json(diagnostic, source_map)))
+        .collect()
+}
+
+pub fn to_json(diagnostic: &Diagnostic, source_map: &SourceMap) -> Value {
+    let primary = diagnostic.labels.iter().find(|label| label.style == LabelStyle::Primary);
+    let related: Vec<Value> = diagnostic.labels.iter()
+        .filter(|label| label.style == LabelStyle::Secondary)
+        .map(|label| json!({
+            "span": span_to_json(label.span, source_map),
+            "message": label.message
+        }))
+        .collect();
+    json!({
+        "code": diagnostic.code,
+        "severity": diagnostic.severity.to_string(),
+        "message": diagnostic.message,
+        "span": primary.map(|label| span_to_json(label.span, source_map)),
+        "label": primary.map(|label| label.message.clone()),
+        "related": related,
+        "suggestion": diagnostic.suggestion.as_ref().map(|suggestion| json!({
+            "message": suggestion.message,
+            "span": span_to_json(suggestion.span, source_map),
+            "replacement": suggestion.replacement
+        })),
+        "rendered": crate::diagnostics::render_in(diagnostic, source_map)
+    })
+}
+
+fn span_to_json(span: Span, source_map: &SourceMap) -> Value {
+    let source = source_map.source(span.file);
+    let (line, column) = position(&source, span.start);
+    let (end_line, end_column) = position(&source, span.end);
+    json!({
+        "file": source_map.name(span.file),
+        "start": span.start,
+        "end": span.end,
+        "line": line,
+        "column": column,
+        "end_line": end_line,
+        "end_column": end_column
+    })
+}
+
+/// A SARIF 2.1.0 log with a single run holding all the diagnostics, with every file
+/// of the program as an artifact
+pub fn to_sarif(diagnostics: &[Diagnostic], source_map: &SourceMap) -> Value {
+    let mut codes: Vec<&'static str> = diagnostics.iter()
+        .filter_map(|diagnostic| diagnostic.code)
+        .collect();
+    codes.sort();
+    codes.dedup();
+    let rules: Vec<Value> = codes.iter()
+        .map(|code| {
+            let explanation = explain::explain(code).unwrap_or("");
+            json!({
+                "id": code,
+                "shortDescription": { "text": explanation.lines().next().unwrap_or("") },
+                "fullDescription": { "text": explanation }
+            })
+        })
+        .collect();
+    let results: Vec<Value> = diagnostics.iter()
+        .map(|diagnostic| sarif_result(diagnostic, &codes, source_map))
+        .collect();
+    let artifacts: Vec<Value> = source_map.files()
+        .map(|file| json!({ "location": { "uri": source_map.name(file) } }))
+        .collect();
+    json!({
+        "$schema": SARIF_SCHEMA,
+        "version": SARIF_VERSION,
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": env!("CARGO_PKG_NAME"),
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "rules": rules
+                }
+            },
+            "artifacts": artifacts,
+            "results": results
+        }]
+    })
+}
+
+fn sarif_result(diagnostic: &Diagnostic, codes: &[&str], source_map: &SourceMap) -> Value {
+    let mut result = json!({
+        "level": diagnostic.severity.to_string(),
+        "message": { "text": diagnostic.message }
+    });
+    if let Some(code) = diagnostic.code {
+        result["ruleId"] = json!(code);
+        result["ruleIndex"] = json!(codes.iter().position(|c| *c == code));
+    }
+    let primary = diagnostic.labels.iter().find(|label| label.style == LabelStyle::Primary);
+    if let Some(label) = primary {
+        result["locations"] = json!([sarif_location(label, source_map)]);
+    }
+    let related: Vec<Value> = diagnostic.labels.iter()
+        .filter(|label| label.style == LabelStyle::Secondary)
+        .enumerate()
+        .map(|(id, label)| {
+            let mut location = sarif_location(label, source_map);
+            location["id"] = json!(id);
+            location
+        })
+        .collect();
+    if !related.is_empty() {
+        result["relatedLocations"] = json!(related);
+    }
+    if let Some(suggestion) = &diagnostic.suggestion {
+        result["fixes"] = json!([sarif_fix(suggestion, source_map)]);
+    }
+    result
+}
+
+fn sarif_location(label: &Label, source_map: &SourceMap) -> Value {
+    json!({
+        "physicalLocation": {
+            "artifactLocation": { "uri": source_map.name(label.span.file) },
+            "region": sarif_region(label.span, source_map)
+        },
+        "message": { "text": label.message }
+    })
+}
+
+fn sarif_fix(suggestion: &Suggestion, source_map: &SourceMap) -> Value {
+    json!({
+        "description": { "text": suggestion.message },
+        "artifactChanges": [{
+            "artifactLocation": { "uri": source_map.name(suggestion.span.file) },
+            "replacements": [{
+                "deletedRegion": sarif_region(suggestion.span, source_map),
+                "insertedContent": { "text": suggestion.replacement }
+            }]
+        }]
+    })
+}
+
+fn sarif_region(span: Span, source_map: &SourceMap) -> Value {
+    let source = source_map.source(span.file);
+    let (start_line, start_column) = position(&source, span.start);
+    let (end_line, end_column) = position(&source, span.end);
+    json!({
+        "startLine": start_line,
+        "startColumn": start_column,
+        "endLine": end_line,
+        "endColumn": end_column,
+        "byteOffset": span.start,
+        "byteLength": span.len()
+    })
+}
+
+/// The line and column, both starting from 1, of the byte offset in the source.
+/// An offset past the end, or inside a char, is taken to be at the char before it
+fn position(source: &str, offset: usize) -> (usize, usize) {
+    let mut offset = offset.min(source.len());
+    while !source.is_char_boundary(offset) {
+        offset -= 1;
+    }
+    let before = &source[..offset];
+    let line = before.matches('\n').count() + 1;
+    let line_start = before.rfind('\n').map(|i| i + 1).unwrap_or(0);
+    (line, before[line_start..].chars().count() + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::errors::CompileError;
+
+    #[test]
+    fn test_json(){
+        let source = "0~1~2^^^^^^666^^^^^^=O";
+        let err = CompileError::ChainedLeachMustEndInMassacre(Span::new(0, 5, 1, 1), Span::new(4, 5, 1, 5));
+        let json = to_json(&err.to_diagnostic(), &SourceMap::from_source("prog.mb", source));
+        assert_eq!(json["code"], "MB0014");
+        assert_eq!(json["severity"], "error");
+        assert_eq!(json["span"], json!({
+            "file": "prog.mb", "start": 0, "end": 5,
+            "line": 1, "column": 1, "end_line": 1, "end_column": 6
+        }));
+        assert_eq!(json["label"], "this chained leach expression");
+        assert_eq!(json["related"][0]["message"], "expected ^^^^^^666^^^^^^=M after this");
+        assert_eq!(json["suggestion"]["replacement"], "^^^^^^666^^^^^^=M");
+        assert_eq!(json["suggestion"]["span"]["start"], 5);
+        assert_eq!(json["suggestion"]["span"]["end"], 5);
+    }
+
+    #[test]
+    fn test_json_lines(){
+        let source = "\\\\|//$!^^^^^^666^^^^^^=O";
+        let diagnostics = vec![
+            CompileError::DrillInCells(Span::new(0, 5, 1, 1)).to_diagnostic(),
+            CompileError::InvalidPrimitiveAccessRegion(Span::new(5, 7, 1, 6)).to_diagnostic()
+        ];
+        let lines = to_json_lines(&diagnostics, &SourceMap::from_source("prog.mb", source));
+        let codes: Vec<Value> = lines.lines()
+            .map(|line| serde_json::from_str::<Value>(line).unwrap()["code"].clone())
+            .collect();
+        assert_eq!(codes, vec![json!("MB0013"), json!("MB0006")]);
+    }
+
+    #[test]
+    fn test_sarif(){
+        let source = "label:x:label:x:^^^^^^666^^^^^^=O";
+        let err = CompileError::DuplicateLabel(Span::new(0, 8, 1, 1), Span::new(8, 16, 1, 9), format!("x"));
+        let sarif = to_sarif(&[err.to_diagnostic()], &SourceMap::from_source("prog.mb", source));
+        assert_eq!(sarif["version"], "2.1.0");
+        let run = &sarif["runs"][0];
+        assert_eq!(run["tool"]["driver"]["rules"][0]["id"], "MB0016");
+        let result = &run["results"][0];
+        assert_eq!(result["ruleId"], "MB0016");
+        assert_eq!(result["ruleIndex"], 0);
+        assert_eq!(result["level"], "error");
+        assert_eq!(result["locations"][0]["physicalLocation"]["region"], json!({
+            "startLine": 1, "startColumn": 9, "endLine": 1, "endColumn": 17,
+            "byteOffset": 8, "byteLength": 8
+        }));
+        assert_eq!(result["relatedLocations"][0]["message"]["text"], "first defined here");
+    }
+
+    #[test]
+    fn test_included_file(){
+        let mut source_map = SourceMap::from_source("prog.mb", "include:lib.mb:^^^^^^666^^^^^^=O");
+        let lib = source_map.add_source("lib.mb", format!("->L\nx"));
+        let err = CompileError::UnrecognizedToken(Span::new(4, 5, 2, 1).in_file(lib));
+        let json = to_json(&err.to_diagnostic(), &source_map);
+        assert_eq!(json["span"]["file"], "lib.mb");
+        assert_eq!(json["span"]["line"], 2);
+        let sarif = to_sarif(&[err.to_diagnostic()], &source_map);
+        let run = &sarif["runs"][0];
+        assert_eq!(run["artifacts"].as_array().unwrap().len(), 2);
+        assert_eq!(run["results"][0]["locations"][0]["physicalLocation"]["artifactLocation"]["uri"], "lib.mb");
+    }
+
+    #[test]
+    fn test_position(){
+        let source = "ab\ncd\u{e9}f";
+        assert_eq!(position(source, 0), (1, 1));
+        assert_eq!(position(source, 3), (2, 1));
+        assert_eq!(position(source, 7), (2, 4));
+        // Inside the é
+        assert_eq!(position(source, 6), (2, 3));
+        assert_eq!(position(source, 20), (2, 5));
+    }
+}
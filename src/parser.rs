@@ -1,11 +1,10 @@
-use std::collections::HashMap;
-use std::{vec, fmt};
-use std::cmp::PartialEq;
-use std::any::Any;
-use std::iter::{Peekable, Enumerate, Iterator};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::mem;
+use std::iter::Iterator;
 use lazy_static::lazy_static;
-use crate::lexer::Token;
-use crate::{errors, r_ident};
+use crate::lexer::{Token, TokenKind, Span};
+use crate::errors::{CompileError, DEFAULT_ERROR_LIMIT};
+use crate::access::{self, MaybeInvalid};
 
 
 macro_rules! p_value {
@@ -17,383 +16,372 @@ macro_rules! p_value {
     };
 }
 
-#[derive(Debug, Clone)]
-struct TokenIterator {
-    tokens: Peekable<Enumerate<vec::IntoIter<Token>>>,
-    offset: usize
+/// The tokens being parsed, pulled from the lexer only as they are needed
+struct TokenIterator<'a> {
+    tokens: Box<dyn Iterator<Item = Result<Token, CompileError>> + 'a>,
+    /// Tokens peeked at or put back, handed out before the rest
+    buffer: VecDeque<Token>,
+    /// The errors the lexer found in the tokens pulled so far
+    errors: Vec<CompileError>,
+    /// The tokens are treated as ended once the lexer has found this many errors
+    error_limit: usize
 }
 
-impl TokenIterator {
-    fn new(tokens: Vec<Token>, offset: usize) -> TokenIterator {
+impl<'a> TokenIterator<'a> {
+    fn new(tokens: Box<dyn Iterator<Item = Result<Token, CompileError>> + 'a>) -> TokenIterator<'a> {
         TokenIterator {
-            tokens: tokens.into_iter().enumerate().peekable(),
-            offset
+            tokens,
+            buffer: VecDeque::new(),
+            errors: vec![],
+            error_limit: DEFAULT_ERROR_LIMIT
         }
     }
-    fn peek(&mut self) -> Option<&Token> {
-        if self.tokens.peek().is_none(){
-            None
+    /// Makes sure there is a token in the buffer, if there are any left
+    fn fill(&mut self) -> bool {
+        while self.buffer.is_empty() && self.errors.len() < self.error_limit {
+            match self.tokens.next() {
+                Some(Ok(token)) => self.buffer.push_back(token),
+                Some(Err(err)) => self.errors.push(err),
+                None => break
+            };
+        }
+        !self.buffer.is_empty()
+    }
+    fn peek(&mut self) -> Option<&TokenKind> {
+        if self.fill() {
+            self.buffer.front().map(|token| &token.kind)
         } else {
-            Some(&(*self.tokens.peek().unwrap()).1)
+            None
+        }
+    }
+    /// Puts the tokens back, to be handed out again in the same order
+    fn put_back(&mut self, tokens: Vec<Token>){
+        for token in tokens.into_iter().rev() {
+            self.buffer.push_front(token);
         }
     }
 }
 
-impl Iterator for TokenIterator {
-    type Item = (usize, Token);
+impl Iterator for TokenIterator<'_> {
+    type Item = Token;
     
     fn next(&mut self) -> Option<Self::Item> {
-        let next = self.tokens.next();
-        if next.is_none(){
-            return next;
+        if self.fill() {
+            self.buffer.pop_front()
+        } else {
+            None
         }
-        let (pos, token) = next.unwrap();
-        Some((pos + self.offset, token))
     }
 }
 
-pub struct Parser {
-    tokens: TokenIterator,
-    curr_region: Region,
-    layers_gates_state: LayersGatesState,
-    encountered_jumps: Vec<(usize, String)>,
-    encountered_labels: Vec<String>
+pub struct Parser<'a> {
+    tokens: TokenIterator<'a>,
+    encountered_jumps: Vec<(Span, String)>,
+    encountered_labels: Vec<(Span, String)>,
+    errors: Vec<CompileError>,
+    error_limit: usize,
+    /// The accesses that are only invalid on some paths
+    maybe_invalid: Vec<MaybeInvalid>
 }
 
-impl Parser {
+impl<'a> Parser<'a> {
     pub fn new(tokens: Vec<Token>) -> Self {
+        Parser::from_stream(tokens.into_iter().map(Ok))
+    }
+    /// A parser that pulls the tokens from the stream as it needs them, so they don't
+    /// all have to be in memory at once. The errors in the stream are reported by parse
+    pub fn from_stream<I>(tokens: I) -> Self
+    where
+        I: Iterator<Item = Result<Token, CompileError>> + 'a
+    {
         Parser {
-            tokens: TokenIterator::new(tokens, 1),
-            curr_region: Region::Cells,
-            layers_gates_state: LayersGatesState::AllClose,
+            tokens: TokenIterator::new(Box::new(tokens)),
             encountered_jumps: vec![],
-            encountered_labels: vec![]
+            encountered_labels: vec![],
+            errors: vec![],
+            error_limit: DEFAULT_ERROR_LIMIT,
+            maybe_invalid: vec![]
         }
     }
-    pub fn parse(&mut self) -> Result<(OrganismExpression, Vec<String>), String> {
-        let parse_result = self.parse_expressions();
-        for (pos, label) in self.encountered_labels.iter().enumerate() {
-            for (pos1, label1) in self.encountered_labels.iter().enumerate() {
-                if pos != pos1 && label == label1 {
-                    return Err(errors::err_duplicate_label(pos, pos1))
+    /// The number of errors to report before giving up on the rest of the tokens
+    pub fn set_error_limit(&mut self, error_limit: usize){
+        self.error_limit = error_limit;
+        self.tokens.error_limit = error_limit;
+    }
+    /// Whether the program is nothing but the death of the organism expression
+    pub fn is_empty(&mut self) -> bool {
+        self.tokens.peek().is_none() && self.tokens.errors.is_empty()
+    }
+    pub fn parse(&mut self) -> Result<(OrganismExpression, Vec<String>), Vec<CompileError>> {
+        let org_expr = self.parse_expressions();
+        // The parser's errors can't be trusted when tokens are missing,
+        // so only the lexer's are reported, all of them
+        if !self.tokens.errors.is_empty() {
+            while self.tokens.next().is_some() {}
+            return Err(mem::replace(&mut self.tokens.errors, vec![]));
+        }
+        // Jumps can't be checked if the parser gave up before seeing all the labels
+        if self.tokens.peek().is_some() {
+            return Err(self.take_errors());
+        }
+        // Only the first duplicate of each label is reported, each later one is a
+        // duplicate of the ones before it too
+        let mut first_spans: HashMap<&str, Span> = HashMap::new();
+        let mut duplicated: HashSet<&str> = HashSet::new();
+        for (span, label) in self.encountered_labels.iter() {
+            match first_spans.get(label.as_str()) {
+                Some(first_span) => if duplicated.insert(label) {
+                    self.errors.push(CompileError::DuplicateLabel(*first_span, *span, label.clone()));
+                },
+                None => {
+                    first_spans.insert(label, *span);
                 }
+            };
+        }
+        for (span, label) in self.encountered_jumps.iter() {
+            if !first_spans.contains_key(label.as_str()){
+                self.errors.push(CompileError::JumpToNonExistentLabel(*span, label.clone()));
             }
         }
-        for (pos, label) in self.encountered_jumps.iter() {
-            if !self.encountered_labels.contains(label){
-                return Err(errors::err_attempt_to_jump_to_non_existent_label(*pos))
+        // Where the program can be at each expression depends on the jumps, so the
+        // accesses are checked once all of them are known
+        let (errors, maybe_invalid) = access::check(&org_expr);
+        self.errors.extend(errors);
+        self.maybe_invalid = maybe_invalid;
+        let label_names: Vec<String> = self.encountered_labels.iter()
+            .map(|(_, label)| label.clone())
+            .collect();
+        if self.errors.is_empty() {
+            Ok((org_expr, label_names))
+        } else {
+            Err(self.take_errors())
+        }
+    }
+    /// The accesses the last parse found to be invalid on some of the paths to them,
+    /// so the lints don't have to check them again
+    pub fn take_maybe_invalid(&mut self) -> Vec<MaybeInvalid> {
+        mem::replace(&mut self.maybe_invalid, vec![])
+    }
+    fn take_errors(&mut self) -> Vec<CompileError> {
+        let mut errors = mem::replace(&mut self.errors, vec![]);
+        // The label errors are only found after everything has been parsed
+        errors.sort_by_key(|err| err.span().map(|span| span.start));
+        errors.truncate(self.error_limit);
+        errors
+    }
+    /// Parses the rest of the tokens. When an expression can't be parsed, the error is
+    /// recorded and the parser skips to the next region, drill, label or death token
+    /// to carry on from there, so the organism expression returned is only of use if no
+    /// errors were recorded
+    fn parse_expressions(&mut self) -> OrganismExpression {
+        let mut exprs: Vec<Expr> = vec![];
+        while let Some(token) = self.tokens.next() {
+            match self.parse_expression(token) {
+                Ok(Some(expr)) => exprs.push(expr),
+                Ok(None) => (),
+                Err(err) => {
+                    self.errors.push(err);
+                    self.synchronize();
+                }
+            };
+            let can_go_on = self.errors.len() < self.error_limit && self.tokens.errors.is_empty();
+            if !can_go_on {
+                break;
             }
         }
-        match parse_result {
-            Ok(oe) => Ok((oe, self.encountered_labels.clone())),
-            Err(err) => Err(err)
+        OrganismExpression::new(exprs)
+    }
+    /// Skips tokens until one that can safely start a new expression
+    fn synchronize(&mut self){
+        while let Some(kind) = self.tokens.peek() {
+            match kind {
+                TokenKind::RegionIdent(_) | TokenKind::Drill |
+                TokenKind::Label(_) | TokenKind::TripleSixEqO => break,
+                _ => { self.tokens.next(); }
+            };
         }
     }
-    fn parse_expressions(&mut self) -> Result<OrganismExpression, String> {
-        let child: Box<dyn Expression>;
-        let mut next_org_expr: Option<Box<OrganismExpression>> = None;
-        match self.tokens.next().unwrap() {
-            (pos, Token::PrimitiveIdent(p_ident)) => {
-                match self.validate_primitive_access(pos){
-                    Ok(()) => (),
-                    Err(err) => return Err(err)
-                };
+    /// Parses the expression starting with the token.
+    /// A death of the organism expression isn't an expression of its own, so there is
+    /// nothing to return for it. The ones before the last are ignored
+    fn parse_expression(&mut self, token: Token) -> Result<Option<Expr>, CompileError> {
+        let child: Expr;
+        let span = token.span;
+        match token.kind {
+            TokenKind::PrimitiveIdent(p_ident) => {
                 let curr_primitive = p_ident.clone();
                 
-                let mut region_changes: Vec<(usize, Token)> = vec![];
+                let mut region_changes: Vec<Token> = vec![];
                 while self.tokens.peek().is_some() && (*self.tokens.peek().unwrap()).is_region(){
-                    let (pos, token) = self.tokens.next().unwrap();
+                    let token = self.tokens.next().unwrap();
                     let r_ident: String;
-                    match token {
-                        Token::RegionIdent(r) => r_ident = r,
+                    match &token.kind {
+                        TokenKind::RegionIdent(r) => r_ident = r.clone(),
                         _ => unreachable!()
                     };
-                    match self.change_region(pos, r_ident.clone()){
-                        Ok(()) => (),
-                        Err(err) => return Err(err)
-                    };
-                    region_changes.push((pos, r_ident!(r_ident)));
+                    if let Err(err) = self.parse_region(token.span, r_ident){
+                        return Err(err);
+                    }
+                    region_changes.push(token);
                 }
                 // If this condition is true, all the region changes that occur after
                 // the occurence of the primitive should end up being part of the leach
                 // expression
-                if self.tokens.peek().is_some() && *self.tokens.peek().unwrap() == Token::Tilde {
-                    let left = Box::new(PrimitiveExpression::new(curr_primitive));
-                    match self.parse_primitive_leach_expression(pos, left, Some(region_changes)){
+                if self.tokens.peek().is_some() && *self.tokens.peek().unwrap() == TokenKind::Tilde {
+                    let left = PrimitiveExpression::new(curr_primitive, span);
+                    match self.parse_primitive_leach_expression(left, Some(region_changes)){
                         Ok(leach_expr) => {
-                            child = leach_expr;
+                            child = Expr::Leach(leach_expr);
                         },
                         Err(err) => return Err(err)
                     };
                 } else {
                     // put back the consumed region tokens
-                    if region_changes.len() != 0 {
-                        let new_tokens = region_changes.into_iter().chain(self.tokens.clone());
-                        let new_tokens: Vec<Token> = new_tokens
-                            .map(|(_, token)| token)
-                            .collect();
-                        self.tokens = TokenIterator::new(new_tokens, pos);
-                    }
-                    child = Box::new(PrimitiveExpression::new(p_ident));
+                    self.tokens.put_back(region_changes);
+                    child = Expr::Primitive(PrimitiveExpression::new(p_ident, span));
                 }
             },
             // The Cell Identifier being encountered here means that it must
             // either be the left operand in a leach expression or it is an
             // expression of no effect
-            (pos, Token::CellIdent(c_ident)) => {
-                match self.validate_cell_access(pos){
-                    Ok(()) => (),
-                    Err(err) => return Err(err)
-                };
-                if *self.tokens.peek().unwrap() == Token::Tilde {
-                    let left = Box::new(CellExpression::new(c_ident.clone()));
-                    let (pos, _) = self.tokens.next().unwrap();
-                    match self.parse_leach_expression(pos, left, None, false, vec![c_ident]){
-                        Ok(leach_expr) => child = leach_expr,
+            TokenKind::CellIdent(c_ident) => {
+                if self.tokens.peek() == Some(&TokenKind::Tilde) {
+                    let left = CellExpression::new(c_ident, span);
+                    let tilde = self.tokens.next().unwrap();
+                    match self.parse_leach_expression(tilde.span, left){
+                        Ok(leach_expr) => child = Expr::Leach(leach_expr),
                         Err(err) => return Err(err)
                     };
                 } else {
-                    child = Box::new(CellExpression::new(c_ident));
+                    child = Expr::Cell(CellExpression::new(c_ident, span));
                 }
             },
             // A standalone region change
-            (pos, Token::RegionIdent(r_ident)) => {
-                match self.change_region(pos, r_ident.clone()){
-                    Ok(()) => (),
-                    Err(err) => return Err(err)
-                };
-                match self.parse_region(pos, r_ident){
-                    Ok(r_expr) => child = Box::new(r_expr),
-                    Err(err) => return Err(err)
-                };
-            },
-            (pos, Token::Drill) => {
-                match self.validate_drill(pos){
-                    Ok(()) => self.drill(),
+            TokenKind::RegionIdent(r_ident) => {
+                match self.parse_region(span, r_ident){
+                    Ok(r_expr) => child = Expr::Region(r_expr),
                     Err(err) => return Err(err)
                 };
-                child = Box::new(DrillExpression::new());
             },
-            (_, Token::Label(label)) => {
-                self.encountered_labels.push(label.clone());
-                child = Box::new(LabelExpression::new(label));
+            TokenKind::Drill => child = Expr::Drill(span),
+            TokenKind::Label(label) => {
+                self.encountered_labels.push((span, label.clone()));
+                child = Expr::Label(LabelExpression::new(label, span));
             }
-            (pos, Token::Jump(label)) => {
-                self.encountered_jumps.push((pos, label.clone()));
-                child = Box::new(JumpExpression::new(label));
+            TokenKind::Jump(label) => {
+                self.encountered_jumps.push((span, label.clone()));
+                child = Expr::Jump { to: label, conditional: false, span };
             }
-            (pos, Token::ConditionalJump(label)) => {
-                self.encountered_jumps.push((pos, label.clone()));
-                child = Box::new(JumpExpression::new_conditional(label))
+            TokenKind::ConditionalJump(label) => {
+                self.encountered_jumps.push((span, label.clone()));
+                child = Expr::Jump { to: label, conditional: true, span };
             }
-            (pos, Token::Tilde) => {
-                return Err(errors::err_leach_expression_must_start_with_primitive_or_cell(pos))
+            TokenKind::Tilde => {
+                return Err(CompileError::LeachMustStartWithPrimitiveOrCell(span))
             }
-            (pos, Token::TripleSixEqM) => {
-                return Err(errors::err_chain_leach_expression_ending_without_chain_leach_expression(pos))
+            TokenKind::TripleSixEqM => {
+                return Err(CompileError::ChainLeachEndWithoutChainLeach(span))
             }
-            (pos, Token::TripleSixEq) => {
-                return Err(errors::err_triple_six_eq_not_expected_here(pos))
+            TokenKind::TripleSixEq => {
+                return Err(CompileError::TripleSixEqNotExpected(span))
             }
-            (pos, Token::TripleSix) => {
-                return Err(errors::err_triple_six_not_expected_here(pos))
+            TokenKind::TripleSix => {
+                return Err(CompileError::TripleSixNotExpected(span))
             }
-            (_, Token::TripleSixEqO) => {
-                let mut new_next_org_expr;
-                if self.tokens.peek().is_some(){
-                    match self.parse_expressions(){
-                        Ok(org_expr) => new_next_org_expr = org_expr,
-                        Err(err) => return Err(err)
-                    };
-                    use std::mem;
-                    let dummy_expr = Box::new(DummyExpression::new());
-                    let dummy_expr1 = Box::new(DummyExpression::new());
-                    let dummy_org_expr = Some(Box::new(OrganismExpression::new(dummy_expr1, None)));
-                    child = mem::replace(&mut new_next_org_expr.child, dummy_expr);
-                    next_org_expr = mem::replace(&mut new_next_org_expr.right, dummy_org_expr);
-                } else {
-                    child = Box::new(DummyExpression::new());
-                }
-            }
-        }
-        if next_org_expr.is_some(){
-            return Ok(OrganismExpression::new(child, next_org_expr))
-        }
-        if self.tokens.peek().is_some(){
-            match self.parse_expressions(){
-                Ok(org_expr) => Ok(OrganismExpression::new(
-                    child,
-                    Some(Box::new(org_expr))
-                )),
-                Err(err) => Err(err)
-            }
-        } else {
-            Ok(OrganismExpression::new(child, None))
+            TokenKind::Include(_) => unreachable!("the lexer reads included files in place of includes"),
+            TokenKind::Macro(..) | TokenKind::EndMacro | TokenKind::Call(_) | TokenKind::Placeholder(_) =>
+                unreachable!("macros are expanded before parsing"),
+            TokenKind::TripleSixEqO => return Ok(None)
         }
-    }
-    fn validate_primitive_access(&self, pos: usize) -> Result<(), String> {
-        if self.curr_region != Region::Layers {
-            return Err(errors::err_invalid_primitive_access_region(pos));
-        }
-        if self.layers_gates_state != LayersGatesState::ThreeOpen {
-            return Err(errors::err_invalid_primitive_access_gates(pos));
-        }
-        Ok(())
-    }
-    fn validate_cell_access(&self, pos: usize) -> Result<(), String> {
-        if self.curr_region != Region::Cells {
-            return Err(errors::err_invalid_cell_access_region(pos));
-        }
-        Ok(())
-    }
-    fn validate_drill(&self, pos: usize) -> Result<(), String> {
-        match self.curr_region {
-            Region::Layers => Ok(()),
-            Region::Cells => Err(errors::err_drill_in_cells(pos))
-        }
-    }
-    fn drill(&mut self){
-        match self.layers_gates_state {
-            LayersGatesState::AllClose => self.layers_gates_state = LayersGatesState::OneOpen,
-            LayersGatesState::OneOpen => self.layers_gates_state = LayersGatesState::TwoOpen,
-            LayersGatesState::TwoOpen => self.layers_gates_state = LayersGatesState::ThreeOpen,
-            LayersGatesState::ThreeOpen => ()
-        };
+        Ok(Some(child))
     }
     fn parse_primitive_leach_expression(
         &mut self,
-        pos: usize,
-        left: Box<dyn PassiveExpression>,
-        region_changes: Option<Vec<(usize, Token)>>
-    ) -> Result<Box<LeachExpression>, String>{
+        left: PrimitiveExpression,
+        region_changes: Option<Vec<Token>>
+    ) -> Result<LeachExpression, CompileError>{
         // Get rid of the Tilde
-        self.tokens.next();
+        let tilde = self.tokens.next().unwrap();
         if self.tokens.peek().is_none(){
-            return Err(errors::err_expected_cell_expression_after(pos));
+            return Err(CompileError::ExpectedCellExpressionAfter(tilde.span));
         }
-        match self.tokens.next().unwrap() {
-            (_, Token::CellIdent(c_ident)) => {
+        let token = self.tokens.next().unwrap();
+        match token.kind {
+            TokenKind::CellIdent(c_ident) => {
                 if !self.cell_ident_is_valid(c_ident.clone()){
-                    return Err(errors::err_unrecognized_cell(pos, c_ident))
+                    return Err(CompileError::UnrecognizedCell(token.span, c_ident))
                 }
                 let region_changes = self.parse_region_changes(region_changes);
-                let right = Some(Box::new(LeachExpression::new(
-                    Box::new(CellExpression::new(c_ident)),
-                    None, false, None
-                )));
-                Ok(Box::new(LeachExpression::new(
-                    left, right, false, region_changes
-                )))
+                let span = left.span.to(token.span);
+                let onto = vec![CellExpression::new(c_ident, token.span)];
+                Ok(LeachExpression::new(
+                    PassiveExpression::Primitive(left), onto, false, region_changes, span
+                ))
             },
-            (pos, _) => Err(errors::err_expected_cell_expression(pos))
+            _ => Err(CompileError::ExpectedCellExpression(token.span))
         }
     }
+    /// Parses the cells the left cell is leached onto. The tilde after it has already
+    /// been taken care of by the caller
     fn parse_leach_expression(
         &mut self,
-        pos: usize,
-        left: Box<dyn PassiveExpression>,
-        region_changes: Option<Vec<(usize, Token)>>,
-        predecessor_is_chain: bool,
-        cells_encountered: Vec<String>
-    ) -> Result<Box<LeachExpression>, String> {
-        if self.tokens.peek().is_none(){
-            return Err(errors::err_expected_cell_expression_after(pos));
-        }
-        // The Tilde has already been taken care of by the caller, so no need to bother about it
-        let token = self.tokens.next();
-        let c_ident: String;
-        let pos: usize;
-        match token.unwrap(){
-            (p, Token::CellIdent(ident)) => {
-                c_ident = ident;
-                pos = p;
-            },
-            (pos, _) => return Err(errors::err_expected_cell_expression(pos))
-        };
-        if !self.cell_ident_is_valid(c_ident.clone()){
-            return Err(errors::err_unrecognized_cell(pos, c_ident));
-        }
-        if cells_encountered.contains(&c_ident){
-            return Err(errors::err_attempt_to_leach_expr_onto_itself(pos));
-        }
-        let right: Option<Box<LeachExpression>>;
-        let mut is_chain = false;
-        let curr_cell_expr = Box::new(CellExpression::new(c_ident));
-        let region_changes: Option<Vec<RegionExpression>> = self.parse_region_changes(region_changes);
-        if self.tokens.peek().is_some(){
-            let next_token_ref = self.tokens.peek().unwrap();
-            match next_token_ref {
+        tilde_span: Span,
+        left: CellExpression
+    ) -> Result<LeachExpression, CompileError> {
+        let chain_start = left.span;
+        let mut tilde_span = tilde_span;
+        let mut onto: Vec<CellExpression> = vec![];
+        loop {
+            let token = match self.tokens.next() {
+                Some(token) => token,
+                None => return Err(CompileError::ExpectedCellExpressionAfter(tilde_span))
+            };
+            let cell_span = token.span;
+            let c_ident = match token.kind {
+                TokenKind::CellIdent(ident) => ident,
+                _ => return Err(CompileError::ExpectedCellExpression(cell_span))
+            };
+            if !self.cell_ident_is_valid(c_ident.clone()){
+                return Err(CompileError::UnrecognizedCell(cell_span, c_ident));
+            }
+            let cell_expr = CellExpression::new(c_ident, cell_span);
+            if cell_expr.ident == left.ident {
+                return Err(CompileError::LeachExprOntoItself(cell_span, left.span));
+            }
+            onto.push(cell_expr);
+            match self.tokens.peek() {
                 // If a tilde comes next, must be a chained leach expression
-                Token::Tilde => {
-                    // consume the tilde so it won't reach the next recursion
-                    let (pos, _) = self.tokens.next().unwrap();
-                    is_chain = true;
-                    match self.parse_leach_expression(pos, curr_cell_expr, None, true, cells_encountered){
-                        Ok(leach_expr) => right = Some(leach_expr),
-                        Err(err) => return Err(err)
-                    };
-                },
+                Some(TokenKind::Tilde) => tilde_span = self.tokens.next().unwrap().span,
                 // ^^^^^^666^^^^^^=M comes next, must be the end of a chained leach
-                // expression
-                Token::TripleSixEqM => {
-                    // Get rid of the token
-                    // It's not needed for code generation
-                    self.tokens.next();
-                    // Consider the expression: 0~1^^^^^^666^^^^^^=M
-                    // The above is a chain leach expression without predecessor
-                    // So it will be called with predecessor_is_chain = false
-                    // So the expression will be labelled as chain, so it will be coded
-                    // as a function call
-                    // But consider the expression: 0~1~2^^^^^^666^^^^^^=M
-                    // The last expression in the chain is 1~2 and should not be tagged as chain
-                    // That is taken care of here because when the ~ is encountered in 0~1,
-                    // predecessor_is_chain will be true, so when the last 1~2 is reached,
-                    // it won't be tagged as chain
-                    is_chain = !predecessor_is_chain;
-                    right = Some(Box::new(LeachExpression::new(curr_cell_expr, None, false, None)));
-                    return Ok(Box::new(
-                        LeachExpression::new(left, right, is_chain, region_changes)
-                    ));
-                },
-                // anything else comes next, must be the start of another unrelated
-                // expression, so right must be this current cell expression
+                // expression. Even 0~1^^^^^^666^^^^^^=M is a chain, so it's coded as
+                // a function call. The massacre itself isn't needed for code generation
+                Some(TokenKind::TripleSixEqM) => {
+                    let massacre = self.tokens.next().unwrap();
+                    let span = chain_start.to(massacre.span);
+                    return Ok(LeachExpression::new(PassiveExpression::Cell(left), onto, true, None, span));
+                }
+                // Anything else comes next, must be the start of another unrelated
+                // expression, so the leach expression ends at this cell
+                _ if onto.len() > 1 => return Err(CompileError::ChainedLeachMustEndInMassacre(
+                    chain_start.to(cell_span), cell_span
+                )),
                 _ => {
-                    right = Some(Box::new(LeachExpression::new(curr_cell_expr, None, false, None)))
+                    let span = chain_start.to(cell_span);
+                    return Ok(LeachExpression::new(PassiveExpression::Cell(left), onto, false, None, span));
                 }
             };
-        } else {
-            right = Some(Box::new(LeachExpression::new(
-                curr_cell_expr, None, false, None
-            )));
-        }
-        if predecessor_is_chain {
-            return Err(errors::err_chained_leach_expression_must_end_in_massacre(pos))
         }
-        Ok(Box::new(
-            LeachExpression::new(left, right, is_chain, region_changes)
-        ))
     }
     fn parse_region_changes(
         &mut self,
-        region_changes: Option<Vec<(usize, Token)>>
+        region_changes: Option<Vec<Token>>
     ) -> Option<Vec<RegionExpression>> {
         if region_changes.is_some(){
             Some(region_changes.unwrap()
                 .into_iter()
-                .map(|(pos, token)| {
-                    match token {
-                        Token::RegionIdent(r_ident) => {
-                            match self.parse_region(pos, r_ident.clone()){
-                                Ok(region_expr) => {
-                                    match self.change_region(pos, r_ident){
-                                        Ok(()) => (),
-                                        Err(err) => return Err(err)
-                                    };
-                                    Ok(region_expr)
-                                },
-                                Err(err) => return Err(err)
-                            }
-                        },
+                .map(|token| {
+                    match token.kind {
+                        TokenKind::RegionIdent(r_ident) => self.parse_region(token.span, r_ident),
                         _ => unreachable!()
                     }
                 })
@@ -412,19 +400,11 @@ impl Parser {
             _ => false
         }
     }
-    fn change_region(&mut self, pos: usize, new_region: String) -> Result<(), String> {
-        match new_region.as_str() {
-            "C" => self.curr_region = Region::Cells,
-            "L" => self.curr_region = Region::Layers,
-            _ => return Err(errors::err_unrecognized_region(pos, new_region))
-        };
-        Ok(())
-    }
-    fn parse_region(&mut self, pos: usize, r_ident: String) -> Result<RegionExpression, String> {
+    fn parse_region(&mut self, span: Span, r_ident: String) -> Result<RegionExpression, CompileError> {
         match r_ident.as_str(){
-            "C" => Ok(RegionExpression::new(Region::Cells)),
-            "L" => Ok(RegionExpression::new(Region::Layers)),
-            _ => Err(errors::err_unrecognized_region(pos, r_ident))
+            "C" => Ok(RegionExpression::new(Region::Cells, span)),
+            "L" => Ok(RegionExpression::new(Region::Layers, span)),
+            _ => Err(CompileError::UnrecognizedRegion(span, r_ident))
         }
     }
 }
@@ -435,14 +415,6 @@ pub enum Region {
     Layers
 }
 
-#[derive(Debug, PartialEq, Eq)]
-enum LayersGatesState {
-    AllClose,
-    OneOpen,
-    TwoOpen,
-    ThreeOpen,
-}
-
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum PrimitiveValue {
     One, Two, Three, Four, Five, Six,
@@ -450,42 +422,66 @@ pub enum PrimitiveValue {
     Addition, Subtraction
 }
 
-pub trait Expression: fmt::Debug + Any {
-    fn get_type(&self) -> ExprType;
-    fn get_repr(&self) -> String;
-    fn as_any(&self) -> &dyn Any;
-    fn as_mut_any(&mut self) -> &mut dyn Any;
+/// An expression of the organism expression
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Leach(LeachExpression),
+    Region(RegionExpression),
+    Drill(Span),
+    Label(LabelExpression),
+    Jump {
+        to: String,
+        conditional: bool,
+        span: Span
+    },
+    /// A cell on its own, with no effect
+    Cell(CellExpression),
+    /// A primitive on its own. It still has to be accessible
+    Primitive(PrimitiveExpression)
 }
 
-pub trait PassiveExpression: Expression {}
-
-trait ActiveExpression: Expression {}
+impl Expr {
+    pub fn span(&self) -> Span {
+        match self {
+            Expr::Leach(leach_expr) => leach_expr.span,
+            Expr::Region(region_expr) => region_expr.span,
+            Expr::Drill(span) => *span,
+            Expr::Label(label_expr) => label_expr.span,
+            Expr::Jump { span, .. } => *span,
+            Expr::Cell(cell_expr) => cell_expr.span,
+            Expr::Primitive(primitive_expr) => primitive_expr.span
+        }
+    }
+}
 
+/// What a leach expression starts with, a cell or a primitive
 #[derive(Debug, Clone, PartialEq)]
-pub struct CellExpression {
-    pub ident: u8
+pub enum PassiveExpression {
+    Cell(CellExpression),
+    Primitive(PrimitiveExpression)
 }
 
-impl Expression for CellExpression {
-    fn get_type(&self) -> ExprType {
-        ExprType::Cell
-    }
-    fn get_repr(&self) -> String {
-        format!("{}", self.ident)
-    }
-    fn as_any(&self) -> &dyn Any {
-        self
-    }
-    fn as_mut_any(&mut self) -> &mut dyn Any {
-        self
+impl PassiveExpression {
+    pub fn span(&self) -> Span {
+        match self {
+            PassiveExpression::Cell(cell_expr) => cell_expr.span,
+            PassiveExpression::Primitive(primitive_expr) => primitive_expr.span
+        }
     }
 }
 
+#[derive(Debug, Clone, PartialEq)]
+pub struct CellExpression {
+    pub ident: u8,
+    pub span: Span
+}
+
 impl CellExpression {
-    pub fn new(c_ident: String) -> CellExpression {
+    pub fn new(c_ident: String, span: Span) -> CellExpression {
         let c_ident = c_ident.chars().next().unwrap();
         CellExpression {
-            ident: c_ident.to_digit(16).unwrap() as u8
+            ident: c_ident.to_digit(16).unwrap() as u8,
+            span
         }
     }
     pub fn ident(&self) -> u8 {
@@ -493,34 +489,17 @@ impl CellExpression {
     }
 }
 
-impl PassiveExpression for CellExpression {}
-
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct PrimitiveExpression {
-    pval: PrimitiveValue
-}
-
-impl Expression for PrimitiveExpression {
-    fn get_type(&self) -> ExprType {
-        ExprType::Primitive
-    }
-    fn get_repr(&self) -> String {
-        format!("{:?}", self.pval)
-    }
-    fn as_any(&self) -> &dyn Any {
-        self
-    }
-    fn as_mut_any(&mut self) -> &mut dyn Any {
-        self
-    }
+    pub pval: PrimitiveValue,
+    pub span: Span
 }
 
-impl PassiveExpression for PrimitiveExpression {}
-
 impl PrimitiveExpression {
-    fn new(p_ident: String) -> PrimitiveExpression {
+    fn new(p_ident: String, span: Span) -> PrimitiveExpression {
         PrimitiveExpression {
-            pval: p_value!(p_ident)
+            pval: p_value!(p_ident),
+            span
         }
     }
     pub fn pval(&self) -> PrimitiveValue {
@@ -528,78 +507,55 @@ impl PrimitiveExpression {
     }
 }
 
-#[derive(Debug)]
+/// Leaches the left expression onto the first cell. A chain, one leached onto more
+/// than one cell or ended in a massacre, leaches each cell onto the next and is
+/// carried out as a function call
+#[derive(Debug, Clone, PartialEq)]
 pub struct LeachExpression {
-    pub left: Box<dyn PassiveExpression>,
-    pub right: Option<Box<LeachExpression>>,
+    pub left: PassiveExpression,
+    /// The cells leached onto, in order
+    pub onto: Vec<CellExpression>,
     pub is_chain: bool,
-    pub region_change: Option<Vec<RegionExpression>>
-}
-
-impl Expression for LeachExpression {
-    fn get_type(&self) -> ExprType {
-        ExprType::Leach
-    }
-    fn get_repr(&self) -> String {
-        format!(
-            "(left: {:?}, right: {:?}, is_chain: {:?})",
-            self.left.get_repr(), self.right, self.is_chain
-        )
-    }
-    fn as_any(&self) -> &dyn Any {
-        self
-    }
-    fn as_mut_any(&mut self) -> &mut dyn Any {
-        self
-    }
+    /// The region changes between a primitive and the tilde after it
+    pub region_change: Option<Vec<RegionExpression>>,
+    pub span: Span
 }
 
 impl LeachExpression {
     pub fn new(
-        left: Box<dyn PassiveExpression>,
-        right: Option<Box<LeachExpression>>,
+        left: PassiveExpression,
+        onto: Vec<CellExpression>,
         is_chain: bool,
-        region_change: Option<Vec<RegionExpression>>
+        region_change: Option<Vec<RegionExpression>>,
+        span: Span
     ) -> LeachExpression {
         LeachExpression {
             left,
-            right,
+            onto,
             is_chain,
-            region_change
+            region_change,
+            span
         }
     }
-    pub fn left(&self) -> &Box<dyn PassiveExpression> {
+    pub fn left(&self) -> &PassiveExpression {
         &self.left
     }
-    pub fn right(&self) -> &Option<Box<LeachExpression>> {
-        &self.right
+    pub fn onto(&self) -> &[CellExpression] {
+        &self.onto
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct RegionExpression {
-    to: Region
-}
-
-impl Expression for RegionExpression {
-    fn get_type(&self) -> ExprType {
-        ExprType::Region
-    }
-    fn get_repr(&self) -> String {
-        format!("(to: {:?})", self.to)
-    }
-    fn as_any(&self) -> &dyn Any {
-        self
-    }
-    fn as_mut_any(&mut self) -> &mut dyn Any {
-        self
-    }
+    pub to: Region,
+    pub span: Span
 }
 
 impl RegionExpression {
-    fn new(to: Region) -> RegionExpression {
+    pub fn new(to: Region, span: Span) -> RegionExpression {
         RegionExpression {
-            to
+            to,
+            span
         }
     }
     pub fn to(&self) -> Region {
@@ -607,170 +563,183 @@ impl RegionExpression {
     }
 }
 
-#[derive(Debug)]
-struct DrillExpression {}
+#[derive(Debug, Clone, PartialEq)]
+pub struct LabelExpression {
+    pub label: String,
+    pub span: Span
+}
 
-impl Expression for DrillExpression {
-    fn get_type(&self) -> ExprType {
-        ExprType::Drill
-    }
-    fn get_repr(&self) -> String {
-        format!("{:?}", Token::Drill)
-    }
-    fn as_any(&self) -> &dyn Any {
-        self
+impl LabelExpression {
+    pub fn new(label: String, span: Span) -> LabelExpression {
+        LabelExpression {
+            label,
+            span
+        }
     }
-    fn as_mut_any(&mut self) -> &mut dyn Any {
-        self
+    pub fn label(&self) -> String {
+        self.label.clone()
     }
 }
 
-impl DrillExpression {
-    fn new() -> DrillExpression {
-        DrillExpression {}
+/// The program, which is itself an expression. Its expressions are carried out in
+/// order, up to the death of the organism expression at the end
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct OrganismExpression {
+    pub exprs: Vec<Expr>
+}
+
+impl OrganismExpression {
+    pub fn new(exprs: Vec<Expr>) -> OrganismExpression {
+        OrganismExpression {
+            exprs
+        }
     }
 }
 
-#[derive(Debug)]
-pub struct LabelExpression {
-    label: String
+/// Walks the organism expression without changing it. Each method is called on an
+/// expression of its kind and by default walks the expressions in it, so a visitor
+/// only has to implement the methods of the kinds it's looking for. Region changes
+/// are visited with visit_region, whether they're on their own or in a leach
+/// expression, and cells and primitives likewise
+pub trait Visitor {
+    fn visit_org_expr(&mut self, org_expr: &OrganismExpression) {
+        walk_org_expr(self, org_expr);
+    }
+    fn visit_expr(&mut self, expr: &Expr) {
+        walk_expr(self, expr);
+    }
+    fn visit_leach(&mut self, leach_expr: &LeachExpression) {
+        walk_leach(self, leach_expr);
+    }
+    fn visit_passive(&mut self, passive_expr: &PassiveExpression) {
+        walk_passive(self, passive_expr);
+    }
+    fn visit_region(&mut self, _region_expr: &RegionExpression) {}
+    fn visit_drill(&mut self, _span: Span) {}
+    fn visit_label(&mut self, _label_expr: &LabelExpression) {}
+    fn visit_jump(&mut self, _to: &str, _conditional: bool, _span: Span) {}
+    fn visit_cell(&mut self, _cell_expr: &CellExpression) {}
+    fn visit_primitive(&mut self, _primitive_expr: &PrimitiveExpression) {}
 }
 
-impl Expression for LabelExpression {
-    fn get_type(&self) -> ExprType {
-        ExprType::Label
-    }
-    fn get_repr(&self) -> String {
-        format!("(label: {:?})", self.label)
-    }
-    fn as_any(&self) -> &dyn Any {
-        self
-    }
-    fn as_mut_any(&mut self) -> &mut dyn Any {
-        self
+pub fn walk_org_expr<V: Visitor + ?Sized>(visitor: &mut V, org_expr: &OrganismExpression) {
+    for expr in org_expr.exprs.iter() {
+        visitor.visit_expr(expr);
     }
 }
 
-impl LabelExpression {
-    pub fn new(label: String) -> LabelExpression {
-        LabelExpression {
-            label
-        }
+pub fn walk_expr<V: Visitor + ?Sized>(visitor: &mut V, expr: &Expr) {
+    match expr {
+        Expr::Leach(leach_expr) => visitor.visit_leach(leach_expr),
+        Expr::Region(region_expr) => visitor.visit_region(region_expr),
+        Expr::Drill(span) => visitor.visit_drill(*span),
+        Expr::Label(label_expr) => visitor.visit_label(label_expr),
+        Expr::Jump { to, conditional, span } => visitor.visit_jump(to, *conditional, *span),
+        Expr::Cell(cell_expr) => visitor.visit_cell(cell_expr),
+        Expr::Primitive(primitive_expr) => visitor.visit_primitive(primitive_expr)
+    };
+}
+
+/// Visits the first operand, then its region changes, then each cell it's leached
+/// onto, in the order they were written
+pub fn walk_leach<V: Visitor + ?Sized>(visitor: &mut V, leach_expr: &LeachExpression) {
+    visitor.visit_passive(&leach_expr.left);
+    for region_expr in leach_expr.region_change.iter().flatten() {
+        visitor.visit_region(region_expr);
     }
-    pub fn label(&self) -> String {
-        self.label.clone()
+    for cell_expr in leach_expr.onto.iter() {
+        visitor.visit_cell(cell_expr);
     }
 }
 
-#[derive(Debug)]
-pub struct JumpExpression {
-    to: String,
-    conditional: bool
+pub fn walk_passive<V: Visitor + ?Sized>(visitor: &mut V, passive_expr: &PassiveExpression) {
+    match passive_expr {
+        PassiveExpression::Cell(cell_expr) => visitor.visit_cell(cell_expr),
+        PassiveExpression::Primitive(primitive_expr) => visitor.visit_primitive(primitive_expr)
+    };
 }
 
-impl Expression for JumpExpression {
-    fn get_type(&self) -> ExprType {
-        ExprType::Jump
+/// Rebuilds the organism expression, taking it apart and putting it back together.
+/// Like the visitor, each method is called on an expression of its kind and by
+/// default folds the expressions in it, so a fold only has to implement the methods
+/// of the kinds it changes. Every span goes through fold_span
+pub trait Fold {
+    fn fold_org_expr(&mut self, org_expr: OrganismExpression) -> OrganismExpression {
+        fold_org_expr(self, org_expr)
     }
-    fn get_repr(&self) -> String {
-        format!("(jump: to:{:?})", self.to)
+    fn fold_expr(&mut self, expr: Expr) -> Expr {
+        fold_expr(self, expr)
     }
-    fn as_any(&self) -> &dyn Any {
-        self
+    fn fold_leach(&mut self, leach_expr: LeachExpression) -> LeachExpression {
+        fold_leach(self, leach_expr)
     }
-    fn as_mut_any(&mut self) -> &mut dyn Any {
-        self
+    fn fold_passive(&mut self, passive_expr: PassiveExpression) -> PassiveExpression {
+        fold_passive(self, passive_expr)
     }
-}
-
-impl JumpExpression {
-    fn new(label: String) -> JumpExpression {
-        JumpExpression {
-            to: label,
-            conditional: false
-        }
+    fn fold_region(&mut self, region_expr: RegionExpression) -> RegionExpression {
+        RegionExpression { span: self.fold_span(region_expr.span), ..region_expr }
     }
-    fn new_conditional(label: String) -> JumpExpression {
-        JumpExpression {
-            to: label,
-            conditional: true
-        }
+    /// A drill is nothing but its span, which is all there is to fold
+    fn fold_drill(&mut self, span: Span) -> Span {
+        fold_drill(self, span)
     }
-    pub fn to(&self) -> String {
-        self.to.clone()
+    fn fold_label(&mut self, label_expr: LabelExpression) -> LabelExpression {
+        LabelExpression { span: self.fold_span(label_expr.span), ..label_expr }
     }
-    pub fn conditional(&self) -> bool {
-        self.conditional
+    /// Jumps aren't an expression of their own, so the whole jump is rebuilt
+    fn fold_jump(&mut self, to: String, conditional: bool, span: Span) -> Expr {
+        fold_jump(self, to, conditional, span)
+    }
+    fn fold_cell(&mut self, cell_expr: CellExpression) -> CellExpression {
+        CellExpression { span: self.fold_span(cell_expr.span), ..cell_expr }
+    }
+    fn fold_primitive(&mut self, primitive_expr: PrimitiveExpression) -> PrimitiveExpression {
+        PrimitiveExpression { span: self.fold_span(primitive_expr.span), ..primitive_expr }
+    }
+    fn fold_span(&mut self, span: Span) -> Span {
+        span
     }
 }
 
-#[derive(Debug)]
-pub struct DummyExpression {}
-
-impl DummyExpression {
-    pub fn new() -> DummyExpression {
-        DummyExpression {}
-    }
+pub fn fold_org_expr<F: Fold + ?Sized>(folder: &mut F, org_expr: OrganismExpression) -> OrganismExpression {
+    OrganismExpression::new(org_expr.exprs.into_iter().map(|expr| folder.fold_expr(expr)).collect())
 }
 
-impl Expression for DummyExpression {
-    fn get_type(&self) -> ExprType {
-        ExprType::Dummy
-    }
-    fn get_repr(&self) -> String {
-        format!("Dummy Expression")
-    }
-    fn as_any(&self) -> &dyn Any {
-        self
-    }
-    fn as_mut_any(&mut self) -> &mut dyn Any {
-        self
+pub fn fold_expr<F: Fold + ?Sized>(folder: &mut F, expr: Expr) -> Expr {
+    match expr {
+        Expr::Leach(leach_expr) => Expr::Leach(folder.fold_leach(leach_expr)),
+        Expr::Region(region_expr) => Expr::Region(folder.fold_region(region_expr)),
+        Expr::Drill(span) => Expr::Drill(folder.fold_drill(span)),
+        Expr::Label(label_expr) => Expr::Label(folder.fold_label(label_expr)),
+        Expr::Jump { to, conditional, span } => folder.fold_jump(to, conditional, span),
+        Expr::Cell(cell_expr) => Expr::Cell(folder.fold_cell(cell_expr)),
+        Expr::Primitive(primitive_expr) => Expr::Primitive(folder.fold_primitive(primitive_expr))
     }
 }
 
-#[derive(Debug)]
-pub struct OrganismExpression {
-    pub child: OrgExprChild,
-    pub right: OrgExprRight
+pub fn fold_drill<F: Fold + ?Sized>(folder: &mut F, span: Span) -> Span {
+    folder.fold_span(span)
 }
 
-pub type OrgExprChild = Box<dyn Expression>;
-pub type OrgExprRight = Option<Box<OrganismExpression>>;
-
-impl Expression for OrganismExpression {
-    fn get_type(&self) -> ExprType {
-        ExprType::Organism
-    }
-    fn get_repr(&self) -> String {
-        format!("(child: ({:?}), right: ({:?}))", self.child.get_repr(), self.right)
-    }
-    fn as_any(&self) -> &dyn Any {
-        self
-    }
-    fn as_mut_any(&mut self) -> &mut dyn Any {
-        self
-    }
+pub fn fold_jump<F: Fold + ?Sized>(folder: &mut F, to: String, conditional: bool, span: Span) -> Expr {
+    Expr::Jump { to, conditional, span: folder.fold_span(span) }
 }
 
-impl OrganismExpression {
-    pub fn new(child: Box<dyn Expression>, right: Option<Box<OrganismExpression>>) -> OrganismExpression {
-        OrganismExpression {
-            child,
-            right
-        }
-    }
+/// Folds the parts of the leach expression in the order walk_leach visits them
+pub fn fold_leach<F: Fold + ?Sized>(folder: &mut F, leach_expr: LeachExpression) -> LeachExpression {
+    let left = folder.fold_passive(leach_expr.left);
+    let region_change = leach_expr.region_change.map(|region_changes|
+        region_changes.into_iter().map(|region_expr| folder.fold_region(region_expr)).collect()
+    );
+    let onto = leach_expr.onto.into_iter().map(|cell_expr| folder.fold_cell(cell_expr)).collect();
+    let span = folder.fold_span(leach_expr.span);
+    LeachExpression::new(left, onto, leach_expr.is_chain, region_change, span)
 }
 
-impl PartialEq for OrganismExpression {
-    fn eq(&self, other: &Self) -> bool {
-        self.child.get_type() == other.child.get_type() &&
-        self.child.get_repr() == other.child.get_repr() &&
-        self.right == other.right
-    }
-    fn ne(&self, other: &Self) -> bool {
-        self.child.get_type() != other.child.get_type() ||
-        self.child.get_repr() != other.child.get_repr() ||
-        self.right != other.right
+pub fn fold_passive<F: Fold + ?Sized>(folder: &mut F, passive_expr: PassiveExpression) -> PassiveExpression {
+    match passive_expr {
+        PassiveExpression::Cell(cell_expr) => PassiveExpression::Cell(folder.fold_cell(cell_expr)),
+        PassiveExpression::Primitive(primitive_expr) => PassiveExpression::Primitive(folder.fold_primitive(primitive_expr))
     }
 }
 
@@ -810,101 +779,64 @@ lazy_static! {
     ].iter().cloned().collect();
 }
 
-#[derive(Debug, PartialEq)]
-pub enum ExprType {
-    Cell,
-    Leach,
-    Organism,
-    Label,
-    Jump,
-    Primitive,
-    Region,
-    Drill,
-    Dummy
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::lexer::Token::*;
+    use std::io;
+    use crate::lexer::{self, Lexer, TokenKind::*};
     use crate::{c_ident, r_ident, p_ident};
+    /// Gives every token a span as if it were a single char in the source
+    fn spanned(kinds: Vec<TokenKind>) -> Vec<Token> {
+        kinds.into_iter()
+            .enumerate()
+            .map(|(i, kind)| Token::new(kind, Span::new(i, i + 1, 1, i + 1)))
+            .collect()
+    }
+    /// The span of the tokens from start up to end, as given by spanned
+    fn span(start: usize, end: usize) -> Span {
+        Span::new(start, end, 1, start + 1)
+    }
+    fn cell(ident: u8, at: usize) -> CellExpression {
+        CellExpression { ident, span: span(at, at + 1) }
+    }
+    fn primitive(pval: PrimitiveValue, at: usize) -> PassiveExpression {
+        PassiveExpression::Primitive(PrimitiveExpression { pval, span: span(at, at + 1) })
+    }
+    fn region(to: Region, at: usize) -> RegionExpression {
+        RegionExpression::new(to, span(at, at + 1))
+    }
     #[test]
     fn test_valid1(){
         let tokens = vec![
-            r_ident!("L"), Token::Drill, Token::Drill, Token::Drill, p_ident!("`"),
-            r_ident!("C"), Token::Tilde, c_ident!("0"), r_ident!("L"), p_ident!("><"),
-            r_ident!("C"), Token::Tilde, c_ident!("1"), c_ident!("0"), Token::Tilde, c_ident!("1")
+            r_ident!("L"), Drill, Drill, Drill, p_ident!("`"),
+            r_ident!("C"), Tilde, c_ident!("0"), r_ident!("L"), p_ident!("><"),
+            r_ident!("C"), Tilde, c_ident!("1"), c_ident!("0"), Tilde, c_ident!("1")
         ];
-        let result = Parser::new(tokens).parse();
-        let expected_result = OrganismExpression {
-            child: Box::new(RegionExpression {
-                to: Region::Layers
-            }),
-            right: Some(Box::new(OrganismExpression {
-                child: Box::new(DrillExpression::new()),
-                right: Some(Box::new(OrganismExpression {
-                    child: Box::new(DrillExpression::new()),
-                    right: Some(Box::new(OrganismExpression {
-                        child: Box::new(DrillExpression::new()),
-                        right: Some(Box::new(OrganismExpression {
-                            child: Box::new(LeachExpression {
-                                left: Box::new(PrimitiveExpression {
-                                    pval: PrimitiveValue::Six
-                                }),
-                                right: Some(Box::new(LeachExpression {
-                                    left: Box::new(CellExpression {
-                                        ident: 0
-                                    }),
-                                    right: None,
-                                    is_chain: false,
-                                    region_change: None
-                                })),
-                                is_chain: false,
-                                region_change: Some(vec![RegionExpression::new(Region::Cells)])
-                            }),
-                            right: Some(Box::new(OrganismExpression {
-                                child: Box::new(RegionExpression::new(Region::Layers)),
-                                right: Some(Box::new(OrganismExpression {
-                                    child: Box::new(LeachExpression {
-                                        left: Box::new(PrimitiveExpression {
-                                            pval: PrimitiveValue::Output
-                                        }),
-                                        right: Some(Box::new(LeachExpression {
-                                            left: Box::new(CellExpression {
-                                                ident: 1
-                                            }),
-                                            right: None,
-                                            is_chain: false,
-                                            region_change: None
-                                        })),
-                                        is_chain: false,
-                                        region_change: Some(vec![RegionExpression::new(Region::Cells)])
-                                    }),
-                                    right: Some(Box::new(OrganismExpression {
-                                        child: Box::new(LeachExpression {
-                                            left: Box::new(CellExpression {
-                                                ident: 0
-                                            }),
-                                            right: Some(Box::new(LeachExpression {
-                                                left: Box::new(CellExpression {
-                                                    ident: 1
-                                                }),
-                                                right: None,
-                                                is_chain: false,
-                                                region_change: None
-                                            })),
-                                            is_chain: false,
-                                            region_change: None
-                                        }),
-                                        right: None
-                                    }))
-                                }))
-                            }))
-                        }))
-                    }))
-                }))
-            }))
-        };
+        let result = Parser::new(spanned(tokens)).parse();
+        let expected_result = OrganismExpression::new(vec![
+            Expr::Region(region(Region::Layers, 0)),
+            Expr::Drill(span(1, 2)),
+            Expr::Drill(span(2, 3)),
+            Expr::Drill(span(3, 4)),
+            Expr::Leach(LeachExpression::new(
+                primitive(PrimitiveValue::Six, 4),
+                vec![cell(0, 7)],
+                false,
+                Some(vec![region(Region::Cells, 5)]),
+                span(4, 8)
+            )),
+            Expr::Region(region(Region::Layers, 8)),
+            Expr::Leach(LeachExpression::new(
+                primitive(PrimitiveValue::Output, 9),
+                vec![cell(1, 12)],
+                false,
+                Some(vec![region(Region::Cells, 10)]),
+                span(9, 13)
+            )),
+            Expr::Leach(LeachExpression::new(
+                PassiveExpression::Cell(cell(0, 13)), vec![cell(1, 15)], false, None, span(13, 16)
+            ))
+        ]);
         assert!(result.is_ok());
         let result = result.unwrap().0;
         assert_eq!(result, expected_result);
@@ -916,76 +848,223 @@ mod tests {
             c_ident!("0"), Tilde, c_ident!("1"), TripleSixEqM, r_ident!("L"), p_ident!("><"),
             r_ident!("C"), Tilde, c_ident!("2"), c_ident!("2"), Tilde, c_ident!("1"), TripleSixEqM
         ];
-        let result = Parser::new(tokens).parse();
-        let expected_result = OrganismExpression {
-            child: Box::new(RegionExpression {to: Region::Layers}),
-            right: Some(Box::new(OrganismExpression {
-                child: Box::new(DrillExpression::new()),
-                right: Some(Box::new(OrganismExpression {
-                    child: Box::new(DrillExpression::new()),
-                    right: Some(Box::new(OrganismExpression {
-                        child: Box::new(DrillExpression::new()),
-                        right: Some(Box::new(OrganismExpression {
-                            child: Box::new(LeachExpression {
-                                left: Box::new(PrimitiveExpression {pval: PrimitiveValue::Input}),
-                                right: Some(Box::new(LeachExpression {
-                                    left: Box::new(CellExpression {ident: 0}),
-                                    right: None,
-                                    is_chain: false,
-                                    region_change: None
-                                })),
-                                is_chain: false,
-                                region_change: Some(vec![RegionExpression::new(Region::Cells)]),
-                            }),
-                            right: Some(Box::new(OrganismExpression {
-                                child: Box::new(LeachExpression {
-                                    left: Box::new(CellExpression {ident: 0}),
-                                    right: Some(Box::new(LeachExpression {
-                                        left: Box::new(CellExpression {ident: 1}),
-                                        right: None, is_chain: false, region_change: None 
-                                    })),
-                                    is_chain: true,
-                                    region_change: None
-                                }),
-                                right: Some(Box::new(OrganismExpression {
-                                    child: Box::new(RegionExpression {to: Region::Layers}),
-                                    right: Some(Box::new(OrganismExpression {
-                                        child: Box::new(LeachExpression {
-                                            left: Box::new(PrimitiveExpression {pval: PrimitiveValue::Output}),
-                                            right: Some(Box::new(LeachExpression {
-                                                left: Box::new(CellExpression {ident: 2}),
-                                                right: None,
-                                                is_chain: false,
-                                                region_change: None
-                                            })),
-                                            is_chain: false,
-                                            region_change: Some(vec![RegionExpression::new(Region::Cells)])
-                                        }),
-                                        right: Some(Box::new(OrganismExpression {
-                                            child: Box::new(LeachExpression {
-                                                left: Box::new(CellExpression {ident: 2}),
-                                                right: Some(Box::new(LeachExpression {
-                                                    left: Box::new(CellExpression {ident: 1}),
-                                                    right: None,
-                                                    is_chain: false,
-                                                    region_change: None
-                                                })),
-                                                is_chain: true,
-                                                region_change: None
-                                            }),
-                                            right: None
-                                        }))
-                                    }))
-                                }))
-                            }))
-                        }))
-                    }))
-                })) 
-            })) 
-        };
+        let result = Parser::new(spanned(tokens)).parse();
+        let expected_result = OrganismExpression::new(vec![
+            Expr::Region(region(Region::Layers, 0)),
+            Expr::Drill(span(1, 2)),
+            Expr::Drill(span(2, 3)),
+            Expr::Drill(span(3, 4)),
+            Expr::Leach(LeachExpression::new(
+                primitive(PrimitiveValue::Input, 4),
+                vec![cell(0, 7)],
+                false,
+                Some(vec![region(Region::Cells, 5)]),
+                span(4, 8)
+            )),
+            Expr::Leach(LeachExpression::new(
+                PassiveExpression::Cell(cell(0, 8)), vec![cell(1, 10)], true, None, span(8, 12)
+            )),
+            Expr::Region(region(Region::Layers, 12)),
+            Expr::Leach(LeachExpression::new(
+                primitive(PrimitiveValue::Output, 13),
+                vec![cell(2, 16)],
+                false,
+                Some(vec![region(Region::Cells, 14)]),
+                span(13, 17)
+            )),
+            Expr::Leach(LeachExpression::new(
+                PassiveExpression::Cell(cell(2, 17)), vec![cell(1, 19)], true, None, span(17, 21)
+            ))
+        ]);
         assert!(result.is_ok());
         let result = result.unwrap().0;
         assert_eq!(result, expected_result);
+    }
+    #[test]
+    fn test_chain_and_deaths(){
+        // A death of the organism expression before the last one is ignored
+        let source = r"0~1~2^^^^^^666^^^^^^=M^^^^^^666^^^^^^=O3^^^^^^666^^^^^^=O";
+        let (org_expr, _) = Parser::new(lexer::tokenize(source).unwrap()).parse().unwrap();
+        assert_eq!(org_expr.exprs, vec![
+            Expr::Leach(LeachExpression::new(
+                PassiveExpression::Cell(cell(0, 0)),
+                vec![cell(1, 2), cell(2, 4)],
+                true,
+                None,
+                span(0, 22)
+            )),
+            Expr::Cell(cell(3, 39))
+        ]);
+    }
+    #[test]
+    fn test_visitor(){
+        /// Counts the cells leached onto in chains and the conditional jumps
+        #[derive(Default)]
+        struct Counter {
+            chained: usize,
+            conditional_jumps: usize,
+            regions: usize
+        }
+        impl Visitor for Counter {
+            fn visit_leach(&mut self, leach_expr: &LeachExpression) {
+                if leach_expr.is_chain {
+                    self.chained += leach_expr.onto().len();
+                }
+                walk_leach(self, leach_expr);
+            }
+            fn visit_region(&mut self, _region_expr: &RegionExpression) {
+                self.regions += 1;
+            }
+            fn visit_jump(&mut self, _to: &str, conditional: bool, _span: Span) {
+                if conditional {
+                    self.conditional_jumps += 1;
+                }
+            }
+        }
+        let source = r"->L\\|//\\|//\\|//$!->C~Alabel:x:0~1~2^^^^^^666^^^^^^=Mijmp:x:jmp:x:^^^^^^666^^^^^^=O";
+        let (org_expr, _) = Parser::new(lexer::tokenize(source).unwrap()).parse().unwrap();
+        let mut counter = Counter::default();
+        counter.visit_org_expr(&org_expr);
+        assert_eq!((counter.chained, counter.conditional_jumps, counter.regions), (2, 1, 2));
+    }
+    #[test]
+    fn test_fold(){
+        /// Swaps the regions and moves every span to the start of the line
+        struct Swap;
+        impl Fold for Swap {
+            fn fold_region(&mut self, region_expr: RegionExpression) -> RegionExpression {
+                let to = match region_expr.to {
+                    Region::Cells => Region::Layers,
+                    Region::Layers => Region::Cells
+                };
+                RegionExpression::new(to, self.fold_span(region_expr.span))
+            }
+            fn fold_span(&mut self, span: Span) -> Span {
+                Span::new(0, 0, span.line, 1)
+            }
+        }
+        let source = r"->L\\|//\\|//\\|//$!->C~A^^^^^^666^^^^^^=O";
+        let (org_expr, _) = Parser::new(lexer::tokenize(source).unwrap()).parse().unwrap();
+        let start = Span::new(0, 0, 1, 1);
+        assert_eq!(Swap.fold_org_expr(org_expr).exprs, vec![
+            Expr::Region(RegionExpression::new(Region::Cells, start)),
+            Expr::Drill(start),
+            Expr::Drill(start),
+            Expr::Drill(start),
+            Expr::Leach(LeachExpression::new(
+                PassiveExpression::Primitive(PrimitiveExpression { pval: PrimitiveValue::One, span: start }),
+                vec![CellExpression { ident: 10, span: start }],
+                false,
+                Some(vec![RegionExpression::new(Region::Layers, start)]),
+                start
+            ))
+        ]);
 
+        /// Makes every jump unconditional and drops the drills' spans to their lines
+        struct Unconditional;
+        impl Fold for Unconditional {
+            fn fold_drill(&mut self, span: Span) -> Span {
+                Span::new(0, 0, span.line, 1)
+            }
+            fn fold_jump(&mut self, to: String, _conditional: bool, span: Span) -> Expr {
+                Expr::Jump { to, conditional: false, span: self.fold_span(span) }
+            }
+        }
+        let source = r"->Llabel:x:\\|//ijmp:x:^^^^^^666^^^^^^=O";
+        let (org_expr, _) = Parser::new(lexer::tokenize(source).unwrap()).parse().unwrap();
+        let exprs = Unconditional.fold_org_expr(org_expr).exprs;
+        assert_eq!(exprs[2], Expr::Drill(Span::new(0, 0, 1, 1)));
+        assert!(matches!(&exprs[3], Expr::Jump { to, conditional: false, .. } if to == "x"));
     }
-}
\ No newline at end of file
+    #[test]
+    fn test_million_tokens(){
+        // Each block is 8 tokens: a label, a chain and a jump back to the label. The
+        // parser mustn't recurse per expression, or the test thread's stack overflows,
+        // and the labels mustn't be checked against each other
+        let blocks = 125_000;
+        let source: String = (0..blocks)
+            .map(|i| format!("label:l{0}:0~1~2^^^^^^666^^^^^^=Mjmp:l{0}:", i))
+            .chain(std::iter::once(format!("^^^^^^666^^^^^^=O")))
+            .collect();
+        let (org_expr, labels) = Parser::from_stream(Lexer::new(io::Cursor::new(source))).parse().unwrap();
+        assert_eq!(org_expr.exprs.len(), 3 * blocks);
+        assert_eq!(labels.len(), blocks);
+    }
+    #[test]
+    fn test_err_duplicate_label(){
+        let tokens = vec![
+            Label(format!("x")), Label(format!("y")), Label(format!("x"))
+        ];
+        let result = Parser::new(spanned(tokens)).parse();
+        let expected_result = Err(vec![
+            CompileError::DuplicateLabel(Span::new(0, 1, 1, 1), Span::new(2, 3, 1, 3), format!("x"))
+        ]);
+        assert_eq!(result.map(|(_, labels)| labels), expected_result);
+    }
+    #[test]
+    fn test_err_jump_to_non_existent_label(){
+        let tokens = vec![
+            Label(format!("x")), Jump(format!("x")), ConditionalJump(format!("y"))
+        ];
+        let result = Parser::new(spanned(tokens)).parse();
+        let expected_result = Err(vec![
+            CompileError::JumpToNonExistentLabel(Span::new(2, 3, 1, 3), format!("y"))
+        ]);
+        assert_eq!(result.map(|(_, labels)| labels), expected_result);
+    }
+    #[test]
+    fn test_err_chain_leach_without_massacre(){
+        let tokens = vec![
+            c_ident!("0"), Tilde, c_ident!("1"), Tilde, c_ident!("2")
+        ];
+        let result = Parser::new(spanned(tokens)).parse();
+        let expected_result = Err(vec![
+            CompileError::ChainedLeachMustEndInMassacre(Span::new(0, 5, 1, 1), Span::new(4, 5, 1, 5))
+        ]);
+        assert_eq!(result.map(|(_, labels)| labels), expected_result);
+    }
+    #[test]
+    fn test_recovery(){
+        let tokens = vec![
+            Tilde, r_ident!("L"), Drill, c_ident!("0"), TripleSixEq,
+            Label(format!("x")), Jump(format!("y")), Label(format!("x"))
+        ];
+        let result = Parser::new(spanned(tokens)).parse();
+        let expected_result = Err(vec![
+            CompileError::LeachMustStartWithPrimitiveOrCell(Span::new(0, 1, 1, 1)),
+            CompileError::InvalidCellAccessRegion(Span::new(3, 4, 1, 4)),
+            CompileError::TripleSixEqNotExpected(Span::new(4, 5, 1, 5)),
+            CompileError::JumpToNonExistentLabel(Span::new(6, 7, 1, 7), format!("y")),
+            CompileError::DuplicateLabel(Span::new(5, 6, 1, 6), Span::new(7, 8, 1, 8), format!("x"))
+        ]);
+        assert_eq!(result.map(|(_, labels)| labels), expected_result);
+    }
+    #[test]
+    fn test_error_limit(){
+        let tokens = vec![
+            Tilde, r_ident!("L"), Tilde, r_ident!("L"), Tilde, r_ident!("L")
+        ];
+        let mut parser = Parser::new(spanned(tokens));
+        parser.set_error_limit(2);
+        let expected_result = Err(vec![
+            CompileError::LeachMustStartWithPrimitiveOrCell(Span::new(0, 1, 1, 1)),
+            CompileError::LeachMustStartWithPrimitiveOrCell(Span::new(2, 3, 1, 3))
+        ]);
+        assert_eq!(parser.parse().map(|(_, labels)| labels), expected_result);
+    }
+    #[test]
+    fn test_stream(){
+        let source = r"->L\\|//\\|//\\|//$!->C->L->C~0label:x:0~1~2^^^^^^666^^^^^^=Mjmp:x:^^^^^^666^^^^^^=O";
+        let streamed = Parser::from_stream(Lexer::new(source.as_bytes())).parse();
+        let collected = Parser::new(lexer::tokenize(source).unwrap()).parse();
+        assert!(streamed.is_ok());
+        assert_eq!(streamed, collected);
+    }
+    #[test]
+    fn test_stream_lexer_errors(){
+        // Only the lexer's errors are reported, not what the parser makes of the tokens around them
+        let source = r"\\|//$!->X~0^^^^^^666^^^^^^=O";
+        let result = Parser::from_stream(Lexer::new(source.as_bytes())).parse();
+        assert_eq!(result, Err(vec![CompileError::UnrecognizedToken(Span::new(9, 10, 1, 10))]));
+    }
+}
@@ -0,0 +1,236 @@
+//! Turns tokens and expressions back into Mindbend source
+
+use crate::lexer::{Span, Token, TokenKind};
+use crate::parser::{
+    OrganismExpression, CellExpression, PrimitiveExpression, PrimitiveValue,
+    LeachExpression, RegionExpression, LabelExpression, Region, Visitor
+};
+
+const TRIPLE_SIX: &'static str = "^^^^^^666^^^^^^";
+const DRILL: &'static str = r"\\|//";
+
+/// The source of the tokens, followed by the death of the organism expression,
+/// which tokenize leaves out. Tokens are printed exactly as they were written, so
+/// tokenizing the source gives back the same tokens, spans and all
+pub fn print_tokens(tokens: &[Token]) -> String {
+    let mut out: String = tokens.iter().map(|token| print_token(&token.kind)).collect();
+    out.push_str(&print_token(&TokenKind::TripleSixEqO));
+    out
+}
+
+pub fn print_token(kind: &TokenKind) -> String {
+    match kind {
+        TokenKind::TripleSix => format!("{}", TRIPLE_SIX),
+        TokenKind::TripleSixEq => format!("{}=", TRIPLE_SIX),
+        TokenKind::TripleSixEqM => format!("{}=M", TRIPLE_SIX),
+        TokenKind::TripleSixEqO => format!("{}=O", TRIPLE_SIX),
+        TokenKind::Tilde => format!("~"),
+        TokenKind::Drill => format!("{}", DRILL),
+        TokenKind::Label(label) => format!("label:{}:", label),
+        TokenKind::Jump(label) => format!("jmp:{}:", label),
+        TokenKind::ConditionalJump(label) => format!("ijmp:{}:", label),
+        TokenKind::Include(path) => format!("include:{}:", path),
+        TokenKind::Macro(name, params) => format!("macro:{}:{}:", name, params.join(",")),
+        TokenKind::EndMacro => format!("endmacro:"),
+        TokenKind::Call(name) => format!("call:{}:", name),
+        TokenKind::Placeholder(name) => format!("<{}>", name),
+        TokenKind::CellIdent(ident) => format!("{}", ident),
+        TokenKind::RegionIdent(ident) => format!("->{}", ident),
+        TokenKind::PrimitiveIdent(ident) => format!("${}", ident)
+    }
+}
+
+/// The canonical source of the organism expression. Primitives are printed by
+/// their symbols, since the expressions don't remember whether an index was used
+pub fn print_ast(org_expr: &OrganismExpression) -> String {
+    let mut printer = Printer { out: String::new() };
+    printer.visit_org_expr(org_expr);
+    printer.out.push_str(&print_token(&TokenKind::TripleSixEqO));
+    printer.out
+}
+
+struct Printer {
+    out: String
+}
+
+impl Visitor for Printer {
+    /// Prints the leach expression's first operand and its region changes, then each
+    /// cell it's leached onto, ending in a massacre if it's a chain
+    fn visit_leach(&mut self, leach_expr: &LeachExpression) {
+        self.visit_passive(leach_expr.left());
+        for region_expr in leach_expr.region_change.iter().flatten() {
+            self.visit_region(region_expr);
+        }
+        for cell_expr in leach_expr.onto() {
+            self.out.push('~');
+            self.visit_cell(cell_expr);
+        }
+        if leach_expr.is_chain {
+            self.out.push_str(&print_token(&TokenKind::TripleSixEqM));
+        }
+    }
+    fn visit_region(&mut self, region_expr: &RegionExpression) {
+        let ident = match region_expr.to() {
+            Region::Cells => "C",
+            Region::Layers => "L"
+        };
+        self.out.push_str(&print_token(&TokenKind::RegionIdent(ident.to_string())));
+    }
+    fn visit_drill(&mut self, _span: Span) {
+        self.out.push_str(DRILL);
+    }
+    fn visit_label(&mut self, label_expr: &LabelExpression) {
+        self.out.push_str(&print_token(&TokenKind::Label(label_expr.label())));
+    }
+    fn visit_jump(&mut self, to: &str, conditional: bool, _span: Span) {
+        let kind = if conditional {
+            TokenKind::ConditionalJump(to.to_string())
+        } else {
+            TokenKind::Jump(to.to_string())
+        };
+        self.out.push_str(&print_token(&kind));
+    }
+    fn visit_cell(&mut self, cell_expr: &CellExpression) {
+        self.out.push_str(&format!("{:X}", cell_expr.ident()));
+    }
+    fn visit_primitive(&mut self, primitive_expr: &PrimitiveExpression) {
+        self.out.push('$');
+        self.out.push_str(primitive_symbol(primitive_expr.pval()));
+    }
+}
+
+fn primitive_symbol(pval: PrimitiveValue) -> &'static str {
+    match pval {
+        PrimitiveValue::One => "!",
+        PrimitiveValue::Two => "@",
+        PrimitiveValue::Three => "#",
+        PrimitiveValue::Four => "+",
+        PrimitiveValue::Five => "%",
+        PrimitiveValue::Six => "`",
+        PrimitiveValue::Seven => "&",
+        PrimitiveValue::Eight => "*",
+        PrimitiveValue::Nine => "(",
+        PrimitiveValue::Zero => ")",
+        PrimitiveValue::Input => "<>",
+        PrimitiveValue::Output => "><",
+        PrimitiveValue::Addition => "}",
+        PrimitiveValue::Subtraction => "{"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::fs;
+    use std::io;
+    use std::path::PathBuf;
+    use std::rc::Rc;
+    use crate::lexer::tokenize;
+    use crate::parser::{Parser, Fold};
+    use crate::source_map::SourceMap;
+
+    /// The sample programs that are meant not to tokenize or parse
+    const ERRONEOUS: [&str; 13] = [
+        "attempt_access_primitive_in_cells_region.mb",
+        "attempt_access_primitive_when_gates_closed.mb",
+        "attempt_repeat_cell_in_chain_leach.mb",
+        "attempt_to_access_cell_in_layers_region2.mb",
+        "attempt_to_drill_in_cells_region.mb",
+        "attempt_to_repeat_cell_in_leach.mb",
+        "chain_leach_without_ending_massacre.mb",
+        "empty_file.mb",
+        "include_cycle.mb",
+        "invalid_utf8.mb",
+        "jump_to_non_existent_label.mb",
+        "malformed_leach_expression.mb",
+        "multiple_errors.mb"
+    ];
+
+    /// Every sample program, with its tokens, its includes read in from next to it or
+    /// sampleprog/lib and its macros expanded, as the compiler reads it
+    fn sample_programs() -> Vec<(String, Result<Vec<Token>, crate::Error>)> {
+        let mut paths: Vec<PathBuf> = fs::read_dir("sampleprog").unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().map(|ext| ext == "mb").unwrap_or(false))
+            .collect();
+        paths.sort();
+        let options = crate::Options { search_path: vec![PathBuf::from("sampleprog/lib")], ..crate::Options::default() };
+        paths.iter().map(|path| {
+            let source_map = Rc::new(RefCell::new(SourceMap::new()));
+            source_map.borrow_mut().add_file(path);
+            let input = io::BufReader::new(fs::File::open(path).unwrap());
+            (path.display().to_string(), crate::tokenize_all(input, source_map, &options))
+        }).collect()
+    }
+
+    fn is_erroneous(path: &str) -> bool {
+        ERRONEOUS.iter().any(|name| path == format!("sampleprog/{}", name))
+    }
+
+    /// Sets every span to the default. Printing primitives by their symbols moves what
+    /// comes after them, so only the expressions are compared
+    struct WithoutSpans;
+
+    impl Fold for WithoutSpans {
+        fn fold_span(&mut self, _span: Span) -> Span {
+            Span::default()
+        }
+    }
+
+    fn without_spans(org_expr: &OrganismExpression) -> OrganismExpression {
+        WithoutSpans.fold_org_expr(org_expr.clone())
+    }
+
+    #[test]
+    fn test_print_token(){
+        assert_eq!(print_token(&TokenKind::PrimitiveIdent(format!("><"))), "$><");
+        assert_eq!(print_token(&TokenKind::TripleSixEqM), "^^^^^^666^^^^^^=M");
+        assert_eq!(print_token(&TokenKind::Drill), r"\\|//");
+        assert_eq!(print_token(&TokenKind::ConditionalJump(format!("x"))), "ijmp:x:");
+    }
+
+    #[test]
+    fn test_print_ast(){
+        let source = r"->L\\|//\\|//\\|//$D->C~0->L$3->C->L->C~1label:x:2~3~4^^^^^^666^^^^^^=Mijmp:x:^^^^^^666^^^^^^=O";
+        let (org_expr, _) = Parser::new(tokenize(source).unwrap()).parse().unwrap();
+        assert_eq!(
+            print_ast(&org_expr),
+            r"->L\\|//\\|//\\|//$!->C~0->L$<>->C->L->C~1label:x:2~3~4^^^^^^666^^^^^^=Mijmp:x:^^^^^^666^^^^^^=O"
+        );
+    }
+
+    #[test]
+    fn test_tokens_round_trip(){
+        for (path, tokens) in sample_programs() {
+            let tokens = match tokens {
+                Err(_) if is_erroneous(&path) => continue,
+                tokens => tokens.expect(&path)
+            };
+            let printed = print_tokens(&tokens);
+            let kinds = |tokens: Vec<Token>| tokens.into_iter().map(|token| token.kind).collect::<Vec<_>>();
+            assert_eq!(tokenize(&printed).map(kinds), Ok(kinds(tokens)), "{}", path);
+            // Without includes and macros, the tokens are the source as it's written
+            let source = fs::read_to_string(&path).unwrap();
+            if !source.contains("include:") && !source.contains("macro:") {
+                assert_eq!(printed, source, "{}", path);
+            }
+        }
+    }
+
+    #[test]
+    fn test_ast_round_trip(){
+        for (path, tokens) in sample_programs() {
+            let parsed = tokens.map_err(|_| ()).and_then(|tokens| Parser::new(tokens).parse().map_err(|_| ()));
+            let org_expr = match parsed {
+                Ok((org_expr, _)) => org_expr,
+                Err(_) if is_erroneous(&path) => continue,
+                Err(_) => panic!("{} doesn't parse", path)
+            };
+            assert!(!is_erroneous(&path), "{} parses, so it shouldn't be in ERRONEOUS", path);
+            let printed = print_ast(&org_expr);
+            let reparsed = Parser::new(tokenize(&printed).unwrap()).parse().map(|(org_expr, _)| org_expr);
+            assert_eq!(reparsed.map(|org_expr| without_spans(&org_expr)), Ok(without_spans(&org_expr)), "{}", path);
+        }
+    }
+}
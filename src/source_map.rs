@@ -0,0 +1,108 @@
+//! The files a program is made of, so that spans can be traced back to the
+//! file they were read from
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Identifies one of the files in a source map
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct FileId(pub usize);
+
+impl FileId {
+    /// The file the compiler was given. Every other file was included from it
+    pub const ROOT: FileId = FileId(0);
+}
+
+#[derive(Debug, Clone)]
+struct SourceFile {
+    /// How the file is referred to in diagnostics
+    name: String,
+    path: Option<PathBuf>,
+    /// The source, if it was given instead of being read from the path
+    source: Option<String>
+}
+
+/// The files of a program, in the order they were first read. The root comes first
+#[derive(Debug, Clone, Default)]
+pub struct SourceMap {
+    files: Vec<SourceFile>
+}
+
+impl SourceMap {
+    pub fn new() -> SourceMap {
+        SourceMap {
+            files: vec![]
+        }
+    }
+    /// A map of a single file, whose source is already in memory
+    pub fn from_source(name: &str, source: &str) -> SourceMap {
+        let mut source_map = SourceMap::new();
+        source_map.add_source(name, source.to_string());
+        source_map
+    }
+    /// Adds a file that is read from the path when its source is needed
+    pub fn add_file(&mut self, path: &Path) -> FileId {
+        self.files.push(SourceFile {
+            name: path.display().to_string(),
+            path: Some(path.to_path_buf()),
+            source: None
+        });
+        FileId(self.files.len() - 1)
+    }
+    pub fn add_source(&mut self, name: &str, source: String) -> FileId {
+        self.files.push(SourceFile {
+            name: name.to_string(),
+            path: None,
+            source: Some(source)
+        });
+        FileId(self.files.len() - 1)
+    }
+    /// Every file in the map, the root first
+    pub fn files(&self) -> impl Iterator<Item = FileId> {
+        (0..self.files.len()).map(FileId)
+    }
+    pub fn name(&self, file: FileId) -> &str {
+        self.files.get(file.0).map(|file| file.name.as_str()).unwrap_or("<unknown>")
+    }
+    pub fn path(&self, file: FileId) -> Option<&Path> {
+        self.files.get(file.0).and_then(|file| file.path.as_deref())
+    }
+    /// The whole source of the file. Files are read again each time, since the
+    /// source is only needed to show the parts of it that diagnostics point at.
+    /// A file that isn't valid UTF-8 is only read up to where it stops being UTF-8,
+    /// as it is when it's lexed, so spans point at the same bytes in both
+    pub fn source(&self, file: FileId) -> String {
+        match self.files.get(file.0) {
+            Some(SourceFile { source: Some(source), .. }) => source.clone(),
+            Some(SourceFile { path: Some(path), .. }) => fs::read(path)
+                .map(|bytes| String::from_utf8(bytes).unwrap_or_else(|err| {
+                    let valid_len = err.utf8_error().valid_up_to();
+                    let mut bytes = err.into_bytes();
+                    bytes.truncate(valid_len);
+                    String::from_utf8(bytes).unwrap()
+                }))
+                .unwrap_or_default(),
+            _ => String::new()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_source_map(){
+        let mut source_map = SourceMap::from_source("prog.mb", "0~1^^^^^^666^^^^^^=O");
+        let lib = source_map.add_file(Path::new("sampleprog/lib/save_cells.mb"));
+        assert_eq!(lib, FileId(1));
+        assert_eq!(source_map.name(FileId::ROOT), "prog.mb");
+        assert_eq!(source_map.source(FileId::ROOT), "0~1^^^^^^666^^^^^^=O");
+        assert_eq!(source_map.name(lib), "sampleprog/lib/save_cells.mb");
+        assert_eq!(source_map.source(lib), fs::read_to_string("sampleprog/lib/save_cells.mb").unwrap());
+        assert_eq!(source_map.path(FileId::ROOT), None);
+        assert_eq!(source_map.source(FileId(2)), "");
+        let invalid = source_map.add_file(Path::new("sampleprog/invalid_utf8.mb"));
+        assert_eq!(source_map.source(invalid), "->L\\\\|//\\\\|//\\\\|//$!->C~0~");
+    }
+}
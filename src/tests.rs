@@ -112,7 +112,14 @@ fn jump_to_nonexistent_label(){
     let filename = "jump_to_non_existent_label.mb";
     ecompile!(
         name => filename,
-        stderr => "Attempt to jump to non existent label at the nth token, where n is around 1\n"
+        stderr => concat!(
+            "error[MB0015]: Attempt to jump to non existent label at line 1, column 1\n",
+            " --> sampleprog/jump_to_non_existent_label.mb:1:1\n",
+            "  |\n",
+            "1 | jmp:non_existent:^^^^^^666^^^^^^=O\n",
+            "  | ^^^^^^^^^^^^^^^^^ no label named `non_existent`\n",
+            "For more information about this error, try `mindbend explain MB0015`\n",
+        )
     );
 }
 
@@ -121,9 +128,16 @@ fn chain_leach_without_ending_massacre(){
     let filename = "chain_leach_without_ending_massacre.mb";
     ecompile!(
         name => filename,
-        stderr => "Chained leach expression at the nth token, \
-            where n is around 5, does not end in a massacre. \
-            A chained leach expression must end in a massacre\n"
+        stderr => concat!(
+            "error[MB0014]: Chained leach expression at line 1, column 1, does not end in a massacre. A chained leach expression must end in a massacre\n",
+            " --> sampleprog/chain_leach_without_ending_massacre.mb:1:1\n",
+            "  |\n",
+            "1 | 0~1~2^^^^^^666^^^^^^=O\n",
+            "  | ^^^^^\n",
+            "  | this chained leach expression\n",
+            "  |     - expected ^^^^^^666^^^^^^=M after this\n",
+            "For more information about this error, try `mindbend explain MB0014`\n",
+        )
     );
 }
 
@@ -132,22 +146,55 @@ fn non_existent_file(){
     let filename = "non_existent_file...........";
     ecompile!(
         name => filename,
-        stderr => "The input file doesn't exist\n"
+        stderr => concat!(
+            "error[MB0025]: Something went wrong while reading the input file: ",
+            "sampleprog/non_existent_file........... doesn't exist\n",
+            "For more information about this error, try `mindbend explain MB0025`\n",
+        )
     );
+    let output = Command::cargo_bin("mindbend")
+        .unwrap()
+        .arg(file!(filename))
+        .arg("--message-format=json")
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert_eq!(output.stderr, b"");
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(json["code"], "MB0025");
+    assert_eq!(json["span"], serde_json::Value::Null);
 }
 
 #[test]
 fn attempt_to_use_expr_in_arg_cell_after_massacre(){
     let filename = "attempt_to_use_expr_in_arg_cell_after_massacre.mb";
-    compile!(name => filename, stdout => "");
-    erun!(stdout => "2Attempt to leach death expression onto another Cell\n");
+    ecompile!(
+        name => filename,
+        stderr => concat!(
+            "error[MB0035]: Attempt to leach death expression onto another Cell at line 1, column 122\n",
+            " --> sampleprog/attempt_to_use_expr_in_arg_cell_after_massacre.mb:1:122\n",
+            "  |\n",
+            "1 | ...^^^^^666^^^^^^=M9~8^^^^^^666^^^^^^=O\n",
+            "  |                    ^ holds the Death Expression here\n",
+            "For more information about this error, try `mindbend explain MB0035`\n",
+        )
+    );
 }
 
 #[test]
 fn attempt_to_leach_expr_onto_cell_after_leached_away(){
     let filename = "attempt_to_leach_expr_onto_cell_after_leached_away.mb";
-    compile!(name => filename, stdout => "");
-    erun!(stdout => "Attempt to leach death expression onto another Cell\n");
+    ecompile!(
+        name => filename,
+        stderr => concat!(
+            "error[MB0035]: Attempt to leach death expression onto another Cell at line 1, column 29\n",
+            " --> sampleprog/attempt_to_leach_expr_onto_cell_after_leached_away.mb:1:29\n",
+            "  |\n",
+            "1 | .../\\\\|//$@->C~00~10~2^^^^^^666^^^^^^=O\n",
+            "  |                    ^ holds the Death Expression here\n",
+            "For more information about this error, try `mindbend explain MB0035`\n",
+        )
+    );
 }
 
 #[test]
@@ -155,7 +202,14 @@ fn attempt_to_drill_in_cells_region(){
     let filename = "attempt_to_drill_in_cells_region.mb";
     ecompile!(
         name => filename,
-        stderr => "Attempt to drill in the Cells Region at the nth token, where n is around 1\n"
+        stderr => concat!(
+            "error[MB0013]: Attempt to drill in the Cells Region at line 1, column 1\n",
+            " --> sampleprog/attempt_to_drill_in_cells_region.mb:1:1\n",
+            "  |\n",
+            "1 | \\\\|//^^^^^^666^^^^^^=O\n",
+            "  | ^^^^^ gates can only be drilled in the Layers Region\n",
+            "For more information about this error, try `mindbend explain MB0013`\n",
+        )
     );
 }
 
@@ -169,15 +223,51 @@ fn attempt_to_access_cell_in_layers_region(){
 #[test]
 fn attempt_to_access_cell_in_layers_region2(){
     let filename = "attempt_to_access_cell_in_layers_region2.mb";
-    compile!(name => filename, stdout => "");
-    erun!(stdout => "Attempting to access cell outside the Cells Region\n");
+    ecompile!(
+        name => filename,
+        stderr => concat!(
+            "error[MB0034]: Attempting to store a primitive in a cell outside the Cells Region at line 1, column 28\n",
+            " --> sampleprog/attempt_to_access_cell_in_layers_region2.mb:1:28\n",
+            "  |\n",
+            "1 | ...//\\\\|//$@->C->L~0^^^^^^666^^^^^^=O\n",
+            "  |                    ^ not in the Cells Region here\n",
+            "For more information about this error, try `mindbend explain MB0034`\n",
+        )
+    );
 }
 
 #[test]
 fn attempt_non_function_primitive_massacre(){
     let filename = "attempt_non_function_primitive_massacre.mb";
-    compile!(name => filename, stdout => "");
-    erun!(stdout => "Attempt to use non-function primitive to massacre\n");
+    ecompile!(
+        name => filename,
+        stderr => concat!(
+            "error[MB0036]: Attempt to use non-function primitive to massacre at line 1, column 1\n",
+            " --> sampleprog/attempt_non_function_primitive_massacre.mb:1:1\n",
+            "  |\n",
+            "1 | 0~1~2^^^^^^666^^^^^^=M^^^^^^666^^^^^^=O\n",
+            "  | ^ holds the Death Expression here\n",
+            "For more information about this error, try `mindbend explain MB0036`\n",
+        )
+    );
+}
+
+#[test]
+fn massacre_given_wrong_number_of_cells(){
+    let filename = "massacre_given_wrong_number_of_cells.mb";
+    ecompile!(
+        name => filename,
+        stderr => concat!(
+            "error[MB0037]: Massacre at line 1, column 26 given the wrong number of cells: addition given 3 operand cells; it takes exactly 2\n",
+            " --> sampleprog/massacre_given_wrong_number_of_cells.mb:1:28\n",
+            "  |\n",
+            "1 | ...\\|//\\\\|//$}->C~00~1~2~3^^^^^^666^^^^^^=M^^^^^^666^^^^^^=O\n",
+            "  |                    -\n",
+            "  |                    the massacre's primitive comes from this cell\n",
+            "  |                      ^^^^^ addition given 3 operand cells; it takes exactly 2\n",
+            "For more information about this error, try `mindbend explain MB0037`\n",
+        )
+    );
 }
 
 #[test]
@@ -185,8 +275,14 @@ fn attempt_access_primitive_when_gates_closed(){
     let filename = "attempt_access_primitive_when_gates_closed.mb";
     ecompile!(
         name => filename,
-        stderr => "Attempting to access primitive when the Layers gates \
-        aren\'t fully open at the nth token, where n is around 2\n"
+        stderr => concat!(
+            "error[MB0007]: Attempting to access primitive when the Layers gates aren't fully open at line 1, column 4\n",
+            " --> sampleprog/attempt_access_primitive_when_gates_closed.mb:1:4\n",
+            "  |\n",
+            "1 | ->L$@^^^^^^666^^^^^^=O\n",
+            "  |    ^^ the gates aren't fully open here\n",
+            "For more information about this error, try `mindbend explain MB0007`\n",
+        )
     );
 }
 
@@ -195,8 +291,14 @@ fn attempt_access_primitive_in_cells_region(){
     let filename = "attempt_access_primitive_in_cells_region.mb";
     ecompile!(
         name => filename,
-        stderr => "Attempting to access primitive outside the Layers Region \
-            at the nth token, where n is around 1\n"
+        stderr => concat!(
+            "error[MB0006]: Attempting to access primitive outside the Layers Region at line 1, column 1\n",
+            " --> sampleprog/attempt_access_primitive_in_cells_region.mb:1:1\n",
+            "  |\n",
+            "1 | $#^^^^^^666^^^^^^=O\n",
+            "  | ^^ not in the Layers Region here\n",
+            "For more information about this error, try `mindbend explain MB0006`\n",
+        )
     );
 }
 
@@ -220,8 +322,14 @@ fn malformed_leach_expression(){
     let filename = "malformed_leach_expression.mb";
     ecompile!(
         name => filename,
-        stderr => "The leach expression at the nth token, where n is around 11, \
-            does not begin with a primitive or Cell\n"
+        stderr => concat!(
+            "error[MB0017]: The leach expression at line 1, column 30, does not begin with a primitive or Cell\n",
+            " --> sampleprog/malformed_leach_expression.mb:1:30\n",
+            "  |\n",
+            "1 | ...\\\\|//$!->C~00->L~2^^^^^^666^^^^^^=O\n",
+            "  |                    ^ expected a primitive or cell before this\n",
+            "For more information about this error, try `mindbend explain MB0017`\n",
+        )
     );
 }
 
@@ -230,7 +338,16 @@ fn attempt_to_repeat_cell_in_leach(){
     let filename = "attempt_to_repeat_cell_in_leach.mb";
     ecompile!(
         name => filename,
-        stderr => "Attempt to leach expression onto itself at the nth token, where n is around 11\n"
+        stderr => concat!(
+            "error[MB0018]: Attempt to leach expression onto itself at line 1, column 28\n",
+            " --> sampleprog/attempt_to_repeat_cell_in_leach.mb:1:28\n",
+            "  |\n",
+            "1 | ...\\|//\\\\|//$@->C~11~1^^^^^^666^^^^^^=O\n",
+            "  |                    -\n",
+            "  |                    the expression comes from this cell\n",
+            "  |                      ^ leached onto again here\n",
+            "For more information about this error, try `mindbend explain MB0018`\n",
+        )
     );
 }
 
@@ -239,7 +356,16 @@ fn attempt_repeat_cell_in_chain_leach(){
     let filename = "attempt_repeat_cell_in_chain_leach.mb";
     ecompile!(
         name => filename,
-        stderr => "Attempt to leach expression onto itself at the nth token, where n is around 15\n"
+        stderr => concat!(
+            "error[MB0018]: Attempt to leach expression onto itself at line 1, column 32\n",
+            " --> sampleprog/attempt_repeat_cell_in_chain_leach.mb:1:32\n",
+            "  |\n",
+            "1 | ...\\|//\\\\|//$@->C~11~2~3~1^^^^^^666^^^^^^=M^^^^^^666^^^^^^=O\n",
+            "  |                    -\n",
+            "  |                    the expression comes from this cell\n",
+            "  |                          ^ leached onto again here\n",
+            "For more information about this error, try `mindbend explain MB0018`\n",
+        )
     );
 }
 
@@ -248,8 +374,49 @@ fn empty_file(){
     let filename = "empty_file.mb";
     ecompile!(
         name => filename,
-        stderr => "The input file is empty\n"
+        stderr => concat!(
+            "error[MB0025]: Something went wrong while reading the input file: sampleprog/empty_file.mb is empty\n",
+            "For more information about this error, try `mindbend explain MB0025`\n",
+        )
+    );
+}
+
+#[test]
+fn only_death(){
+    let filename = "only_death.mb";
+    ecompile!(
+        name => filename,
+        stderr => concat!(
+            "error[MB0040]: Only Organism death.\n",
+            "For more information about this error, try `mindbend explain MB0040`\n",
+        )
     );
+    let output = Command::cargo_bin("mindbend")
+        .unwrap()
+        .arg(file!(filename))
+        .arg("--message-format=sarif")
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let log: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(log["runs"][0]["results"][0]["ruleId"], "MB0040");
+}
+
+#[test]
+fn emit_write_failure(){
+    let output = Command::cargo_bin("mindbend")
+        .unwrap()
+        .arg(file!("print1.mb"))
+        .arg("--emit=tokens=no_such_dir/tokens.json")
+        .arg("--message-format=json")
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(json["code"], "MB0023");
+    assert!(json["message"].as_str().unwrap().starts_with(
+        "Something went wrong while writing to a file: the tokens to no_such_dir/tokens.json: "
+    ));
 }
 
 #[test]
@@ -263,4 +430,621 @@ fn compile_with_alternate_output_filename(){
     );
     let open_file_attempt = fs::File::open(out_filename);
     assert!(open_file_attempt.is_ok());
-}
\ No newline at end of file
+}
+
+#[test]
+fn explain_error_code(){
+    Command::cargo_bin("mindbend")
+        .unwrap()
+        .arg("explain")
+        .arg("MB0013")
+        .assert()
+        .success()
+        .stdout(predicates::str::starts_with("A drill expression was used in the Cells Region.\n"));
+}
+
+#[test]
+fn explain_unknown_error_code(){
+    Command::cargo_bin("mindbend")
+        .unwrap()
+        .arg("explain")
+        .arg("MB9999")
+        .assert()
+        .failure()
+        .stderr("MB9999 is not a Mindbend error code or lint\n");
+}
+
+#[test]
+fn multiple_errors(){
+    let filename = "multiple_errors.mb";
+    ecompile!(
+        name => filename,
+        stderr => concat!(
+            "error[MB0013]: Attempt to drill in the Cells Region at line 1, column 1\n",
+            " --> sampleprog/multiple_errors.mb:1:1\n",
+            "  |\n",
+            "1 | \\\\|//$!->C~0label:x:jmp:y:^^^^^^666^^^^^^=O\n",
+            "  | ^^^^^ gates can only be drilled in the Layers Region\n",
+            "\n",
+            "error[MB0006]: Attempting to access primitive outside the Layers Region at line 1, column 6\n",
+            " --> sampleprog/multiple_errors.mb:1:6\n",
+            "  |\n",
+            "1 | \\\\|//$!->C~0label:x:jmp:y:^^^^^^666^^^^^^=O\n",
+            "  |      ^^ not in the Layers Region here\n",
+            "\n",
+            "error[MB0015]: Attempt to jump to non existent label at line 1, column 21\n",
+            " --> sampleprog/multiple_errors.mb:1:21\n",
+            "  |\n",
+            "1 | .../$!->C~0label:x:jmp:y:^^^^^^666^^^^^^=O\n",
+            "  |                    ^^^^^^ no label named `y`\n",
+            "For more information about these errors, try `mindbend explain` with one of MB0006, MB0013, MB0015\n",
+        )
+    );
+}
+
+#[test]
+fn error_limit(){
+    Command::cargo_bin("mindbend")
+        .unwrap()
+        .arg(file!("multiple_errors.mb"))
+        .arg("--error-limit=1")
+        .assert()
+        .failure()
+        .stderr(concat!(
+            "error[MB0013]: Attempt to drill in the Cells Region at line 1, column 1\n",
+            " --> sampleprog/multiple_errors.mb:1:1\n",
+            "  |\n",
+            "1 | \\\\|//$!->C~0label:x:jmp:y:^^^^^^666^^^^^^=O\n",
+            "  | ^^^^^ gates can only be drilled in the Layers Region\n",
+            "Stopped at the error limit of 1. Use --error-limit to see more\n",
+            "For more information about this error, try `mindbend explain MB0013`\n",
+        ));
+}
+
+
+#[test]
+fn json_message_format(){
+    let output = Command::cargo_bin("mindbend")
+        .unwrap()
+        .arg(file!("multiple_errors.mb"))
+        .arg("--message-format=json")
+        .assert()
+        .failure()
+        .stderr("")
+        .get_output()
+        .stdout
+        .clone();
+    let output = String::from_utf8(output).unwrap();
+    let diagnostics: Vec<serde_json::Value> = output.lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    let codes: Vec<&str> = diagnostics.iter().map(|d| d["code"].as_str().unwrap()).collect();
+    assert_eq!(codes, vec!["MB0013", "MB0006", "MB0015"]);
+    assert_eq!(diagnostics[0]["severity"], "error");
+    assert_eq!(diagnostics[0]["span"]["line"], 1);
+    assert_eq!(diagnostics[0]["span"]["column"], 1);
+    assert_eq!(diagnostics[0]["suggestion"]["replacement"], "->L");
+}
+
+#[test]
+fn sarif_message_format(){
+    let output = Command::cargo_bin("mindbend")
+        .unwrap()
+        .arg(file!("multiple_errors.mb"))
+        .arg("--message-format=sarif")
+        .assert()
+        .failure()
+        .get_output()
+        .stdout
+        .clone();
+    let log: serde_json::Value = serde_json::from_slice(&output).unwrap();
+    assert_eq!(log["version"], "2.1.0");
+    let results = log["runs"][0]["results"].as_array().unwrap();
+    assert_eq!(results.len(), 3);
+    assert_eq!(results[2]["ruleId"], "MB0015");
+    assert_eq!(results[2]["locations"][0]["physicalLocation"]["region"]["startColumn"], 21);
+}
+
+#[test]
+fn lint_warnings(){
+    Command::cargo_bin("mindbend")
+        .unwrap()
+        .arg(file!("lints.mb"))
+        .assert()
+        .success()
+        .stderr(predicates::str::contains("warning[redundant-drill]: Drill when all the gates are already open\n"))
+        .stderr(predicates::str::contains("warning[jump-to-next-label]: Jump to the label right after it\n"))
+        .stderr(predicates::str::contains("warning[lone-cell-expression]: Cell expression has no effect\n"));
+}
+
+#[test]
+fn deny_warnings(){
+    Command::cargo_bin("mindbend")
+        .unwrap()
+        .arg(file!("lints.mb"))
+        .args(&["-D", "warnings", "-A", "redundant-drill", "-A", "jump-to-next-label"])
+        .assert()
+        .failure()
+        .stderr(concat!(
+            "error[lone-cell-expression]: Cell expression has no effect\n",
+            " --> sampleprog/lints.mb:1:46\n",
+            "  |\n",
+            "1 | ...0ijmp:x:label:x:0^^^^^^666^^^^^^=O\n",
+            "  |                    ^ not leached and not leached onto\n",
+            "  = note: `-W lone-cell-expression` is on by default\n",
+            "  = note: `-D warnings` turns every warning into an error\n",
+            "For more information about this error, try `mindbend explain lone-cell-expression`\n",
+        ));
+}
+
+#[test]
+fn warnings_and_errors_separated_alike(){
+    Command::cargo_bin("mindbend")
+        .unwrap()
+        .arg(file!("lints.mb"))
+        .args(&["-D", "redundant-drill", "-A", "jump-to-next-label"])
+        .assert()
+        .failure()
+        .stderr(concat!(
+            "warning[lone-cell-expression]: Cell expression has no effect\n",
+            " --> sampleprog/lints.mb:1:46\n",
+            "  |\n",
+            "1 | ...0ijmp:x:label:x:0^^^^^^666^^^^^^=O\n",
+            "  |                    ^ not leached and not leached onto\n",
+            "  = note: `-W lone-cell-expression` is on by default\n",
+            "\n",
+            "error[redundant-drill]: Drill when all the gates are already open\n",
+            " --> sampleprog/lints.mb:1:19\n",
+            "  |\n",
+            "1 | ...L\\\\|//\\\\|//\\\\|//\\\\|//$!->C~0ijmp:x:label:x:0^^^^^^666^^^^^^=O\n",
+            "  |                    ^^^^^ the 3 drills before this opened all the gates\n",
+            "  = note: `-D redundant-drill` was given\n",
+            "For more information about this error, try `mindbend explain redundant-drill`\n",
+        ));
+}
+
+#[test]
+fn allow_lints(){
+    Command::cargo_bin("mindbend")
+        .unwrap()
+        .arg(file!("lints.mb"))
+        .args(&["-A", "warnings"])
+        .assert()
+        .success()
+        .stderr("");
+}
+
+#[test]
+fn unknown_lint(){
+    Command::cargo_bin("mindbend")
+        .unwrap()
+        .arg(file!("lints.mb"))
+        .args(&["-D", "no-such-lint"])
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("unknown lint `no-such-lint`"));
+}
+
+#[test]
+fn include_search_path(){
+    let filename = "include_search_path.mb";
+    compile!(name => filename, stdout => "", "-I" => file!("lib"));
+    run!(stdout => "1");
+}
+
+#[test]
+fn include_not_found(){
+    ecompile!(
+        name => "include_search_path.mb",
+        stderr => predicates::str::contains(concat!(
+            "error[MB0026]: The file save_cells.mb included at line 1, column 47 couldn't be found\n",
+            " --> sampleprog/include_search_path.mb:1:47\n",
+        ))
+    );
+}
+
+#[test]
+fn include_cycle(){
+    ecompile!(
+        name => "include_cycle.mb",
+        stderr => concat!(
+            "error[MB0027]: The file cycle.mb included at line 1, column 1 ends up including itself\n",
+            " --> sampleprog/lib/cycle.mb:1:1\n",
+            "  |\n",
+            "1 | include:cycle.mb:\n",
+            "  | ^^^^^^^^^^^^^^^^^ cycle.mb is already being included\n",
+            "For more information about this error, try `mindbend explain MB0027`\n",
+        )
+    );
+}
+
+#[test]
+fn macros(){
+    let filename = "macros.mb";
+    compile!(name => filename, stdout => "");
+    run!(stdout => "12");
+}
+
+#[test]
+fn emit_expanded(){
+    let filename = "macros.mb";
+    compile!(
+        name => filename,
+        stdout => concat!(
+            r"->L\\|//\\|//\\|//$+->C~0->L$(->C~10~2->L\\|//->C1~32~4->L\\|//->C3~54~6->L\\|//->C5~76~8->L$><->C~99~8~7^^^^^^666^^^^^^=M",
+            r"->L\\|//\\|//\\|//$%->C~0->L$)->C~10~2->L\\|//->C1~32~4->L\\|//->C3~54~6->L\\|//->C5~76~8->L$><->C~99~8~7^^^^^^666^^^^^^=M",
+            "^^^^^^666^^^^^^=O"
+        ),
+        "--emit" => "=expanded"
+    );
+}
+
+#[test]
+fn emit_tokens_and_ast(){
+    let output = Command::cargo_bin("mindbend")
+        .unwrap()
+        .arg(file!("print1.mb"))
+        .arg("--emit=tokens,ast")
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    let output = String::from_utf8(output).unwrap();
+    let mut dumps = serde_json::Deserializer::from_str(&output).into_iter::<serde_json::Value>();
+    let tokens = dumps.next().unwrap().unwrap();
+    let ast = dumps.next().unwrap().unwrap();
+    assert_eq!(tokens["version"], 1);
+    assert_eq!(tokens["tokens"][0]["kind"], "region");
+    assert_eq!(tokens["tokens"][0]["span"]["file"], file!("print1.mb"));
+    assert_eq!(ast["version"], 1);
+    assert_eq!(ast["exprs"][0]["kind"], "region");
+    assert_eq!(ast["exprs"][0]["to"], "layers");
+}
+
+#[test]
+fn emit_llvm_ir(){
+    let output = Command::cargo_bin("mindbend")
+        .unwrap()
+        .arg(file!("print1.mb"))
+        .arg("--emit=llvm-ir")
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    let ir = String::from_utf8(output).unwrap();
+    assert!(ir.contains("define i32 @main()"));
+    // The code for the first drill, at line 1, column 4
+    assert!(ir.contains("%l1c4.carry_out_drill_routine = call i32"));
+    assert!(ir.contains("l1c4.drill_success_block:"));
+}
+
+#[test]
+fn emit_to_stdout_with_json_diagnostics(){
+    Command::cargo_bin("mindbend")
+        .unwrap()
+        .arg(file!("print1.mb"))
+        .arg("--emit=llvm-ir")
+        .arg("--message-format=json")
+        .assert()
+        .failure()
+        .stdout("")
+        .stderr("Can't print what's emitted along with the json diagnostics on stdout. Give each emit a path, as KIND=PATH\n");
+}
+
+#[test]
+fn emit_code_to_files(){
+    Command::cargo_bin("mindbend")
+        .unwrap()
+        .arg(file!("print1.mb"))
+        .arg("--emit=llvm-bc=emit_code.bc,asm=emit_code.s,obj,link")
+        .assert()
+        .success()
+        .stdout("");
+    let bitcode = fs::read("emit_code.bc").unwrap();
+    assert_eq!(&bitcode[..4], b"BC\xc0\xde");
+    assert!(fs::read_to_string("emit_code.s").unwrap().contains("main"));
+    // Without a path, the object file is written next to the executable
+    let object = format!("{}.o", DEFAULT_OUT_FILENAME);
+    assert!(!fs::read(&object).unwrap().is_empty());
+    for path in ["emit_code.bc", "emit_code.s", object.as_str()].iter() {
+        fs::remove_file(path).unwrap();
+    }
+    run!(stdout => "1");
+}
+
+#[test]
+fn same_behaviour_at_every_opt_level(){
+    // (program, input, what it prints, whether it succeeds)
+    let programs = [
+        ("print1to5.mb", "", "12345", true),
+        ("printA.mb", "", "A", true),
+        ("accept_a_num_and_print.mb", "7", "7", true),
+        ("macros.mb", "", "12", true),
+        ("attempt_access_primitive_gates_closed.mb", "", "Attempt to access primitive when the gates aren't open\n", false)
+    ];
+    for (filename, input, expected_out, succeeds) in programs.iter() {
+        for level in ["0", "1", "2", "3", "s"].iter() {
+            let out = format!("./out_{}_O{}", filename.trim_end_matches(".mb"), level);
+            Command::cargo_bin("mindbend")
+                .unwrap()
+                .arg(file!(filename))
+                .arg(format!("-O{}", level))
+                .arg("-o")
+                .arg(&out)
+                .assert()
+                .success()
+                .stdout("");
+            let output = Command::new(&out).write_stdin(*input).output().unwrap();
+            fs::remove_file(&out).unwrap();
+            assert_eq!(String::from_utf8_lossy(&output.stdout), *expected_out, "{} at -O{}", filename, level);
+            assert_eq!(output.status.success(), *succeeds, "{} at -O{}", filename, level);
+        }
+    }
+}
+
+#[test]
+fn emit_for_another_target(){
+    let output = Command::cargo_bin("mindbend")
+        .unwrap()
+        .arg(file!("print1.mb"))
+        .arg("--target")
+        .arg("aarch64-linux-gnu")
+        .arg("--emit=llvm-ir,obj=aarch64.o")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let ir = String::from_utf8_lossy(&output.stdout);
+    assert!(ir.contains("target triple = \"aarch64-unknown-linux-gnu\""));
+    assert!(ir.contains("target datalayout = \"e-m:e"));
+    let object = fs::read("aarch64.o").unwrap();
+    fs::remove_file("aarch64.o").unwrap();
+    assert_eq!(&object[..4], b"\x7fELF");
+    // e_machine, EM_AARCH64
+    assert_eq!(&object[18..20], &[183, 0]);
+}
+
+#[test]
+fn unknown_target(){
+    Command::cargo_bin("mindbend")
+        .unwrap()
+        .arg(file!("print1.mb"))
+        .arg("--target")
+        .arg("no-such-target")
+        .assert()
+        .failure()
+        .stderr(predicates::str::starts_with(
+            "error[MB0022]: Something went wrong while creating the target machine: "
+        ));
+}
+
+#[test]
+fn print_targets(){
+    Command::cargo_bin("mindbend")
+        .unwrap()
+        .arg("--print")
+        .arg("targets")
+        .assert()
+        .success()
+        .stdout(predicates::str::starts_with("Default target: "))
+        .stdout(predicates::str::contains("\n  aarch64 "))
+        .stdout(predicates::str::contains("\n  x86-64 "));
+}
+
+#[test]
+fn objects_are_reproducible(){
+    let flag_sets: [&[&str]; 4] = [
+        &[],
+        &["-O2"],
+        &["--target-cpu=native"],
+        &["-O3", "--target", "aarch64-linux-gnu", "--target-cpu=cortex-a72", "--target-feature=+crc,-neon"]
+    ];
+    for flags in flag_sets.iter() {
+        let objects: Vec<Vec<u8>> = ["reproducible1.o", "reproducible2.o"].iter().map(|path| {
+            Command::cargo_bin("mindbend")
+                .unwrap()
+                .arg(file!("print1to5.mb"))
+                .args(flags.iter())
+                .arg(format!("--emit=obj={}", path))
+                .assert()
+                .success();
+            let object = fs::read(path).unwrap();
+            fs::remove_file(path).unwrap();
+            object
+        }).collect();
+        assert!(objects[0] == objects[1], "the objects built with {:?} differ", flags);
+    }
+}
+
+#[test]
+fn native_cpu_for_another_target(){
+    Command::cargo_bin("mindbend")
+        .unwrap()
+        .arg(file!("print1.mb"))
+        .arg("--target")
+        .arg("aarch64-linux-gnu")
+        .arg("--target-cpu=native")
+        .assert()
+        .failure()
+        .stderr(predicates::str::starts_with(concat!(
+            "error[MB0022]: Something went wrong while creating the target machine: ",
+            "the native CPU is the host's, so it can't be used for aarch64-unknown-linux-gnu"
+        )));
+}
+
+#[test]
+fn host_given_as_target(){
+    // The host's triple, as LLVM has it, which may not be in its normalized form
+    let output = Command::cargo_bin("mindbend")
+        .unwrap()
+        .arg("--print")
+        .arg("targets")
+        .output()
+        .unwrap();
+    let targets = String::from_utf8(output.stdout).unwrap();
+    let host = targets.lines().next().unwrap().trim_start_matches("Default target: ");
+    // Linked with gcc, as without a target, and for the host's CPU
+    Command::cargo_bin("mindbend")
+        .unwrap()
+        .arg(file!("print1.mb"))
+        .arg("--target")
+        .arg(host)
+        .arg("--target-cpu=native")
+        .arg("-o")
+        .arg("./host_given_as_target")
+        .assert()
+        .success();
+    Command::new("./host_given_as_target").assert().success().stdout("1");
+    fs::remove_file("host_given_as_target").unwrap();
+}
+
+#[test]
+fn target_feature_without_sign(){
+    Command::cargo_bin("mindbend")
+        .unwrap()
+        .arg(file!("print1.mb"))
+        .arg("--target-feature=avx2")
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("expected +avx2 or -avx2"));
+}
+
+#[test]
+fn compile_object_only(){
+    Command::cargo_bin("mindbend")
+        .unwrap()
+        .arg(file!("print1.mb"))
+        .arg("-c")
+        .arg("-o")
+        .arg("object_only.o")
+        .assert()
+        .success()
+        .stdout("");
+    let object = fs::read("object_only.o").unwrap();
+    fs::remove_file("object_only.o").unwrap();
+    assert_eq!(&object[..4], b"\x7fELF");
+    // e_type, ET_REL
+    assert_eq!(&object[16..18], &[1, 0]);
+}
+
+#[test]
+fn link_with_linker_and_args(){
+    Command::cargo_bin("mindbend")
+        .unwrap()
+        .arg(file!("print1.mb"))
+        .arg("--linker=cc")
+        .arg("--link-arg=-Wl,-Map=linker_and_args.map")
+        .arg("-o")
+        .arg("./linker_and_args")
+        .assert()
+        .success();
+    assert!(fs::metadata("linker_and_args.map").is_ok());
+    Command::new("./linker_and_args").assert().success().stdout("1");
+    fs::remove_file("linker_and_args.map").unwrap();
+    fs::remove_file("linker_and_args").unwrap();
+}
+
+#[test]
+fn link_with_ld_alone(){
+    // ld isn't given the C runtime the program's _start and putchar come from
+    Command::cargo_bin("mindbend")
+        .unwrap()
+        .arg(file!("print1.mb"))
+        .arg("--linker=ld")
+        .arg("-o")
+        .arg("./ld_alone")
+        .assert()
+        .failure()
+        .stderr(predicates::str::starts_with(
+            "error[MB0024]: Something went wrong while linking the program: ld failed with exit status: 1\n"
+        ))
+        .stderr(predicates::str::contains("putchar"))
+        .stderr(predicates::str::contains(
+            "ld is given no C runtime, so its start files, -lc and the dynamic linker have to be passed with --link-arg"
+        ));
+}
+
+#[test]
+fn link_pie_and_static(){
+    // (flags, ET_EXEC or ET_DYN, whether it's linked statically)
+    let cases: [(&[&str], u8, bool); 4] = [
+        (&[], 2, false),
+        (&["--pie"], 3, false),
+        (&["--static"], 2, true),
+        (&["--pie", "--static"], 3, true)
+    ];
+    for (flags, e_type, static_link) in cases.iter() {
+        let out = format!("./link{}", flags.concat().replace("--", "_"));
+        Command::cargo_bin("mindbend")
+            .unwrap()
+            .arg(file!("print1.mb"))
+            .args(flags.iter())
+            .arg("-o")
+            .arg(&out)
+            .assert()
+            .success();
+        let executable = fs::read(&out).unwrap();
+        assert_eq!(executable[16], *e_type, "{:?}", flags);
+        // A dynamically linked executable has an .interp section naming the dynamic
+        // linker it's loaded by
+        let has_interpreter = executable.windows(8).any(|bytes| bytes == b".interp\0");
+        assert_eq!(has_interpreter, !static_link, "{:?}", flags);
+        Command::new(&out).assert().success().stdout("1");
+        fs::remove_file(&out).unwrap();
+    }
+}
+
+#[test]
+fn link_failure(){
+    Command::cargo_bin("mindbend")
+        .unwrap()
+        .arg(file!("print1.mb"))
+        .arg("--link-arg=-lno_such_library")
+        .arg("-o")
+        .arg("./link_failure")
+        .assert()
+        .failure()
+        .stderr(predicates::str::starts_with(
+            "error[MB0024]: Something went wrong while linking the program: gcc failed with exit status: 1\n"
+        ))
+        .stderr(predicates::str::contains("no_such_library"));
+    Command::cargo_bin("mindbend")
+        .unwrap()
+        .arg(file!("print1.mb"))
+        .arg("--linker=no-such-linker")
+        .arg("-o")
+        .arg("./link_failure")
+        .assert()
+        .failure()
+        .stderr(predicates::str::starts_with(
+            "error[MB0024]: Something went wrong while linking the program: couldn't run no-such-linker: "
+        ));
+}
+
+#[test]
+fn invalid_utf8(){
+    // The whitespace after the invalid byte isn't reported, since nothing after it is read
+    let filename = "invalid_utf8.mb";
+    ecompile!(
+        name => filename,
+        stderr => concat!(
+            "error[MB0038]: The source isn't valid UTF-8 at line 1, column 27\n",
+            " --> sampleprog/invalid_utf8.mb:1:27\n",
+            "  |\n",
+            "1 | ...|//\\\\|//$!->C~0~\n",
+            "  |                    ^ the next byte isn't valid UTF-8\n",
+            "For more information about this error, try `mindbend explain MB0038`\n",
+        )
+    );
+    let output = Command::cargo_bin("mindbend")
+        .unwrap()
+        .arg(file!(filename))
+        .arg("--message-format=json")
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(json["code"], "MB0038");
+    assert_eq!(json["span"]["column"], 27);
+}